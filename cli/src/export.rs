@@ -0,0 +1,181 @@
+use chrono::{NaiveDateTime, SecondsFormat, TimeZone};
+use chrono_tz::Tz;
+use libs::record::{Task, TaskState};
+use serde::Serialize;
+
+/// Columns of an exported task, the same as `im import` reads.
+const TASK_CSV_HEADER: &str = "name,state,begin_time,end_time,duration";
+
+/// A finished task with its timestamps already formatted for export.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ExportRow {
+    pub name: String,
+    pub state: TaskState,
+    pub begin_time: String,
+    pub end_time: String,
+    pub duration: i64,
+}
+
+/// Parse an IANA time zone name such as `America/New_York`.
+pub fn parse_timezone(input: &str) -> Result<Tz, String> {
+    input.parse::<Tz>().map_err(|_| {
+        format!(
+            "Unknown time zone `{}`. Expected an IANA name such as America/New_York",
+            input
+        )
+    })
+}
+
+/// Format a stored timestamp, which is a naive time in `source`.
+///
+/// Without a `target` it is kept as stored. Otherwise it is converted to
+/// `target` as ISO 8601 with the offset. An ambiguous time, repeated when
+/// clocks go back in `source`, is read as the earlier one, and a time
+/// skipped when they go forward as an hour later. A time `source` still
+/// can't place is kept as stored.
+fn format_time<Z: TimeZone>(at: NaiveDateTime, source: &Z, target: Option<Tz>) -> String {
+    let stored = || at.format("%Y-%m-%dT%H:%M:%S%.f").to_string();
+    let Some(target) = target else {
+        return stored();
+    };
+    let localized = source.from_local_datetime(&at).earliest().or_else(|| {
+        source
+            .from_local_datetime(&(at + chrono::Duration::hours(1)))
+            .earliest()
+    });
+    match localized {
+        Some(localized) => localized
+            .with_timezone(&target)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+        None => stored(),
+    }
+}
+
+/// Export the finished tasks, with timestamps stored in `source`.
+pub fn export_rows<Z: TimeZone>(tasks: &[Task], source: &Z, target: Option<Tz>) -> Vec<ExportRow> {
+    tasks
+        .iter()
+        .filter(|t| t.state == TaskState::End)
+        .map(|t| ExportRow {
+            name: t.name.clone(),
+            state: t.state.clone(),
+            begin_time: format_time(t.begin_time, source, target),
+            end_time: format_time(t.end_time, source, target),
+            duration: t.duration.0,
+        })
+        .collect()
+}
+
+/// Quote a CSV field when it holds a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn render_csv(rows: &[ExportRow]) -> String {
+    let mut out = format!("{}\n", TASK_CSV_HEADER);
+    for row in rows {
+        out.push_str(&format!(
+            "{},{:?},{},{},{}\n",
+            csv_field(&row.name),
+            row.state,
+            row.begin_time,
+            row.end_time,
+            row.duration
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, Utc};
+
+    use libs::record::Seconds;
+
+    use super::*;
+
+    fn at(d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    fn task(name: &str, begin_time: NaiveDateTime, end_time: NaiveDateTime) -> Task {
+        Task {
+            name: name.to_string(),
+            state: TaskState::End,
+            begin_time,
+            end_time,
+            duration: Seconds::between(begin_time, end_time),
+            ..Task::default()
+        }
+    }
+
+    #[test]
+    fn test_export_across_dst_boundary() {
+        // New York moves from -05:00 to -04:00 at 07:00 UTC on 2024-03-10.
+        let tasks = vec![task("writing, drafts", at(10, 6, 30), at(10, 7, 30))];
+        let new_york = parse_timezone("America/New_York").unwrap();
+
+        assert_eq!(
+            render_csv(&export_rows(&tasks, &Utc, Some(new_york))),
+            "name,state,begin_time,end_time,duration\n\
+             \"writing, drafts\",End,2024-03-10T01:30:00-05:00,2024-03-10T03:30:00-04:00,3600\n"
+        );
+        assert_eq!(
+            render_csv(&export_rows(&tasks, &Utc, None)),
+            "name,state,begin_time,end_time,duration\n\
+             \"writing, drafts\",End,2024-03-10T06:30:00,2024-03-10T07:30:00,3600\n"
+        );
+    }
+
+    #[test]
+    fn test_export_times_stored_around_dst_changes() {
+        let new_york = parse_timezone("America/New_York").unwrap();
+        let utc = Some(chrono_tz::UTC);
+
+        // 01:30 happens twice on 2024-11-03, the earlier one is at -04:00.
+        let repeated = NaiveDate::from_ymd_opt(2024, 11, 3)
+            .unwrap()
+            .and_hms_opt(1, 30, 0)
+            .unwrap();
+        assert_eq!(
+            format_time(repeated, &new_york, utc),
+            "2024-11-03T05:30:00+00:00"
+        );
+
+        // 02:30 never happens on 2024-03-10, it is read as 03:30 at -04:00.
+        assert_eq!(
+            format_time(at(10, 2, 30), &new_york, utc),
+            "2024-03-10T07:30:00+00:00"
+        );
+        assert_eq!(
+            format_time(at(10, 1, 30), &new_york, utc),
+            "2024-03-10T06:30:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_export_only_finished_tasks() {
+        let mut active = task("review", at(11, 9, 0), at(11, 9, 0));
+        active.state = TaskState::Begin;
+
+        assert!(export_rows(&[active], &Utc, None).is_empty());
+    }
+
+    #[test]
+    fn test_parse_unknown_timezone() {
+        assert!(parse_timezone("Europe/Berlin").is_ok());
+        assert_eq!(
+            parse_timezone("Mars/Olympus"),
+            Err("Unknown time zone `Mars/Olympus`. \
+                 Expected an IANA name such as America/New_York"
+                .to_string())
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use libs::record::Task;
+
+/// Command running `hook` for `task` having just changed state.
+///
+/// The hook gets the state, the task name and the seconds worked so far,
+/// both as arguments in that order and as `IMON_TASK_STATE`,
+/// `IMON_TASK_NAME` and `IMON_TASK_DURATION`.
+fn hook_command(hook: &Path, task: &Task) -> Command {
+    let state = format!("{:?}", task.state);
+    let duration = task.worked_seconds().0.to_string();
+    let mut command = Command::new(hook);
+    command
+        .args([state.as_str(), task.name.as_str(), duration.as_str()])
+        .env("IMON_TASK_STATE", &state)
+        .env("IMON_TASK_NAME", &task.name)
+        .env("IMON_TASK_DURATION", &duration)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit());
+    command
+}
+
+/// Start the user's transition hook, if any, without waiting for it.
+///
+/// The hook keeps its stderr, so what it reports shows up in the terminal;
+/// a hook that can't be started is reported here. Either way the transition
+/// itself has already been recorded.
+pub fn run_transition_hook(hook: Option<&Path>, task: &Task) {
+    let Some(hook) = hook else {
+        return;
+    };
+    if let Err(e) = hook_command(hook, task).spawn() {
+        tracing::error!("Couldn't run the hook {:?}: {}", hook, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use libs::record::{Seconds, TaskState};
+
+    use super::*;
+
+    #[test]
+    fn test_hook_gets_state_name_and_duration() {
+        let task = Task {
+            duration: Seconds(1800),
+            ..Task::placeholder("fix the bug", TaskState::Break)
+        };
+        let command = hook_command(Path::new("/usr/local/bin/dnd"), &task);
+
+        let args: Vec<&OsStr> = command.get_args().collect();
+        assert_eq!(args, ["Break", "fix the bug", "1800"]);
+        let env: Vec<(&OsStr, Option<&OsStr>)> = command.get_envs().collect();
+        assert!(env.contains(&(OsStr::new("IMON_TASK_STATE"), Some(OsStr::new("Break")))));
+        assert!(env.contains(&(
+            OsStr::new("IMON_TASK_NAME"),
+            Some(OsStr::new("fix the bug"))
+        )));
+        assert!(env.contains(&(OsStr::new("IMON_TASK_DURATION"), Some(OsStr::new("1800")))));
+    }
+}
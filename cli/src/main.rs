@@ -1,17 +1,38 @@
 use std::{
     fs,
-    io::{Read, Write},
+    io::{IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use libs::payload::{RegisterRecordPayload, StoreTaskPayload};
-use libs::record::{Task, TaskState};
+use libs::payload::{
+    GetSingleRecordPayload, RegisterRecordPayload, SetGoalPayload, SetRatePayload,
+    StoreTaskPayload, UpdateTaskPayload,
+};
+use libs::record::{normalize_task_name, HourlyRate, Seconds, Task, TaskState, UserRecordView};
+use libs::response::{
+    AllRecordsResponse, RecordResponse, RegisterResponse, ServerTimeResponse, StatusResponse,
+};
+use libs::stats::{break_seconds_on, group_by_day, infer_breaks, total_durations};
 
-use clap::{Parser, Subcommand};
-use dirs;
-use serde::{Deserialize, Serialize};
+use clap::{Parser, Subcommand, ValueEnum};
+use tracing_subscriber::fmt::writer::MakeWriterExt;
 
-use crate::util::make_request;
+use crate::hook::run_transition_hook;
+use crate::outbox::Outbox;
+use crate::remote::RemoteTaskCache;
+use crate::util::{
+    clock_skew, describe_clock_skew, format_goal_progress, format_log_footer, make_admin_request,
+    make_csv_request, make_get_request, make_request, parse_duration, parse_money,
+    parse_time_range, parse_user_key, read_task_name, RequestError,
+};
 
+pub mod export;
+pub mod hook;
+pub mod outbox;
+pub mod remote;
+pub mod report;
+pub mod sync;
 pub mod util;
 
 #[derive(Parser)]
@@ -19,46 +40,177 @@ pub mod util;
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Also show chatter and debug output.
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Only show errors.
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Go ahead even if earlier offline changes can't be sent; the new one
+    /// is queued after them.
+    #[arg(long, global = true)]
+    force_local: bool,
+    /// Take the current task from the server instead of this machine's log,
+    /// for when you switch between machines. Also set by `IMON_REMOTE=1`.
+    #[arg(long, global = true, env = "IMON_REMOTE")]
+    remote: bool,
+    /// Script to start after `on`, `break`, `back` and `done`, with the
+    /// state, task name and seconds worked as arguments, e.g. to toggle
+    /// Do Not Disturb. Also set by `IMON_ON_TRANSITION_HOOK`.
+    #[arg(long, global = true, env = "IMON_ON_TRANSITION_HOOK")]
+    on_transition_hook: Option<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct TaskResponse {
-    status: String,
-    message: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct AuthResponseData {
-    user_key: String,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct AuthResponse {
-    status: String,
-    data: AuthResponseData,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct NewTaskResponse {
-    status: String,
+/// Route all output through `tracing`: errors to stderr, the rest to stdout,
+/// without timestamps or levels so it reads like plain messages.
+fn init_output(cli: &Cli) {
+    let level = if cli.verbose {
+        tracing::Level::DEBUG
+    } else if cli.quiet {
+        tracing::Level::ERROR
+    } else {
+        tracing::Level::INFO
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(
+            std::io::stderr
+                .with_max_level(tracing::Level::WARN)
+                .or_else(std::io::stdout),
+        )
+        .without_time()
+        .with_level(false)
+        .with_target(false)
+        .init();
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// What are you working on?
     On {
-        name: Option<String>,
+        /// The task name, or `-` to read it from stdin. Several words are
+        /// joined with spaces, so `im on fix the bug` needs no quotes.
+        name: Vec<String>,
+        /// Read the task name from stdin, e.g. `echo "fix bug #42" | im on --stdin`.
+        #[arg(long, conflicts_with = "name")]
+        stdin: bool,
+        /// Count this task's time towards a parent task.
+        #[arg(long)]
+        parent: Option<String>,
+        /// Store the name in lowercase, so differently cased names are
+        /// counted as one task. Should match the server's setting.
+        #[arg(long, env = "IMON_TASK_NAME_LOWERCASE")]
+        lowercase: bool,
+        /// Mark the task as billable, see `billable` in grouped stats.
+        #[arg(long)]
+        billable: bool,
     },
     /// Take a break.
     Break,
     /// Go back to work.
     Back,
     /// Signals that you have done working on registered task.
-    Done,
-    Check,
+    Done {
+        /// Only succeed once the server has recorded the task: nothing is
+        /// queued or written locally otherwise, and the exit code is 1.
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Show the current task, how long you've been focused since your last
+    /// break, and today's progress towards your goal.
+    Check {
+        /// Suggest a break once focused this long, e.g. `2h` or `90m`.
+        #[arg(long, env = "IMON_FOCUS_WARN", value_parser = parse_duration)]
+        warn_after: Option<i64>,
+    },
+    /// Report finished tasks, grouped by day.
+    Report {
+        /// Time range, e.g. `today`, `yesterday`, `7d` or `2024-01-31`.
+        #[arg(long, default_value = "today", conflicts_with = "week")]
+        range: String,
+        /// Report this week, same as `--range this-week`.
+        #[arg(long)]
+        week: bool,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+        /// Write the report to this file instead of stdout.
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Add the total time per task name.
+        #[arg(long)]
+        by_task: bool,
+        /// Also count subtasks in their parents' totals.
+        #[arg(long, requires = "by_task")]
+        roll_up: bool,
+    },
+    /// List the latest finished tasks, newest first.
+    Log {
+        /// How many tasks to show.
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// Print the tasks as JSON, without the summary line.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Export finished tasks, in the CSV format `im import` reads or as JSON.
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Convert begin and end times to this IANA time zone, e.g.
+        /// `America/New_York`, written as ISO 8601 with the offset.
+        #[arg(long, value_parser = export::parse_timezone, conflicts_with = "utc")]
+        output_timezone: Option<chrono_tz::Tz>,
+        /// Keep times as stored, without an offset. This is the default.
+        #[arg(long)]
+        utc: bool,
+        /// Write the export to this file instead of stdout.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Propose breaks where finished tasks are far apart. Nothing is changed.
+    Analyze {
+        /// Shortest gap to count as a break, e.g. `15m`.
+        #[arg(long, default_value = "15m")]
+        gap: String,
+        /// Time range, e.g. `today`, `yesterday`, `7d` or `2024-01-31`.
+        #[arg(long, default_value = "today")]
+        range: String,
+    },
+    /// Import finished tasks from a CSV file with the header
+    /// `name,state,begin_time,end_time,duration`, all in one request.
+    Import {
+        file: PathBuf,
+        /// Import tasks overlapping logged ones, e.g. for parallel tracking.
+        #[arg(long)]
+        allow_overlap: bool,
+    },
+    /// Set a daily goal, e.g. `6h` or `1h30m`, or `off` to clear it.
+    Goal { duration: String },
+    /// Set an hourly rate for billable tasks, e.g. `45.50 EUR`,
+    /// or `off` to clear it.
+    Rate {
+        amount: String,
+        currency: Option<String>,
+    },
+    /// Reconcile the local task state with the server: the current task,
+    /// and the tasks finished on this machine lately.
+    Sync {
+        /// Side to keep on conflict, instead of asking.
+        #[arg(long, value_enum)]
+        prefer: Option<SyncSide>,
+        /// How many days back to compare finished tasks.
+        #[arg(long, default_value_t = 7)]
+        days: u32,
+    },
     /// Register yourself.
     #[command(subcommand)]
     Auth(AuthCommand),
+    /// Admin commands, authorised with `IMON_ADMIN_SECRET`.
+    #[command(subcommand)]
+    Admin(AdminCommand),
+    /// Diagnostics for when durations look off.
+    #[command(subcommand)]
+    Debug(DebugCommand),
 }
 
 #[derive(Subcommand)]
@@ -70,6 +222,74 @@ enum AuthCommand {
     LogIn { user_key: String },
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum SyncSide {
+    Server,
+    Local,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Markdown,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum AdminCommand {
+    /// List all users and what they are working on.
+    Users,
+}
+
+#[derive(Subcommand)]
+enum DebugCommand {
+    /// Compare this machine's clock and time zone with the server's.
+    ServerTime,
+}
+
+fn print_user_table(user_records: &[UserRecordView]) {
+    println!(
+        "{:<20} {:>6} {:<30} {:<20}",
+        "USER", "ID", "CURRENT TASK", "LAST UPDATED"
+    );
+    for record in user_records {
+        let (current_task, last_updated) = match &record.current_task {
+            Some(task) => (
+                format!("{} ({:?})", task.name, task.state),
+                task.begin_time
+                    .max(task.end_time)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string(),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!(
+            "{:<20} {:>6} {:<30} {:<20}",
+            record.user_name, record.id, current_task, last_updated
+        );
+    }
+}
+
+/// Why `latest_task` can't move to `next`, worded for the user, or `None`
+/// if it can. Only an active task can take a break, come back or be done.
+fn refuse_transition(latest_task: &Task, next: &TaskState) -> Option<String> {
+    if !latest_task.state.is_active() {
+        return Some("You are not working on anything.".to_string());
+    }
+    match (&latest_task.state, next) {
+        (TaskState::Break, TaskState::Break) => Some("You are already on break.".to_string()),
+        (TaskState::Begin | TaskState::Back, TaskState::Back) => Some(format!(
+            "You are already working on `{}`.",
+            latest_task.name
+        )),
+        _ => None,
+    }
+}
+
 fn get_latest_task_local(file: &mut fs::File) -> Task {
     let mut content = String::new();
     file.read_to_string(&mut content).unwrap();
@@ -87,6 +307,165 @@ fn get_latest_task_local(file: &mut fs::File) -> Task {
     }
 }
 
+fn fetch_current_task(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    user_key: &str,
+) -> Result<Task, RequestError> {
+    let payload = GetSingleRecordPayload {
+        key: user_key.to_string(),
+        include_history: false,
+    };
+    make_request::<_, RecordResponse>(client, reqwest::Method::POST, url, payload)
+        .map(|resp_body| resp_body.data.task_log.current_task)
+}
+
+/// The server's current task, served from `cache` while it's fresh.
+/// Falls back to `local_task` when the server can't be reached.
+fn get_latest_task_remote(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    user_key: &str,
+    cache: &RemoteTaskCache,
+    local_task: Task,
+) -> Task {
+    let now = chrono::offset::Local::now().naive_local();
+    if let Some(task) = cache.get(now) {
+        return task;
+    }
+    match fetch_current_task(client, url, user_key) {
+        Ok(task) => {
+            if let Err(e) = cache.set(&task, now) {
+                tracing::error!("Couldn't write to file: {}", e);
+            }
+            task
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Couldn't fetch the current task ({}), using the local one.",
+                e
+            );
+            local_task
+        }
+    }
+}
+
+fn is_same_task(a: &Task, b: &Task) -> bool {
+    (a.is_placeholder() && b.is_placeholder())
+        || (a.name == b.name && a.state == b.state && a.begin_time == b.begin_time)
+}
+
+fn ask_sync_side(local_task: &Task, server_task: &Task, unsent: usize) -> Option<SyncSide> {
+    println!(
+        "Local: `{}` ({:?}), server: `{}` ({:?}).",
+        local_task.name, local_task.state, server_task.name, server_task.state
+    );
+    if unsent > 0 {
+        println!("{} finished task(s) here aren't on the server.", unsent);
+    }
+    print!("Keep [l]ocal or [s]erver? ");
+    std::io::stdout().flush().ok()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok()?;
+    match answer.trim() {
+        "l" | "local" => Some(SyncSide::Local),
+        "s" | "server" => Some(SyncSide::Server),
+        _ => None,
+    }
+}
+
+/// Register `user_name` and keep the returned key in `user_log_path`.
+fn register_user(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    user_name: &str,
+    user_log_path: &Path,
+) -> Option<String> {
+    let payload = RegisterRecordPayload {
+        user_name: user_name.to_string(),
+    };
+    let user_key =
+        match make_request::<_, RegisterResponse>(client, reqwest::Method::POST, url, payload) {
+            Ok(resp_body) => resp_body.data.user_key,
+            Err(e) => {
+                tracing::error!("Failed to post to upstream: {}", e);
+                return None;
+            }
+        };
+
+    if let Err(e) = fs::write(user_log_path, &user_key) {
+        tracing::error!("Couldn't write to file: {}", e);
+        return None;
+    }
+    Some(user_key)
+}
+
+/// Look `user_key` up with `lookup` and keep it in `user_log_path` once the
+/// server has confirmed it is that user's. Returns the user name, or why
+/// nothing was saved.
+fn log_in(
+    user_key: &str,
+    user_log_path: &Path,
+    lookup: impl FnOnce(GetSingleRecordPayload) -> Result<RecordResponse, RequestError>,
+) -> Result<String, String> {
+    let Some((user_name, id)) = parse_user_key(user_key) else {
+        return Err(format!(
+            "`{}` isn't a user key, expected e.g. `user:alice:0001`.",
+            user_key
+        ));
+    };
+
+    let payload = GetSingleRecordPayload {
+        key: user_key.to_string(),
+        include_history: false,
+    };
+    let record = match lookup(payload) {
+        Ok(resp_body) => resp_body.data.task_log,
+        Err(RequestError::Status { status, .. }) if status.is_client_error() => {
+            return Err("User not found.".to_string());
+        }
+        Err(e) => return Err(format!("Failed to fetch from upstream: {}", e)),
+    };
+    if record.user_name != user_name || record.id != id {
+        return Err(format!(
+            "The server answered with the record of `{}` ({}), not this key's.",
+            record.user_name, record.id
+        ));
+    }
+
+    fs::write(user_log_path, user_key).map_err(|e| format!("Couldn't write to file: {}", e))?;
+    Ok(record.user_name)
+}
+
+/// First run on a terminal: ask for a user name and register it. `None`
+/// once the failure has been reported.
+fn onboard(client: &reqwest::blocking::Client, url: &str, user_log_path: &Path) -> Option<String> {
+    println!("Welcome to imon! You are not registered yet.");
+    print!("Pick a user name: ");
+    std::io::stdout().flush().ok()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok()?;
+    let user_name = answer.trim();
+    if user_name.is_empty() {
+        tracing::error!("No user name given.");
+        return None;
+    }
+
+    let user_key = register_user(client, url, user_name, user_log_path)?;
+    tracing::info!("Registered as `{}`.", user_name);
+    Some(user_key)
+}
+
+/// Stop with an error unless a user is registered or logged in here.
+fn require_user_key(user_key: &str) {
+    if user_key.is_empty() {
+        tracing::error!("Please register yourself first.");
+        std::process::exit(1);
+    }
+}
+
 fn retrieve_user_key(file: &mut fs::File) -> String {
     let mut content = String::new();
     file.read_to_string(&mut content).unwrap();
@@ -97,24 +476,220 @@ fn retrieve_user_key(file: &mut fs::File) -> String {
 
 // const SERVICE_URL: &'static str = "https://imon-service.shuttleapp.rs";
 const SERVICE_DOMAIN: &str = "http://localhost:8000";
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 10;
+
+/// Connect and request timeout, overridable with `IMON_TIMEOUT_SECS`.
+fn request_timeout() -> Duration {
+    let secs = std::env::var("IMON_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Client builder for the TLS backend picked at build time. rustls wins
+/// when both features are enabled.
+fn client_builder() -> reqwest::blocking::ClientBuilder {
+    let builder = reqwest::blocking::Client::builder();
+    #[cfg(feature = "rustls-tls")]
+    let builder = builder.use_rustls_tls();
+    #[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+    let builder = builder.use_native_tls();
+    builder
+}
+
+/// How a task write reaches the server, see `prepare_delivery`.
+#[derive(Clone, Copy)]
+enum Delivery {
+    Online,
+    /// Earlier writes are still queued, so this one is queued behind them.
+    Queued,
+    /// The server must record this write now, so it is never queued.
+    Now,
+}
+
+/// Send what's queued in `outbox`, reporting the writes the server refused.
+/// Those are dropped: sending them again would only be refused again.
+fn flush_outbox(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    outbox: &Outbox,
+) -> Result<(), (RequestError, usize)> {
+    let flushed = outbox.flush(|payload| {
+        make_request::<_, StatusResponse>(client, reqwest::Method::POST, url, payload).map(|_| ())
+    })?;
+    for (task, e) in flushed.rejected {
+        let reason = match &e {
+            RequestError::Status {
+                body: Some(body), ..
+            } if body["message"].is_string() => body["message"].as_str().unwrap().to_string(),
+            e => e.to_string(),
+        };
+        tracing::error!(
+            "The server refused a queued change to `{}` ({:?}), dropped it: {}",
+            task.name,
+            task.state,
+            reason
+        );
+    }
+    Ok(())
+}
+
+/// Send what's queued in `outbox` before a new task write, so the server
+/// sees writes in the same order as the local log.
+///
+/// Returns `None` when the write must not go ahead: something is still
+/// queued and `force_local` isn't set.
+fn prepare_delivery(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    outbox: &Outbox,
+    force_local: bool,
+) -> Option<Delivery> {
+    match flush_outbox(client, url, outbox) {
+        Ok(()) => Some(Delivery::Online),
+        Err((e, pending)) if force_local => {
+            tracing::warn!(
+                "{} earlier change(s) still not sent ({}), queued this one after them.",
+                pending,
+                e
+            );
+            Some(Delivery::Queued)
+        }
+        Err((e, pending)) => {
+            tracing::error!(
+                "{} earlier change(s) couldn't be sent ({}). Try again once the server is reachable, or pass `--force-local`.",
+                pending,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Where `send_or_queue` left a task write.
+enum Sent {
+    /// The server has recorded it.
+    Recorded,
+    /// It waits in the outbox for the next write.
+    Queued,
+}
+
+/// Post a task write, or queue it in `outbox` when the server is
+/// unreachable or earlier writes are still queued.
+fn send_or_queue(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    outbox: &Outbox,
+    payload: StoreTaskPayload,
+    delivery: Delivery,
+) -> Result<Sent, RequestError> {
+    match delivery {
+        Delivery::Now => {
+            return make_request::<_, StatusResponse>(client, reqwest::Method::POST, url, &payload)
+                .map(|_| Sent::Recorded);
+        }
+        Delivery::Online => {
+            match make_request::<_, StatusResponse>(client, reqwest::Method::POST, url, &payload) {
+                Ok(_) => return Ok(Sent::Recorded),
+                Err(e) if e.is_temporary() => {
+                    tracing::error!("{} Saved locally, it will be sent next time.", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Delivery::Queued => {}
+    }
+    if let Err(e) = outbox.push(&payload) {
+        tracing::error!("Couldn't write to file: {}", e);
+    }
+    Ok(Sent::Queued)
+}
+
+/// What a task transition goes through, besides the operation log.
+struct Transitions<'a> {
+    client: &'a reqwest::blocking::Client,
+    url: &'a str,
+    key: &'a str,
+    outbox: &'a Outbox,
+    remote_cache: &'a RemoteTaskCache,
+    hook: Option<&'a Path>,
+}
+
+impl Transitions<'_> {
+    /// Send `task` as the new current task, or queue it behind unsent
+    /// writes when `force_local` allows it, then keep it locally: as the
+    /// cached remote task, in `op_log` and through the transition hook.
+    ///
+    /// With `wait`, the server must record it now. Fails with `None` when it
+    /// was held back, see `prepare_delivery`, and with the server's answer
+    /// when it was refused. Nothing is kept locally then.
+    fn deliver(
+        &self,
+        op_log: &mut fs::File,
+        task: &Task,
+        force_local: bool,
+        wait: bool,
+    ) -> Result<(), Option<RequestError>> {
+        let delivery = prepare_delivery(self.client, self.url, self.outbox, force_local && !wait)
+            .ok_or(None)?;
+        let delivery = if wait { Delivery::Now } else { delivery };
+        let payload = StoreTaskPayload {
+            key: self.key.to_string(),
+            task: task.clone(),
+        };
+        send_or_queue(self.client, self.url, self.outbox, payload, delivery).map_err(Some)?;
+
+        if let Err(e) = self
+            .remote_cache
+            .set(task, chrono::offset::Local::now().naive_local())
+        {
+            tracing::error!("Couldn't write to file: {}", e);
+        }
+        if let Err(e) = writeln!(op_log, "{}", serde_json::to_string(task).unwrap()) {
+            tracing::error!("Couldn't write to file: {}", e);
+        }
+        run_transition_hook(self.hook, task);
+        Ok(())
+    }
+}
 
 struct Endpoints {
     auth: String,
     post_task_payload: String,
-    get_task_log: String,
+    update_task: String,
+    get_record: String,
+    get_all_records: String,
+    set_goal: String,
+    set_rate: String,
+    import_csv: String,
+    server_time: String,
 }
 
 fn main() {
+    let cli = Cli::parse();
+    init_output(&cli);
+
     let endpoints = Endpoints {
         auth: format!("{}{}", SERVICE_DOMAIN, "/v1/record/new"),
         post_task_payload: format!("{}{}", SERVICE_DOMAIN, "/v1/task/new"),
-        get_task_log: format!("{}{}", SERVICE_DOMAIN, "/v1/task-log"),
+        update_task: format!("{}{}", SERVICE_DOMAIN, "/v1/task/update"),
+        get_record: format!("{}{}", SERVICE_DOMAIN, "/v1/record"),
+        get_all_records: format!("{}{}", SERVICE_DOMAIN, "/v1/record/all"),
+        set_goal: format!("{}{}", SERVICE_DOMAIN, "/v1/record/goal"),
+        set_rate: format!("{}{}", SERVICE_DOMAIN, "/v1/record/rate"),
+        import_csv: format!("{}{}", SERVICE_DOMAIN, "/v1/task/import/csv"),
+        server_time: format!("{}{}", SERVICE_DOMAIN, "/v1/time"),
     };
-    let request_client = reqwest::blocking::Client::new();
+    let request_client = client_builder()
+        .connect_timeout(request_timeout())
+        .timeout(request_timeout())
+        .build()
+        .unwrap();
 
     let log_dir = dirs::state_dir().unwrap().join("imon/log");
     if let Err(e) = fs::create_dir_all(&log_dir) {
-        eprintln!("Failed to create log directory: {}", e);
+        tracing::error!("Failed to create log directory: {}", e);
         return;
     }
 
@@ -123,11 +698,23 @@ fn main() {
         .read(true)
         .write(true)
         .create(true)
+        .truncate(false)
         .open(&user_log_path)
         .unwrap();
 
     // Format: $role:$user_name:$id
     let current_user_key = retrieve_user_key(&mut user_file);
+    let needs_no_user = matches!(
+        cli.command,
+        Some(Commands::Auth(_) | Commands::Admin(_) | Commands::Debug(_))
+    );
+    let current_user_key =
+        if current_user_key.is_empty() && !needs_no_user && std::io::stdin().is_terminal() {
+            onboard(&request_client, &endpoints.auth, &user_log_path)
+                .unwrap_or_else(|| std::process::exit(1))
+        } else {
+            current_user_key
+        };
     let current_user_name = current_user_key.split(':').nth(1).unwrap_or("");
 
     let op_log_path = log_dir.join("imon.log");
@@ -135,271 +722,850 @@ fn main() {
         .read(true)
         .append(true)
         .create(true)
-        .open(op_log_path)
+        .open(&op_log_path)
         .unwrap();
 
+    let remote_cache = RemoteTaskCache::new(log_dir.join("remote_task.json"));
     let latest_task = get_latest_task_local(&mut file);
-
-    let cli = Cli::parse();
+    let latest_task = if cli.remote && !current_user_key.is_empty() {
+        get_latest_task_remote(
+            &request_client,
+            &endpoints.get_record,
+            &current_user_key,
+            &remote_cache,
+            latest_task,
+        )
+    } else {
+        latest_task
+    };
+    let outbox = Outbox::new(log_dir.join("outbox.log"));
+    let transitions = Transitions {
+        client: &request_client,
+        url: &endpoints.post_task_payload,
+        key: &current_user_key,
+        outbox: &outbox,
+        remote_cache: &remote_cache,
+        hook: cli.on_transition_hook.as_deref(),
+    };
 
     if let Some(command) = &cli.command {
         match command {
-            Commands::On { name } => {
-                if current_user_key.is_empty() {
-                    println!("Please register yourself first.");
-                    return;
-                }
+            Commands::On {
+                name,
+                stdin,
+                parent,
+                lowercase,
+                billable,
+            } => {
+                require_user_key(&current_user_key);
 
-                if latest_task.state == TaskState::Begin
-                    || latest_task.state == TaskState::Break
-                    || latest_task.state == TaskState::Back
-                {
-                    println!(
+                if latest_task.state.is_active() {
+                    tracing::info!(
                         "You are already working on `{}`. Please finish it first.",
                         latest_task.name
                     );
                     return;
                 }
 
-                let new_task = Task::generate_begin_task(name.as_ref().unwrap().to_string());
-
-                println!("Sure, you are.");
-
-                let payload = StoreTaskPayload {
-                    key: current_user_key.clone(),
-                    task: new_task.clone(),
+                let name = match name.as_slice() {
+                    [dash] if dash == "-" => read_task_name(&mut std::io::stdin()),
+                    [] if *stdin => read_task_name(&mut std::io::stdin()),
+                    words => Ok(words.join(" ")),
                 };
-                if let Err(e) = make_request::<_, NewTaskResponse>(
-                    &request_client,
-                    reqwest::Method::POST,
-                    &endpoints.post_task_payload,
-                    payload,
-                ) {
-                    eprintln!("Failed to post to upstream: {}", e);
+                let name = match name {
+                    Ok(name) => normalize_task_name(&name, *lowercase),
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+                if name.is_empty() {
+                    tracing::info!("A task needs a name.");
                     return;
                 }
+                let new_task = match parent {
+                    Some(parent) => match Task::generate_begin_subtask(
+                        name,
+                        normalize_task_name(parent, *lowercase),
+                    ) {
+                        Some(task) => task,
+                        None => {
+                            tracing::info!("A task can't be its own parent.");
+                            return;
+                        }
+                    },
+                    None => Task::generate_begin_task(name),
+                };
+                let new_task = Task {
+                    billable: *billable,
+                    ..new_task
+                };
+
+                tracing::debug!("Sure, you are.");
 
-                if let Err(e) = writeln!(file, "{}", serde_json::to_string(&new_task).unwrap()) {
-                    eprintln!("Couldn't write to file: {}", e);
+                match transitions.deliver(&mut file, &new_task, cli.force_local, false) {
+                    Ok(()) | Err(None) => {}
+                    Err(Some(RequestError::Status {
+                        status: reqwest::StatusCode::CONFLICT,
+                        body: Some(body),
+                    })) => {
+                        match serde_json::from_value::<Task>(body["data"]["current_task"].clone()) {
+                            Ok(current_task) => tracing::info!(
+                                "You are still working on `{}` ({}). Please finish it first.",
+                                current_task.name,
+                                current_task.worked_seconds(),
+                            ),
+                            Err(_) => tracing::info!("You are still working on another task."),
+                        }
+                    }
+                    Err(Some(e)) => tracing::error!("Failed to post to upstream: {}", e),
                 }
             }
             Commands::Break => {
-                if current_user_key.is_empty() {
-                    println!("Please register yourself first.");
+                require_user_key(&current_user_key);
+
+                if let Some(message) = refuse_transition(&latest_task, &TaskState::Break) {
+                    tracing::info!("{}", message);
                     return;
                 }
 
-                if latest_task.state == TaskState::Break {
-                    println!("You are already on break.");
+                let new_task = Task::generate_break_task(&latest_task);
+
+                tracing::debug!("Really?");
+
+                if let Err(Some(e)) =
+                    transitions.deliver(&mut file, &new_task, cli.force_local, false)
+                {
+                    tracing::error!("Failed to post to upstream: {}", e);
+                }
+            }
+            Commands::Back => {
+                require_user_key(&current_user_key);
+
+                if let Some(message) = refuse_transition(&latest_task, &TaskState::Back) {
+                    tracing::info!("{}", message);
                     return;
-                } else if latest_task.state == TaskState::End {
-                    println!("You are not working on anything.");
+                }
+
+                let new_task = Task::generate_back_task(&latest_task);
+
+                tracing::debug!("Ah, finally.");
+                tracing::info!(
+                    "break: {} (total breaks: {})",
+                    new_task.total_break_seconds - latest_task.total_break_seconds,
+                    new_task.total_break_seconds,
+                );
+
+                if let Err(Some(e)) =
+                    transitions.deliver(&mut file, &new_task, cli.force_local, false)
+                {
+                    tracing::error!("Failed to post to upstream: {}", e);
+                }
+            }
+            Commands::Done { wait } => {
+                require_user_key(&current_user_key);
+
+                if let Some(message) = refuse_transition(&latest_task, &TaskState::End) {
+                    tracing::info!("{}", message);
                     return;
                 }
 
-                let new_task = Task::generate_break_task(&latest_task);
+                let new_task = Task::generate_done_task(&latest_task);
 
-                println!("Really?");
+                // Waiting means the server must get this write now, so it
+                // can't be queued behind unsent ones.
+                match transitions.deliver(&mut file, &new_task, cli.force_local, *wait) {
+                    Ok(()) => tracing::info!(
+                        "You have worked on `{}` for {}.",
+                        new_task.name,
+                        new_task.duration,
+                    ),
+                    Err(None) if *wait => std::process::exit(1),
+                    Err(None) => {}
+                    Err(Some(e)) if *wait => {
+                        tracing::error!("The server didn't record the task: {}", e);
+                        std::process::exit(1);
+                    }
+                    Err(Some(e)) => tracing::error!("Failed to post to upstream: {}", e),
+                }
+            }
+            Commands::Check { warn_after } => {
+                require_user_key(&current_user_key);
+                if latest_task.is_placeholder() {
+                    tracing::info!("Ready for new challenges!");
+                } else {
+                    tracing::info!("You are working on `{}`.", latest_task.name);
+                }
+                if let Some(since) = latest_task.focused_since() {
+                    let now = chrono::offset::Local::now().naive_local();
+                    let streak = (now - since).num_seconds().max(0);
+                    tracing::info!("Focused for {} since your last break.", Seconds(streak));
+                    if warn_after.is_some_and(|limit| streak >= limit) {
+                        tracing::warn!(
+                            "You've been focused {}, consider a break.",
+                            Seconds(streak)
+                        );
+                    }
+                }
 
-                let payload = StoreTaskPayload {
+                let payload = GetSingleRecordPayload {
                     key: current_user_key.clone(),
-                    task: new_task.clone(),
+                    include_history: true,
                 };
-                if let Err(e) = make_request::<_, NewTaskResponse>(
+                match make_request::<_, RecordResponse>(
                     &request_client,
                     reqwest::Method::POST,
-                    &endpoints.post_task_payload,
+                    &endpoints.get_record,
                     payload,
                 ) {
-                    eprintln!("Failed to post to upstream: {}", e);
-                    return;
+                    Ok(resp_body) => {
+                        let record = resp_body.data.task_log;
+                        let today = chrono::offset::Local::now().date_naive();
+                        let day_start = today.and_hms_opt(0, 0, 0).unwrap();
+                        let worked = record.total_duration_in_range(
+                            day_start,
+                            day_start + chrono::Duration::days(1),
+                        );
+                        match record.daily_goal_seconds {
+                            Some(goal) => {
+                                tracing::info!("Today: {}", format_goal_progress(worked, goal))
+                            }
+                            None => tracing::info!("Today: {}", worked),
+                        }
+                        tracing::info!(
+                            "Breaks today: {}",
+                            break_seconds_on(&record.task_history, today)
+                        );
+                    }
+                    Err(e) => tracing::error!("Failed to fetch today's progress: {}", e),
                 }
+            }
+            Commands::Report {
+                range,
+                week,
+                format,
+                out,
+                by_task,
+                roll_up,
+            } => {
+                require_user_key(&current_user_key);
 
-                if let Err(e) = writeln!(file, "{}", serde_json::to_string(&new_task).unwrap()) {
-                    eprintln!("Couldn't write to file: {}", e);
+                let range = if *week { "this-week" } else { range.as_str() };
+                let now = chrono::offset::Local::now().naive_local();
+                let (from, to) = match parse_time_range(range, now) {
+                    Ok(bounds) => bounds,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+
+                let payload = GetSingleRecordPayload {
+                    key: current_user_key.clone(),
+                    include_history: true,
+                };
+                let record = match make_request::<_, RecordResponse>(
+                    &request_client,
+                    reqwest::Method::POST,
+                    &endpoints.get_record,
+                    payload,
+                ) {
+                    Ok(resp_body) => resp_body.data.task_log,
+                    Err(e) => {
+                        tracing::error!("Failed to fetch from upstream: {}", e);
+                        return;
+                    }
+                };
+
+                let days = group_by_day(&record.task_history, from, to);
+                let title = format!(
+                    "{} to {}",
+                    from.format("%Y-%m-%d"),
+                    to.format("%Y-%m-%d %H:%M")
+                );
+                let by_task = by_task.then(|| {
+                    let tasks: Vec<Task> = days.values().flatten().cloned().collect();
+                    total_durations(&tasks, *roll_up)
+                });
+                let report = match format {
+                    ReportFormat::Markdown => report::render_markdown(
+                        &title,
+                        &days,
+                        by_task.as_ref(),
+                        record.hourly_rate.as_ref(),
+                    ),
+                };
+
+                match out {
+                    Some(path) => {
+                        if let Err(e) = fs::write(path, report) {
+                            tracing::error!("Couldn't write to file: {}", e);
+                        }
+                    }
+                    None => print!("{}", report),
                 }
             }
-            Commands::Back {} => {
-                if current_user_key.is_empty() {
-                    println!("Please register yourself first.");
-                    return;
-                }
+            Commands::Log { limit, json } => {
+                require_user_key(&current_user_key);
 
-                if latest_task.state == TaskState::Begin {
-                    println!("You are already working on `{}`.", latest_task.name);
-                    return;
-                } else if latest_task.state == TaskState::End {
-                    println!("You are not working on anything.");
+                let payload = GetSingleRecordPayload {
+                    key: current_user_key.clone(),
+                    include_history: true,
+                };
+                let task_history = match make_request::<_, RecordResponse>(
+                    &request_client,
+                    reqwest::Method::POST,
+                    &endpoints.get_record,
+                    payload,
+                ) {
+                    Ok(resp_body) => resp_body.data.task_log.task_history,
+                    Err(e) => {
+                        tracing::error!("Failed to fetch from upstream: {}", e);
+                        return;
+                    }
+                };
+
+                let finished: Vec<&Task> = task_history
+                    .iter()
+                    .filter(|t| t.state == TaskState::End)
+                    .collect();
+                let shown: Vec<&Task> = finished.iter().rev().take(*limit).copied().collect();
+
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&shown).unwrap());
                     return;
                 }
 
-                let new_task = Task::generate_back_task(&latest_task);
-
-                println!("Ah, finally.");
+                for task in &shown {
+                    println!(
+                        "{}  {:>6}  {}",
+                        task.begin_time.format("%Y-%m-%d %H:%M"),
+                        task.duration,
+                        task.name
+                    );
+                }
+                println!(
+                    "{}",
+                    format_log_footer(
+                        shown.len(),
+                        finished.len(),
+                        shown.iter().map(|t| t.duration).sum()
+                    )
+                );
+            }
+            Commands::Export {
+                format,
+                output_timezone,
+                utc: _,
+                out,
+            } => {
+                require_user_key(&current_user_key);
 
-                let payload = StoreTaskPayload {
+                let payload = GetSingleRecordPayload {
                     key: current_user_key.clone(),
-                    task: new_task.clone(),
+                    include_history: true,
                 };
-                if let Err(e) = make_request::<_, NewTaskResponse>(
+                let task_history = match make_request::<_, RecordResponse>(
                     &request_client,
                     reqwest::Method::POST,
-                    &endpoints.post_task_payload,
+                    &endpoints.get_record,
                     payload,
                 ) {
-                    eprintln!("Failed to post to upstream: {}", e);
-                    return;
-                }
+                    Ok(resp_body) => resp_body.data.task_log.task_history,
+                    Err(e) => {
+                        tracing::error!("Failed to fetch from upstream: {}", e);
+                        return;
+                    }
+                };
+
+                // Tasks are stamped with the local time of the machine that ran `im`.
+                let rows = export::export_rows(&task_history, &chrono::Local, *output_timezone);
+                let export = match format {
+                    ExportFormat::Csv => export::render_csv(&rows),
+                    ExportFormat::Json => {
+                        format!("{}\n", serde_json::to_string_pretty(&rows).unwrap())
+                    }
+                };
 
-                if let Err(e) = writeln!(file, "{}", serde_json::to_string(&new_task).unwrap()) {
-                    eprintln!("Couldn't write to file: {}", e);
+                match out {
+                    Some(path) => {
+                        if let Err(e) = fs::write(path, export) {
+                            tracing::error!("Couldn't write to file: {}", e);
+                        }
+                    }
+                    None => print!("{}", export),
                 }
             }
-            Commands::Done {} => {
-                if current_user_key.is_empty() {
-                    println!("Please register yourself first.");
-                    return;
+            Commands::Import {
+                file,
+                allow_overlap,
+            } => {
+                require_user_key(&current_user_key);
+
+                let csv = match fs::read_to_string(file) {
+                    Ok(csv) => csv,
+                    Err(e) => {
+                        tracing::error!("Couldn't read {}: {}", file.display(), e);
+                        return;
+                    }
+                };
+
+                match make_csv_request::<serde_json::Value>(
+                    &request_client,
+                    &endpoints.import_csv,
+                    &current_user_key,
+                    *allow_overlap,
+                    csv,
+                ) {
+                    Ok(body) => {
+                        tracing::info!("Imported {} tasks.", body["data"]["imported"]);
+                    }
+                    Err(RequestError::Status {
+                        body: Some(body), ..
+                    }) if body["errors"].is_array() => {
+                        tracing::error!("{}", body["message"].as_str().unwrap_or_default());
+                        for error in body["errors"].as_array().unwrap() {
+                            tracing::error!(
+                                "line {}: {}",
+                                error["line"],
+                                error["message"].as_str().unwrap_or_default()
+                            );
+                        }
+                    }
+                    Err(RequestError::Status {
+                        status: reqwest::StatusCode::CONFLICT,
+                        body: Some(body),
+                    }) => {
+                        tracing::error!("{}", body["message"].as_str().unwrap_or_default());
+                        tracing::info!("Pass --allow-overlap to import it anyway.");
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to import: {}", e);
+                    }
                 }
+            }
+            Commands::Analyze { gap, range } => {
+                require_user_key(&current_user_key);
+
+                let min_gap_seconds = match parse_duration(gap) {
+                    Ok(seconds) => Seconds(seconds),
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+                let now = chrono::offset::Local::now().naive_local();
+                let (from, to) = match parse_time_range(range, now) {
+                    Ok(bounds) => bounds,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+
+                let payload = GetSingleRecordPayload {
+                    key: current_user_key.clone(),
+                    include_history: true,
+                };
+                let task_history = match make_request::<_, RecordResponse>(
+                    &request_client,
+                    reqwest::Method::POST,
+                    &endpoints.get_record,
+                    payload,
+                ) {
+                    Ok(resp_body) => resp_body.data.task_log.task_history,
+                    Err(e) => {
+                        tracing::error!("Failed to fetch from upstream: {}", e);
+                        return;
+                    }
+                };
 
-                if latest_task.state == TaskState::End {
-                    println!("You are not working on anything.");
+                let breaks: Vec<_> = infer_breaks(&task_history, min_gap_seconds)
+                    .into_iter()
+                    .filter(|b| b.from >= from && b.from < to)
+                    .collect();
+                if breaks.is_empty() {
+                    tracing::info!("No gaps of {} or more.", min_gap_seconds);
                     return;
                 }
 
-                let new_task = Task::generate_done_task(&latest_task);
+                println!("Proposed breaks, not applied:");
+                for inferred in breaks {
+                    println!(
+                        "+ [break] {} - {} ({}) between `{}` and `{}`",
+                        inferred.from.format("%Y-%m-%d %H:%M"),
+                        inferred.to.format("%H:%M"),
+                        inferred.seconds,
+                        task_history[inferred.after_index].name,
+                        task_history[inferred.before_index].name,
+                    );
+                }
+            }
+            Commands::Goal { duration } => {
+                require_user_key(&current_user_key);
 
-                println!(
-                    "You have worked on `{}` for {}.",
-                    new_task.name, new_task.duration,
-                );
+                let daily_goal_seconds = match duration.as_str() {
+                    "off" => None,
+                    duration => match parse_duration(duration) {
+                        Ok(seconds) => Some(seconds),
+                        Err(e) => {
+                            tracing::error!("{}", e);
+                            return;
+                        }
+                    },
+                };
 
-                let payload = StoreTaskPayload {
+                let payload = SetGoalPayload {
                     key: current_user_key.clone(),
-                    task: new_task.clone(),
+                    daily_goal_seconds,
                 };
-                if let Err(e) = make_request::<_, NewTaskResponse>(
+                match make_request::<_, StatusResponse>(
                     &request_client,
                     reqwest::Method::POST,
-                    &endpoints.post_task_payload,
+                    &endpoints.set_goal,
                     payload,
                 ) {
-                    eprintln!("Failed to post to upstream: {}", e);
-                    return;
+                    Ok(_) => match daily_goal_seconds {
+                        Some(seconds) => {
+                            tracing::info!("Goal set to {} a day.", Seconds(seconds))
+                        }
+                        None => tracing::info!("Goal cleared."),
+                    },
+                    Err(e) => tracing::error!("Failed to post to upstream: {}", e),
                 }
+            }
+            Commands::Rate { amount, currency } => {
+                require_user_key(&current_user_key);
+
+                let hourly_rate = match (amount.as_str(), currency) {
+                    ("off", _) => None,
+                    (_, None) => {
+                        tracing::error!("Missing currency, e.g. `im rate {} EUR`", amount);
+                        return;
+                    }
+                    (amount, Some(currency)) => match parse_money(amount) {
+                        Ok(cents) => Some(HourlyRate {
+                            cents,
+                            currency: currency.to_uppercase(),
+                        }),
+                        Err(e) => {
+                            tracing::error!("{}", e);
+                            return;
+                        }
+                    },
+                };
 
-                if let Err(e) = writeln!(file, "{}", serde_json::to_string(&new_task).unwrap()) {
-                    eprintln!("Couldn't write to file: {}", e);
+                let payload = SetRatePayload {
+                    key: current_user_key.clone(),
+                    hourly_rate: hourly_rate.clone(),
+                };
+                match make_request::<_, StatusResponse>(
+                    &request_client,
+                    reqwest::Method::POST,
+                    &endpoints.set_rate,
+                    payload,
+                ) {
+                    Ok(_) => match hourly_rate {
+                        Some(rate) => {
+                            tracing::info!("Rate set to {} an hour.", rate.earnings(Seconds(3600)))
+                        }
+                        None => tracing::info!("Rate cleared."),
+                    },
+                    Err(e) => tracing::error!("Failed to post to upstream: {}", e),
                 }
             }
-            Commands::Check {} => {
-                if current_user_key.is_empty() {
-                    println!("Please register yourself first.");
-                    return;
-                } else if latest_task.is_placeholder() {
-                    println!("Ready for new challenges!");
+            Commands::Sync { prefer, days } => {
+                require_user_key(&current_user_key);
+
+                // Queued writes go first, so they are compared as the
+                // server has them.
+                if let Err((e, pending)) =
+                    flush_outbox(&request_client, &endpoints.post_task_payload, &outbox)
+                {
+                    tracing::error!(
+                        "{} earlier change(s) couldn't be sent ({}). Try again once the server is reachable.",
+                        pending,
+                        e
+                    );
                     return;
                 }
-                println!("You are working on `{}`.", latest_task.name);
-            }
-            Commands::Auth { 0: auth_command } => match auth_command {
-                AuthCommand::New { user_name } => {
-                    if !current_user_name.is_empty() {
-                        println!("You are already registered as `{}`.", current_user_name);
-                        println!("Please unregister first.");
+
+                let payload = GetSingleRecordPayload {
+                    key: current_user_key.clone(),
+                    include_history: true,
+                };
+                let record = match make_request::<_, RecordResponse>(
+                    &request_client,
+                    reqwest::Method::POST,
+                    &endpoints.get_record,
+                    payload,
+                ) {
+                    Ok(resp_body) => resp_body.data.task_log,
+                    Err(e) => {
+                        tracing::error!("Failed to fetch from upstream: {}", e);
                         return;
                     }
+                };
+                let server_task = record.current_task;
 
-                    let payload = RegisterRecordPayload {
-                        user_name: user_name.clone(),
-                    };
+                let content = match fs::read_to_string(&op_log_path) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        tracing::error!("Couldn't read {}: {}", op_log_path.display(), e);
+                        return;
+                    }
+                };
+                let local_tasks = sync::collapse_log(&content);
+                let since = chrono::offset::Local::now().naive_local()
+                    - chrono::Duration::days(i64::from(*days));
+                let missing_on_server =
+                    sync::finished_missing_from(&local_tasks, &record.task_history, since);
+                let missing_locally =
+                    sync::finished_missing_from(&record.task_history, &local_tasks, since);
+                if !missing_locally.is_empty() {
+                    tracing::info!(
+                        "{} task(s) finished elsewhere in the last {} days, they stay on the server.",
+                        missing_locally.len(),
+                        days
+                    );
+                }
 
-                    match make_request::<_, AuthResponse>(
-                        &request_client,
-                        reqwest::Method::POST,
-                        &endpoints.auth,
-                        payload,
-                    ) {
-                        Ok(resp_body) => {
-                            let mut user_file = fs::File::options()
-                                .write(true)
-                                .create(true)
-                                .truncate(true)
-                                .open(user_log_path)
-                                .unwrap();
-
-                            if let Err(e) =
-                                user_file.write_all(&resp_body.data.user_key.into_bytes())
-                            {
-                                eprintln!("Couldn't write to file: {}", e);
+                if is_same_task(&latest_task, &server_task) && missing_on_server.is_empty() {
+                    tracing::info!("Already in sync.");
+                    return;
+                }
+
+                let Some(side) = prefer
+                    .or_else(|| ask_sync_side(&latest_task, &server_task, missing_on_server.len()))
+                else {
+                    tracing::info!("Nothing changed.");
+                    return;
+                };
+
+                if let SyncSide::Local = side {
+                    if !missing_on_server.is_empty() {
+                        let csv = export::render_csv(&export::export_rows(
+                            &missing_on_server,
+                            &chrono::Local,
+                            None,
+                        ));
+                        match make_csv_request::<serde_json::Value>(
+                            &request_client,
+                            &endpoints.import_csv,
+                            &current_user_key,
+                            false,
+                            csv,
+                        ) {
+                            Ok(body) => {
+                                tracing::info!(
+                                    "Sent {} finished task(s).",
+                                    body["data"]["imported"]
+                                )
+                            }
+                            Err(RequestError::Status {
+                                body: Some(body), ..
+                            }) if body["message"].is_string() => {
+                                tracing::error!(
+                                    "The server didn't take the finished tasks: {}",
+                                    body["message"].as_str().unwrap_or_default()
+                                );
+                                return;
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to post to upstream: {}", e);
                                 return;
                             }
                         }
+                    }
+
+                    let sent = match sync::plan_current_task(&latest_task, &server_task) {
+                        sync::CurrentTaskSync::Nothing => Ok(()),
+                        sync::CurrentTaskSync::Refuse(message) => {
+                            tracing::error!("{} Or keep the server side.", message);
+                            return;
+                        }
+                        sync::CurrentTaskSync::Transition(states) => {
+                            states.into_iter().try_for_each(|state| {
+                                let payload = UpdateTaskPayload {
+                                    key: current_user_key.clone(),
+                                    state,
+                                };
+                                make_request::<_, StatusResponse>(
+                                    &request_client,
+                                    reqwest::Method::POST,
+                                    &endpoints.update_task,
+                                    payload,
+                                )
+                                .map(|_| ())
+                            })
+                        }
+                        sync::CurrentTaskSync::Create => {
+                            let payload = StoreTaskPayload {
+                                key: current_user_key.clone(),
+                                task: latest_task.clone(),
+                            };
+                            make_request::<_, StatusResponse>(
+                                &request_client,
+                                reqwest::Method::POST,
+                                &endpoints.post_task_payload,
+                                payload,
+                            )
+                            .map(|_| ())
+                        }
+                    };
+                    match sent {
+                        Ok(()) => {}
+                        Err(RequestError::Status {
+                            body: Some(body), ..
+                        }) if body["message"].is_string() => {
+                            tracing::error!(
+                                "The server didn't take `{}`: {}",
+                                latest_task.name,
+                                body["message"].as_str().unwrap_or_default()
+                            );
+                            return;
+                        }
                         Err(e) => {
-                            eprintln!("Failed to post to upstream: {}", e);
+                            tracing::error!("Failed to post to upstream: {}", e);
                             return;
                         }
                     }
+                }
 
-                    println!("Drink water, {}.", user_name);
+                // The server stamps transitions with its own time, so the
+                // local log takes its version of the current task either way.
+                let server_task = match side {
+                    SyncSide::Server => Ok(server_task),
+                    SyncSide::Local => fetch_current_task(
+                        &request_client,
+                        &endpoints.get_record,
+                        &current_user_key,
+                    ),
+                };
+                match server_task {
+                    Ok(task)
+                        if matches!(side, SyncSide::Local)
+                            || !is_same_task(&latest_task, &task) =>
+                    {
+                        if let Err(e) = writeln!(file, "{}", serde_json::to_string(&task).unwrap())
+                        {
+                            tracing::error!("Couldn't write to file: {}", e);
+                            return;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!("Failed to fetch from upstream: {}", e);
+                        return;
+                    }
                 }
-                AuthCommand::LogIn { user_key } => {
+
+                tracing::info!("Synced.");
+            }
+            Commands::Auth { 0: auth_command } => match auth_command {
+                AuthCommand::New { user_name } => {
                     if !current_user_name.is_empty() {
-                        println!("You are already registered as `{}`.", current_user_name);
-                        println!("Please unregister first.");
+                        tracing::info!("You are already registered as `{}`.", current_user_name);
+                        tracing::info!("Please unregister first.");
                         return;
                     }
 
-                    match request_client
-                        .post(endpoints.get_task_log)
-                        .json(&serde_json::json!({
-                            "key": user_key,
-                        }))
-                        .send()
+                    if register_user(&request_client, &endpoints.auth, user_name, &user_log_path)
+                        .is_none()
                     {
-                        Ok(r) => {
-                            match r.error_for_status() {
-                                Ok(res) => {
-                                    let json_r = res.json::<TaskResponse>().unwrap();
-                                    println!("{:?}", json_r);
-
-                                    let mut user_file = fs::File::options()
-                                        .write(true)
-                                        .create(true)
-                                        .truncate(true)
-                                        .open(user_log_path)
-                                        .unwrap();
-
-                                    if let Err(e) = user_file.write_all(user_key.as_bytes()) {
-                                        eprintln!("Couldn't write to file: {}", e);
-                                        return;
-                                    }
-                                }
-                                Err(e) => {
-                                    if e.status().unwrap().is_client_error() {
-                                        println!("User not found.");
-                                    }
-                                }
-                            };
+                        std::process::exit(1);
+                    }
+
+                    tracing::debug!("Drink water, {}.", user_name);
+                }
+                AuthCommand::LogIn { user_key } => {
+                    if !current_user_name.is_empty() {
+                        tracing::info!("You are already registered as `{}`.", current_user_name);
+                        tracing::info!("Please unregister first.");
+                        return;
+                    }
+
+                    let lookup = |payload| {
+                        make_request::<_, RecordResponse>(
+                            &request_client,
+                            reqwest::Method::POST,
+                            &endpoints.get_record,
+                            payload,
+                        )
+                    };
+                    match log_in(user_key, &user_log_path, lookup) {
+                        Ok(user_name) => tracing::info!("Logged in as `{}`.", user_name),
+                        Err(e) => {
+                            tracing::error!("{}", e);
+                            std::process::exit(1);
                         }
+                    }
+                    tracing::debug!("Drink water, {}.", user_key);
+                }
+            },
+            Commands::Admin { 0: admin_command } => match admin_command {
+                AdminCommand::Users => {
+                    let Ok(admin_secret) = std::env::var("IMON_ADMIN_SECRET") else {
+                        tracing::error!("Please set `IMON_ADMIN_SECRET` first.");
+                        return;
+                    };
+
+                    match make_admin_request::<AllRecordsResponse>(
+                        &request_client,
+                        reqwest::Method::GET,
+                        &endpoints.get_all_records,
+                        &admin_secret,
+                    ) {
+                        Ok(resp_body) => print_user_table(&resp_body.data.user_records),
                         Err(e) => {
-                            println!("{:?}", e);
+                            tracing::error!("Failed to fetch from upstream: {}", e);
+                        }
+                    }
+                }
+            },
+            Commands::Debug { 0: debug_command } => match debug_command {
+                DebugCommand::ServerTime => {
+                    let sent = chrono::Utc::now();
+                    let resp_body = match make_get_request::<ServerTimeResponse>(
+                        &request_client,
+                        &endpoints.server_time,
+                    ) {
+                        Ok(resp_body) => resp_body,
+                        Err(e) => {
+                            tracing::error!("Failed to fetch from upstream: {}", e);
+                            return;
                         }
                     };
+                    let received = chrono::Utc::now();
+                    let server = resp_body.data;
+                    let local = chrono::Local::now();
 
-                    println!("Drink water, {}.", user_key);
+                    tracing::info!("Server time: {}", server.time.to_rfc3339());
+                    tracing::info!("Local time:  {}", local.to_rfc3339());
+                    let skew = clock_skew(sent, received, server.time.with_timezone(&chrono::Utc));
+                    tracing::info!("{}", describe_clock_skew(skew));
+                    let local_offset = local.format("%:z").to_string();
+                    if server.utc_offset != local_offset {
+                        tracing::warn!(
+                            "Time zones differ (server {}, local {}): tasks changed through \
+                             the server get its local time.",
+                            server.utc_offset,
+                            local_offset
+                        );
+                    }
                 }
             },
         }
     } else {
         // Case: no commands, just `im`.
         if !current_user_name.is_empty() {
-            println!(
+            tracing::info!(
                 "{}. You are {}.",
                 current_user_name.to_uppercase(),
                 current_user_name
             );
         } else {
-            println!("You are not logged in.")
+            tracing::info!("You are not logged in.")
         }
     }
 }
@@ -408,6 +1574,57 @@ fn main() {
 mod tests {
     use super::*;
 
+    fn record_response(user_name: &str, id: i32) -> RecordResponse {
+        RecordResponse::ok(libs::response::TaskLogData {
+            task_log: libs::record::UserRecord::new(id, user_name.to_string()),
+        })
+    }
+
+    #[test]
+    fn test_log_in_only_saves_confirmed_keys() {
+        let path = std::env::temp_dir().join(format!("imon-login-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let not_found = log_in("user:alice:0001", &path, |_| {
+            Err(RequestError::Status {
+                status: reqwest::StatusCode::UNPROCESSABLE_ENTITY,
+                body: None,
+            })
+        });
+        assert_eq!(not_found, Err("User not found.".to_string()));
+        assert!(!path.exists());
+
+        let other_user = log_in("user:alice:0001", &path, |_| Ok(record_response("bob", 2)));
+        assert!(other_user.is_err());
+        assert!(!path.exists());
+
+        let not_a_key = log_in("alice", &path, |_| panic!("no lookup for a malformed key"));
+        assert!(not_a_key.is_err());
+        assert!(!path.exists());
+
+        let found = log_in("user:alice:0001", &path, |payload| {
+            assert_eq!(payload.key, "user:alice:0001");
+            Ok(record_response("alice", 1))
+        });
+        assert_eq!(found, Ok("alice".to_string()));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "user:alice:0001");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_on_joins_unquoted_words() {
+        let cli = Cli::try_parse_from(["im", "on", "fix", "the", "bug", "--billable"]).unwrap();
+        let Some(Commands::On { name, billable, .. }) = cli.command else {
+            panic!("expected `on`");
+        };
+        assert_eq!(name.join(" "), "fix the bug");
+        assert!(billable);
+
+        let cli = Cli::try_parse_from(["im", "on"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::On { name, .. }) if name.is_empty()));
+        assert!(Cli::try_parse_from(["im", "on", "fix", "--stdin"]).is_err());
+    }
+
     #[test]
     fn test_get_latest_task_local() {
         let mut file = fs::File::options()
@@ -419,4 +1636,50 @@ mod tests {
 
         let _parts_by_space = get_latest_task_local(&mut file);
     }
+
+    #[test]
+    fn test_is_same_task() {
+        let task = Task::generate_begin_task("writing".to_string());
+        let mut other = task.clone();
+        assert!(is_same_task(&task, &other));
+
+        other.state = TaskState::Break;
+        assert!(!is_same_task(&task, &other));
+
+        let fresh = Task::placeholder("fresh", TaskState::Placeholder);
+        let reset = Task::placeholder("reset", TaskState::Placeholder);
+        assert!(is_same_task(&fresh, &reset));
+    }
+
+    #[test]
+    fn test_refuse_transition_from_inactive_states() {
+        for state in [TaskState::End, TaskState::Placeholder] {
+            let task = Task::placeholder("writing", state);
+            for next in [TaskState::Break, TaskState::Back, TaskState::End] {
+                assert_eq!(
+                    refuse_transition(&task, &next).as_deref(),
+                    Some("You are not working on anything.")
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_refuse_transition_from_active_states() {
+        let task = Task::generate_begin_task("writing".to_string());
+        assert!(refuse_transition(&task, &TaskState::Break).is_none());
+        assert!(refuse_transition(&task, &TaskState::End).is_none());
+        assert_eq!(
+            refuse_transition(&task, &TaskState::Back).as_deref(),
+            Some("You are already working on `writing`.")
+        );
+
+        let on_break = Task::generate_break_task(&task);
+        assert!(refuse_transition(&on_break, &TaskState::Back).is_none());
+        assert!(refuse_transition(&on_break, &TaskState::End).is_none());
+        assert_eq!(
+            refuse_transition(&on_break, &TaskState::Break).as_deref(),
+            Some("You are already on break.")
+        );
+    }
 }
@@ -0,0 +1,212 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use libs::{payload::StoreTaskPayload, record::Task};
+
+use crate::util::RequestError;
+
+/// Task writes that couldn't reach the server yet, one JSON payload per line,
+/// oldest first.
+pub struct Outbox {
+    path: PathBuf,
+}
+
+/// What `Outbox::flush` got through.
+#[derive(Debug, Default)]
+pub struct Flushed {
+    /// Tasks of the payloads the server refused, dropped from the outbox since sending
+    /// them again would only be refused again.
+    pub rejected: Vec<(Task, RequestError)>,
+}
+
+impl Outbox {
+    pub fn new(path: PathBuf) -> Self {
+        Outbox { path }
+    }
+
+    /// Queued payloads, oldest first. Lines that don't parse are reported
+    /// and left in the outbox, see `read`.
+    pub fn pending(&self) -> Vec<StoreTaskPayload> {
+        self.read().0
+    }
+
+    /// The parsed payloads, and the lines that aren't one.
+    fn read(&self) -> (Vec<StoreTaskPayload>, Vec<String>) {
+        let Ok(content) = fs::read_to_string(&self.path) else {
+            return (vec![], vec![]);
+        };
+        let mut payloads = vec![];
+        let mut unreadable = vec![];
+        for (index, line) in content.lines().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(payload) => payloads.push(payload),
+                Err(e) => {
+                    tracing::warn!(
+                        "Kept unreadable line {} of {}: {}",
+                        index + 1,
+                        self.path.display(),
+                        e
+                    );
+                    unreadable.push(line.to_string());
+                }
+            }
+        }
+        (payloads, unreadable)
+    }
+
+    pub fn push(&self, payload: &StoreTaskPayload) -> io::Result<()> {
+        let mut file = fs::File::options()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(payload)?)
+    }
+
+    fn replace(&self, unreadable: &[String], payloads: &[StoreTaskPayload]) -> io::Result<()> {
+        let mut content = String::new();
+        for line in unreadable {
+            content.push_str(line);
+            content.push('\n');
+        }
+        for payload in payloads {
+            content.push_str(&serde_json::to_string(payload)?);
+            content.push('\n');
+        }
+        fs::write(&self.path, content)
+    }
+
+    /// Send pending payloads in order, stopping when the server can't take
+    /// them for now, see `RequestError::is_temporary`.
+    ///
+    /// Sent and rejected payloads are dropped from the outbox, the rejected
+    /// ones are returned with why. When the server can't be reached, the
+    /// error is returned with the number of payloads still pending.
+    pub fn flush(
+        &self,
+        mut send: impl FnMut(&StoreTaskPayload) -> Result<(), RequestError>,
+    ) -> Result<Flushed, (RequestError, usize)> {
+        let (pending, unreadable) = self.read();
+        let mut flushed = Flushed::default();
+        for (sent, payload) in pending.iter().enumerate() {
+            match send(payload) {
+                Ok(()) => {}
+                Err(e) if e.is_temporary() => {
+                    let remaining = &pending[sent..];
+                    if let Err(io_err) = self.replace(&unreadable, remaining) {
+                        tracing::error!("Couldn't write to file: {}", io_err);
+                    }
+                    return Err((e, remaining.len()));
+                }
+                Err(e) => flushed.rejected.push((payload.task.clone(), e)),
+            }
+        }
+        if !pending.is_empty() {
+            if let Err(io_err) = self.replace(&unreadable, &[]) {
+                tracing::error!("Couldn't write to file: {}", io_err);
+            }
+        }
+        Ok(flushed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outbox(name: &str) -> Outbox {
+        let path =
+            std::env::temp_dir().join(format!("imon-outbox-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        Outbox::new(path)
+    }
+
+    fn payload(name: &str) -> StoreTaskPayload {
+        StoreTaskPayload {
+            key: "user:alice:0001".to_string(),
+            task: Task::generate_begin_task(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_flush_sends_in_order_and_empties() {
+        let outbox = outbox("in-order");
+        outbox.push(&payload("first")).unwrap();
+        outbox.push(&payload("second")).unwrap();
+
+        let mut sent = vec![];
+        let result = outbox.flush(|p| {
+            sent.push(p.task.name.clone());
+            Ok(())
+        });
+
+        assert!(result.is_ok_and(|flushed| flushed.rejected.is_empty()));
+        assert_eq!(sent, vec!["first", "second"]);
+        assert!(outbox.pending().is_empty());
+    }
+
+    #[test]
+    fn test_flush_keeps_unsent_when_unreachable() {
+        let outbox = outbox("failure");
+        for name in ["first", "second", "third"] {
+            outbox.push(&payload(name)).unwrap();
+        }
+
+        let result = outbox.flush(|p| match p.task.name.as_str() {
+            "first" => Ok(()),
+            _ => Err(RequestError::Timeout),
+        });
+
+        assert!(matches!(result, Err((RequestError::Timeout, 2))));
+        let pending: Vec<String> = outbox.pending().into_iter().map(|p| p.task.name).collect();
+        assert_eq!(pending, vec!["second", "third"]);
+    }
+
+    #[test]
+    fn test_flush_drops_rejected_and_goes_on() {
+        let outbox = outbox("rejected");
+        for name in ["first", "second", "third"] {
+            outbox.push(&payload(name)).unwrap();
+        }
+
+        let mut sent = vec![];
+        let result = outbox.flush(|p| {
+            sent.push(p.task.name.clone());
+            match p.task.name.as_str() {
+                "second" => Err(RequestError::Status {
+                    status: reqwest::StatusCode::CONFLICT,
+                    body: None,
+                }),
+                _ => Ok(()),
+            }
+        });
+
+        let rejected: Vec<String> = result
+            .unwrap()
+            .rejected
+            .into_iter()
+            .map(|(task, _)| task.name)
+            .collect();
+        assert_eq!(rejected, vec!["second"]);
+        assert_eq!(sent, vec!["first", "second", "third"]);
+        assert!(outbox.pending().is_empty());
+    }
+
+    #[test]
+    fn test_flush_keeps_unreadable_lines() {
+        let outbox = outbox("unreadable");
+        outbox.push(&payload("first")).unwrap();
+        let mut file = fs::File::options().append(true).open(&outbox.path).unwrap();
+        writeln!(file, "{{\"key\": \"user:alice:0001\"").unwrap();
+
+        assert_eq!(outbox.pending().len(), 1);
+        assert!(outbox.flush(|_| Ok(())).is_ok());
+        assert!(outbox.pending().is_empty());
+        assert_eq!(
+            fs::read_to_string(&outbox.path).unwrap(),
+            "{\"key\": \"user:alice:0001\"\n"
+        );
+    }
+}
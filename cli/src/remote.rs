@@ -0,0 +1,71 @@
+use std::{fs, io, path::PathBuf, time::Duration};
+
+use chrono::NaiveDateTime;
+use libs::record::Task;
+use serde::{Deserialize, Serialize};
+
+/// How long a task read from the server is trusted before asking again.
+pub const REMOTE_TASK_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize)]
+struct CachedTask {
+    fetched_at: NaiveDateTime,
+    task: Task,
+}
+
+/// Last known server-side `current_task`, so back-to-back commands in
+/// `--remote` mode don't each wait for a round-trip.
+pub struct RemoteTaskCache {
+    path: PathBuf,
+}
+
+impl RemoteTaskCache {
+    pub fn new(path: PathBuf) -> Self {
+        RemoteTaskCache { path }
+    }
+
+    /// The cached task, unless it's older than `REMOTE_TASK_TTL` at `now`.
+    pub fn get(&self, now: NaiveDateTime) -> Option<Task> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let cached = serde_json::from_str::<CachedTask>(&content).ok()?;
+        let age = (now - cached.fetched_at).to_std().ok()?;
+        (age < REMOTE_TASK_TTL).then_some(cached.task)
+    }
+
+    pub fn set(&self, task: &Task, now: NaiveDateTime) -> io::Result<()> {
+        let cached = CachedTask {
+            fetched_at: now,
+            task: task.clone(),
+        };
+        fs::write(&self.path, serde_json::to_string(&cached)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use super::*;
+
+    #[test]
+    fn test_cached_task_expires() {
+        let path = std::env::temp_dir().join(format!("imon-remote-{}.json", std::process::id()));
+        let cache = RemoteTaskCache::new(path.clone());
+        let fetched_at = NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        let task = Task::generate_begin_task("write".to_string());
+
+        assert!(cache.get(fetched_at).is_none());
+        cache.set(&task, fetched_at).unwrap();
+
+        let cached = cache.get(fetched_at + Duration::seconds(10)).unwrap();
+        assert_eq!(cached.name, "write");
+        assert!(cache.get(fetched_at + Duration::seconds(31)).is_none());
+        // A clock that went backwards doesn't count as fresh.
+        assert!(cache.get(fetched_at - Duration::seconds(1)).is_none());
+
+        let _ = fs::remove_file(path);
+    }
+}
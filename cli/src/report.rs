@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use libs::record::{HourlyRate, Seconds, Task};
+
+/// Render tasks grouped by day as a Markdown document: a heading per day,
+/// a bullet per task with its duration, and the total for the whole range.
+/// With `by_task`, the total per task name, see `total_durations`, comes
+/// before it. With an `hourly_rate`, the billable time and what it earns
+/// are added.
+pub fn render_markdown(
+    title: &str,
+    days: &BTreeMap<NaiveDate, Vec<Task>>,
+    by_task: Option<&BTreeMap<String, Seconds>>,
+    hourly_rate: Option<&HourlyRate>,
+) -> String {
+    let mut out = format!("# {}\n\n", title);
+    if days.is_empty() {
+        out.push_str("No finished tasks.\n");
+        return out;
+    }
+
+    let (mut total, mut billable) = (Seconds(0), Seconds(0));
+    for (day, tasks) in days {
+        out.push_str(&format!("## {}\n\n", day.format("%A, %Y-%m-%d")));
+        for task in tasks {
+            out.push_str(&format!("- {} ({})\n", task.name, task.duration));
+            total += task.duration;
+            if task.billable {
+                billable += task.duration;
+            }
+        }
+        out.push('\n');
+    }
+    if let Some(by_task) = by_task {
+        out.push_str("## By task\n\n");
+        for (name, duration) in by_task {
+            out.push_str(&format!("- {} ({})\n", name, duration));
+        }
+        out.push('\n');
+    }
+    out.push_str(&format!("**Total: {}**\n", total));
+    if let Some(rate) = hourly_rate {
+        out.push_str(&format!(
+            "**Billable: {}, {}**\n",
+            billable,
+            rate.earnings(billable)
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use libs::stats::total_durations;
+
+    use super::*;
+
+    #[test]
+    fn test_render_markdown_report() {
+        let day = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let task = |name: &str, duration| Task {
+            name: name.to_string(),
+            duration: Seconds(duration),
+            ..Task::generate_done_task(&Task::generate_begin_task(name.to_string()))
+        };
+        let days = BTreeMap::from([(day, vec![task("writing", 5400), task("review", 1800)])]);
+
+        assert_eq!(
+            render_markdown("Week", &days, None, None),
+            "# Week\n\n\
+             ## Wednesday, 2024-01-31\n\n\
+             - writing (1h30m)\n\
+             - review (30m)\n\n\
+             **Total: 2h0m**\n"
+        );
+    }
+
+    #[test]
+    fn test_render_markdown_report_by_task() {
+        let day = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let task = |name: &str, parent: Option<&str>, duration| Task {
+            name: name.to_string(),
+            duration: Seconds(duration),
+            parent: parent.map(str::to_string),
+            ..Task::generate_done_task(&Task::generate_begin_task(name.to_string()))
+        };
+        let tasks = vec![
+            task("release", None, 1800),
+            task("tests", Some("release"), 3600),
+        ];
+        let by_task = total_durations(&tasks, true);
+        let days = BTreeMap::from([(day, tasks)]);
+
+        assert!(
+            render_markdown("Week", &days, Some(&by_task), None).ends_with(
+                "## By task\n\n\
+             - release (1h30m)\n\
+             - tests (1h0m)\n\n\
+             **Total: 1h30m**\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_markdown_report_with_earnings() {
+        let day = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let task = |name: &str, duration, billable| Task {
+            name: name.to_string(),
+            duration: Seconds(duration),
+            billable,
+            ..Task::generate_done_task(&Task::generate_begin_task(name.to_string()))
+        };
+        let days = BTreeMap::from([(
+            day,
+            vec![task("client", 5400, true), task("admin", 1800, false)],
+        )]);
+        let rate = HourlyRate {
+            cents: 4550,
+            currency: "EUR".to_string(),
+        };
+
+        assert!(render_markdown("Week", &days, None, Some(&rate))
+            .ends_with("**Total: 2h0m**\n**Billable: 1h30m, 68.25 EUR**\n"));
+    }
+
+    #[test]
+    fn test_render_markdown_empty_report() {
+        assert_eq!(
+            render_markdown("Week", &BTreeMap::new(), None, None),
+            "# Week\n\nNo finished tasks.\n"
+        );
+    }
+}
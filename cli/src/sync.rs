@@ -0,0 +1,207 @@
+use chrono::NaiveDateTime;
+use libs::record::{Task, TaskState};
+
+/// The tasks in the local log, oldest first. The log holds a snapshot per
+/// transition, so only the last snapshot of each task is kept.
+pub fn collapse_log(content: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = vec![];
+    for line in content.lines() {
+        let Ok(task) = serde_json::from_str::<Task>(line) else {
+            continue;
+        };
+        if task.is_placeholder() {
+            continue;
+        }
+        match tasks.iter_mut().find(|t| is_same_task(t, &task)) {
+            Some(existing) => *existing = task,
+            None => tasks.push(task),
+        }
+    }
+    tasks
+}
+
+/// Two snapshots of one task, whatever state each was taken in.
+fn is_same_task(a: &Task, b: &Task) -> bool {
+    a.name == b.name && a.begin_time == b.begin_time
+}
+
+/// Tasks of `tasks` finished and begun since `since` that `others` lacks.
+pub fn finished_missing_from(tasks: &[Task], others: &[Task], since: NaiveDateTime) -> Vec<Task> {
+    tasks
+        .iter()
+        .filter(|t| t.state == TaskState::End && t.begin_time >= since)
+        .filter(|t| !others.iter().any(|other| is_same_task(t, other)))
+        .cloned()
+        .collect()
+}
+
+/// How the server's current task takes the local one's place.
+#[derive(Debug, PartialEq)]
+pub enum CurrentTaskSync {
+    /// Nothing to send: both are the same, or there is only a placeholder
+    /// or a finished task here.
+    Nothing,
+    /// Move the server's task through these states with `/v1/task/update`.
+    Transition(Vec<TaskState>),
+    /// Post the local task as a new one.
+    Create,
+    /// The local task can't be kept, worded for the user.
+    Refuse(String),
+}
+
+/// What keeping `local` as the current task takes, with `server` the
+/// server's one. Finished tasks are left to the history import.
+pub fn plan_current_task(local: &Task, server: &Task) -> CurrentTaskSync {
+    if local.is_placeholder() || (local.state == TaskState::End && !is_same_task(local, server)) {
+        return CurrentTaskSync::Nothing;
+    }
+    if is_same_task(local, server) {
+        if local.state == server.state {
+            return CurrentTaskSync::Nothing;
+        }
+        return match transition_steps(&server.state, &local.state) {
+            Some(steps) => CurrentTaskSync::Transition(steps),
+            None => CurrentTaskSync::Refuse(format!(
+                "`{}` is already {:?} on the server, it can't go back to {:?}.",
+                server.name, server.state, local.state
+            )),
+        };
+    }
+    if server.state.is_active() {
+        return CurrentTaskSync::Refuse(format!(
+            "The server is still working on `{}`, finish it there first.",
+            server.name
+        ));
+    }
+    CurrentTaskSync::Create
+}
+
+/// States taking a task from `from` to `to`. Being back from `Begin` or
+/// `Back` means a break was taken and ended in between. A task only
+/// begins once.
+fn transition_steps(from: &TaskState, to: &TaskState) -> Option<Vec<TaskState>> {
+    if *to == TaskState::Begin {
+        return None;
+    }
+    if from.can_transition_to(to) {
+        return Some(vec![to.clone()]);
+    }
+    match (from, to) {
+        (TaskState::Begin | TaskState::Back, TaskState::Back) => {
+            Some(vec![TaskState::Break, TaskState::Back])
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(tasks: &[Task]) -> Vec<(&str, TaskState)> {
+        tasks
+            .iter()
+            .map(|t| (t.name.as_str(), t.state.clone()))
+            .collect()
+    }
+
+    fn log(tasks: &[&Task]) -> String {
+        tasks
+            .iter()
+            .map(|t| format!("{}\n", serde_json::to_string(t).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_collapse_log_keeps_last_snapshot_of_each_task() {
+        let writing = Task::generate_begin_task("writing".to_string());
+        let on_break = Task::generate_break_task(&writing);
+        let done = Task::generate_done_task(&on_break);
+        let review = Task::generate_begin_task("review".to_string());
+        let fresh = Task::placeholder("fresh", TaskState::Placeholder);
+
+        let content = log(&[&fresh, &writing, &on_break, &done, &review]);
+        let tasks = collapse_log(&format!("{}not json\n", content));
+
+        assert_eq!(
+            names(&tasks),
+            vec![("writing", TaskState::End), ("review", TaskState::Begin)]
+        );
+    }
+
+    #[test]
+    fn test_finished_missing_from_compares_recent_finished_tasks() {
+        let now = chrono::offset::Local::now().naive_local();
+        let old = Task {
+            begin_time: now - chrono::Duration::days(30),
+            ..Task::generate_done_task(&Task::generate_begin_task("old".to_string()))
+        };
+        let shared = Task::generate_done_task(&Task::generate_begin_task("shared".to_string()));
+        let local_only = Task::generate_done_task(&Task::generate_begin_task("local".to_string()));
+        let active = Task::generate_begin_task("active".to_string());
+
+        let local = vec![old, shared.clone(), local_only, active];
+        let server = vec![shared];
+
+        let since = now - chrono::Duration::days(7);
+        assert_eq!(
+            names(&finished_missing_from(&local, &server, since)),
+            vec![("local", TaskState::End)]
+        );
+        assert!(finished_missing_from(&server, &local, since).is_empty());
+    }
+
+    #[test]
+    fn test_plan_skips_placeholders_and_finished_tasks() {
+        let server = Task::generate_begin_task("writing".to_string());
+        let fresh = Task::placeholder("fresh", TaskState::Placeholder);
+        assert_eq!(plan_current_task(&fresh, &server), CurrentTaskSync::Nothing);
+
+        let done = Task::generate_done_task(&Task::generate_begin_task("review".to_string()));
+        assert_eq!(plan_current_task(&done, &server), CurrentTaskSync::Nothing);
+        assert_eq!(
+            plan_current_task(&server, &server),
+            CurrentTaskSync::Nothing
+        );
+    }
+
+    #[test]
+    fn test_plan_transitions_the_same_task() {
+        let server = Task::generate_begin_task("writing".to_string());
+        let on_break = Task::generate_break_task(&server);
+        let back = Task::generate_back_task(&on_break);
+        let done = Task::generate_done_task(&back);
+
+        assert_eq!(
+            plan_current_task(&on_break, &server),
+            CurrentTaskSync::Transition(vec![TaskState::Break])
+        );
+        assert_eq!(
+            plan_current_task(&back, &server),
+            CurrentTaskSync::Transition(vec![TaskState::Break, TaskState::Back])
+        );
+        assert_eq!(
+            plan_current_task(&done, &on_break),
+            CurrentTaskSync::Transition(vec![TaskState::End])
+        );
+        assert!(matches!(
+            plan_current_task(&server, &done),
+            CurrentTaskSync::Refuse(_)
+        ));
+    }
+
+    #[test]
+    fn test_plan_creates_only_when_server_is_idle() {
+        let local = Task::generate_begin_task("writing".to_string());
+        let busy = Task::generate_begin_task("review".to_string());
+        assert!(matches!(
+            plan_current_task(&local, &busy),
+            CurrentTaskSync::Refuse(_)
+        ));
+
+        let idle = Task::generate_done_task(&busy);
+        assert_eq!(plan_current_task(&local, &idle), CurrentTaskSync::Create);
+        let fresh = Task::placeholder("initialised", TaskState::Placeholder);
+        assert_eq!(plan_current_task(&local, &fresh), CurrentTaskSync::Create);
+    }
+}
@@ -1,29 +1,474 @@
-use reqwest::{blocking::Client, Method};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use reqwest::{
+    blocking::{Client, RequestBuilder},
+    Method, StatusCode,
+};
 use serde::Serialize;
 
+use libs::{record::Seconds, ADMIN_SECRET_HEADER};
+
+#[derive(Debug)]
+pub enum RequestError {
+    /// The server didn't answer in time.
+    Timeout,
+    /// The request never got a response.
+    Send(String),
+    /// The server answered with a non-success status, and maybe an error envelope.
+    Status {
+        status: StatusCode,
+        body: Option<serde_json::Value>,
+    },
+    /// The server answered with a success status, but not the expected body.
+    Decode(String),
+}
+
+impl RequestError {
+    /// Whether sending the request again later may work: the server wasn't
+    /// reached, or asked to slow down.
+    pub fn is_temporary(&self) -> bool {
+        match self {
+            RequestError::Timeout | RequestError::Send(_) => true,
+            RequestError::Status { status, .. } => *status == StatusCode::TOO_MANY_REQUESTS,
+            RequestError::Decode(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Timeout => write!(f, "Server not responding."),
+            RequestError::Send(e) => write!(f, "Error sending request: {}", e),
+            RequestError::Status { status, .. } => write!(f, "Error: {:?}", status),
+            RequestError::Decode(e) => write!(f, "Unexpected response: {}", e),
+        }
+    }
+}
+
 pub fn make_request<T, B>(
     request_client: &Client,
     method: Method,
     url: &str,
     body: T,
-) -> Result<B, String>
+) -> Result<B, RequestError>
 where
     T: Serialize,
     B: std::fmt::Debug + serde::de::DeserializeOwned,
 {
-    let resp = request_client
-        .request(method, url)
-        .json(&body)
-        .send()
-        .map_err(|e| format!("Error sending request: {}", e))?;
+    send_request(request_client.request(method, url).json(&body))
+}
+
+/// Same as `make_request`, for admin-only endpoints which take no body.
+pub fn make_admin_request<B>(
+    request_client: &Client,
+    method: Method,
+    url: &str,
+    admin_secret: &str,
+) -> Result<B, RequestError>
+where
+    B: std::fmt::Debug + serde::de::DeserializeOwned,
+{
+    send_request(
+        request_client
+            .request(method, url)
+            .header(ADMIN_SECRET_HEADER, admin_secret),
+    )
+}
+
+/// `GET` an endpoint that needs neither a body nor a user.
+pub fn make_get_request<B>(request_client: &Client, url: &str) -> Result<B, RequestError>
+where
+    B: std::fmt::Debug + serde::de::DeserializeOwned,
+{
+    send_request(request_client.get(url))
+}
+
+/// Send a whole CSV file in one request, for endpoints importing into the
+/// record named by `key`. One request regardless of size, the server
+/// applies all rows or none.
+pub fn make_csv_request<B>(
+    request_client: &Client,
+    url: &str,
+    key: &str,
+    allow_overlap: bool,
+    csv: String,
+) -> Result<B, RequestError>
+where
+    B: std::fmt::Debug + serde::de::DeserializeOwned,
+{
+    send_request(
+        request_client
+            .post(url)
+            .query(&[("key", key), ("allow_overlap", &allow_overlap.to_string())])
+            .header(reqwest::header::CONTENT_TYPE, "text/csv")
+            .body(csv),
+    )
+}
+
+fn send_request<B>(request: RequestBuilder) -> Result<B, RequestError>
+where
+    B: std::fmt::Debug + serde::de::DeserializeOwned,
+{
+    let resp = request.send().map_err(|e| {
+        if e.is_timeout() {
+            RequestError::Timeout
+        } else {
+            RequestError::Send(e.to_string())
+        }
+    })?;
 
     let status = resp.status();
 
     if status.is_success() {
-        let body = resp.json::<B>().unwrap();
-        println!("{:?}", body);
+        let body = resp
+            .json::<B>()
+            .map_err(|e| RequestError::Decode(e.to_string()))?;
+        tracing::debug!("{:?}", body);
         Ok(body)
     } else {
-        Err(format!("Error: {:?}", status))
+        Err(RequestError::Status {
+            status,
+            body: resp.json::<serde_json::Value>().ok(),
+        })
+    }
+}
+
+/// The user name and id in a user's key, `user:<user_name>:<id>`.
+pub fn parse_user_key(key: &str) -> Option<(&str, i32)> {
+    let mut segments = key.split(':');
+    match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some("user"), Some(user_name), Some(id), None) if !user_name.is_empty() => {
+            Some((user_name, id.parse().ok().filter(|id| *id >= 0)?))
+        }
+        _ => None,
+    }
+}
+
+/// Read a task name piped to stdin, without its trailing newline.
+/// Refuses input spanning several lines, it's a single name.
+pub fn read_task_name(input: &mut impl std::io::Read) -> Result<String, String> {
+    let mut name = String::new();
+    input
+        .read_to_string(&mut name)
+        .map_err(|e| format!("Couldn't read the task name: {}", e))?;
+    let name = name.strip_suffix('\n').unwrap_or(&name);
+    let name = name.strip_suffix('\r').unwrap_or(name);
+    if name.contains('\n') {
+        return Err("Expected the task name on a single line".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// How far the server's clock is ahead of this machine's, negative when it
+/// is behind. `server_time` was read between `sent` and `received`, so it is
+/// compared with the middle of the two.
+pub fn clock_skew(
+    sent: DateTime<Utc>,
+    received: DateTime<Utc>,
+    server_time: DateTime<Utc>,
+) -> Duration {
+    let midpoint = sent + (received - sent) / 2;
+    server_time - midpoint
+}
+
+/// E.g. `Server clock is 2m ahead of this machine.` Skews under a second
+/// are within what one request can measure.
+pub fn describe_clock_skew(skew: Duration) -> String {
+    let seconds = skew.num_seconds();
+    match seconds {
+        0 => "Server clock is in sync with this machine.".to_string(),
+        _ if seconds > 0 => format!(
+            "Server clock is {} ahead of this machine.",
+            Seconds(seconds)
+        ),
+        _ => format!("Server clock is {} behind this machine.", Seconds(-seconds)),
+    }
+}
+
+/// Parse a duration such as `6h`, `90m` or `1h30m` into seconds.
+pub fn parse_duration(input: &str) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "Invalid duration `{}`. Expected e.g. 6h, 90m or 1h30m",
+            input
+        )
+    };
+    let input = input.trim();
+    let (hours, minutes) = match input.split_once('h') {
+        Some((hours, rest)) => (hours, rest.strip_suffix('m').unwrap_or(rest)),
+        None => ("0", input.strip_suffix('m').ok_or_else(invalid)?),
+    };
+    let hours = hours.parse::<i64>().map_err(|_| invalid())?;
+    let minutes = match minutes {
+        "" => 0,
+        m => m.parse::<i64>().map_err(|_| invalid())?,
+    };
+
+    match hours * 3600 + minutes * 60 {
+        0 => Err(invalid()),
+        seconds => Ok(seconds),
+    }
+}
+
+/// Parse an amount of money such as `45`, `45.5` or `45.50` into cents.
+pub fn parse_money(input: &str) -> Result<i64, String> {
+    let invalid = || format!("Invalid amount `{}`. Expected e.g. 45 or 45.50", input);
+    let input = input.trim();
+    let (units, fraction) = input.split_once('.').unwrap_or((input, ""));
+    // Digits only, so a sign can't slip through on a zero, as in `-0.5`.
+    if !units.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let units = units.parse::<i64>().map_err(|_| invalid())?;
+    let fraction = match fraction.len() {
+        0 => 0,
+        1 | 2 if fraction.chars().all(|c| c.is_ascii_digit()) => format!("{:0<2}", fraction)
+            .parse::<i64>()
+            .map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+
+    match units * 100 + fraction {
+        cents if cents > 0 => Ok(cents),
+        _ => Err(invalid()),
+    }
+}
+
+/// Format progress towards a daily goal, e.g. `3h12m / 6h0m (53%)`.
+pub fn format_goal_progress(worked: Seconds, goal_seconds: i64) -> String {
+    format!(
+        "{} / {} ({}%)",
+        worked,
+        Seconds(goal_seconds),
+        worked.0 * 100 / goal_seconds.max(1)
+    )
+}
+
+/// Footer under a list of tasks, e.g. `showing 10 of 342 tasks, total 58h12m`.
+pub fn format_log_footer(shown: usize, total: usize, shown_seconds: Seconds) -> String {
+    format!(
+        "showing {} of {} tasks, total {}",
+        shown, total, shown_seconds
+    )
+}
+
+/// Parse a human time range, relative to `now`, into a half-open `(from, to)`.
+///
+/// Accepted forms:
+/// - `today`, `yesterday`, `this-week` (weeks start on Monday)
+/// - `<n>d`: the last `n` days up to now, e.g. `7d`
+/// - an ISO date, e.g. `2024-01-31`: that whole day
+/// - `HH:MM`: from that time today up to now
+pub fn parse_time_range(
+    input: &str,
+    now: NaiveDateTime,
+) -> Result<(NaiveDateTime, NaiveDateTime), String> {
+    let input = input.trim();
+    let start_of_today = now.date().and_time(NaiveTime::MIN);
+
+    match input {
+        "today" => return Ok((start_of_today, now)),
+        "yesterday" => return Ok((start_of_today - Duration::days(1), start_of_today)),
+        "this-week" => {
+            let days_since_monday = now.weekday().num_days_from_monday() as i64;
+            return Ok((start_of_today - Duration::days(days_since_monday), now));
+        }
+        _ => {}
+    }
+
+    if let Some(days) = input.strip_suffix('d') {
+        if let Ok(days) = days.parse::<u32>() {
+            return Ok((now - Duration::days(days as i64), now));
+        }
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        let from = date.and_time(NaiveTime::MIN);
+        return Ok((from, from + Duration::days(1)));
+    }
+
+    if let Ok(time) = NaiveTime::parse_from_str(input, "%H:%M") {
+        let from = now.date().and_time(time);
+        if from > now {
+            return Err(format!("`{}` is later than now", input));
+        }
+        return Ok((from, now));
+    }
+
+    Err(format!(
+        "Invalid time range `{}`. Expected one of: today, yesterday, this-week, <n>d, YYYY-MM-DD, HH:MM",
+        input
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_skew_against_request_midpoint() {
+        let sent = now().and_utc();
+        let received = sent + Duration::seconds(2);
+
+        let ahead = clock_skew(sent, received, sent + Duration::seconds(121));
+        assert_eq!(ahead, Duration::seconds(120));
+        assert_eq!(
+            describe_clock_skew(ahead),
+            "Server clock is 2m ahead of this machine."
+        );
+
+        let behind = clock_skew(sent, received, sent - Duration::seconds(44));
+        assert_eq!(
+            describe_clock_skew(behind),
+            "Server clock is 45s behind this machine."
+        );
+        assert_eq!(
+            describe_clock_skew(clock_skew(sent, received, sent + Duration::seconds(1))),
+            "Server clock is in sync with this machine."
+        );
+    }
+
+    /// Wednesday 2024-01-31 15:30.
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(15, 30, 0)
+            .unwrap()
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_today() {
+        assert_eq!(
+            parse_time_range("today", now()),
+            Ok((at(2024, 1, 31, 0, 0), now()))
+        );
+    }
+
+    #[test]
+    fn test_parse_yesterday() {
+        assert_eq!(
+            parse_time_range("yesterday", now()),
+            Ok((at(2024, 1, 30, 0, 0), at(2024, 1, 31, 0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_parse_this_week() {
+        assert_eq!(
+            parse_time_range("this-week", now()),
+            Ok((at(2024, 1, 29, 0, 0), now()))
+        );
+    }
+
+    #[test]
+    fn test_parse_days() {
+        assert_eq!(
+            parse_time_range("7d", now()),
+            Ok((at(2024, 1, 24, 15, 30), now()))
+        );
+    }
+
+    #[test]
+    fn test_parse_iso_date() {
+        assert_eq!(
+            parse_time_range("2024-01-15", now()),
+            Ok((at(2024, 1, 15, 0, 0), at(2024, 1, 16, 0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_parse_time_of_day() {
+        assert_eq!(
+            parse_time_range("09:15", now()),
+            Ok((at(2024, 1, 31, 9, 15), now()))
+        );
+        assert!(parse_time_range("18:00", now()).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_time_range("last-month", now()).is_err());
+        assert!(parse_time_range("xd", now()).is_err());
+        assert!(parse_time_range("2024-13-01", now()).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("6h"), Ok(6 * 3600));
+        assert_eq!(parse_duration("90m"), Ok(90 * 60));
+        assert_eq!(parse_duration("1h30m"), Ok(5400));
+        assert!(parse_duration("0h").is_err());
+        assert!(parse_duration("soon").is_err());
+    }
+
+    #[test]
+    fn test_parse_user_key() {
+        assert_eq!(parse_user_key("user:alice:0001"), Some(("alice", 1)));
+        assert_eq!(parse_user_key("user:bob:0042"), Some(("bob", 42)));
+        // Sudo users have no task log to log in to.
+        for key in [
+            "alice",
+            "user:alice",
+            "user::0001",
+            "user:alice:x",
+            "sudo:alice:0001",
+        ] {
+            assert_eq!(parse_user_key(key), None, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn test_read_task_name() {
+        assert_eq!(
+            read_task_name(&mut "fix bug #42\n".as_bytes()),
+            Ok("fix bug #42".to_string())
+        );
+        assert_eq!(
+            read_task_name(&mut "fix bug #42\r\n".as_bytes()),
+            Ok("fix bug #42".to_string())
+        );
+        assert_eq!(read_task_name(&mut "".as_bytes()), Ok(String::new()));
+        assert!(read_task_name(&mut "fix\nbug\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_money() {
+        assert_eq!(parse_money("45"), Ok(4500));
+        assert_eq!(parse_money("45.5"), Ok(4550));
+        assert_eq!(parse_money("45.05"), Ok(4505));
+        assert!(parse_money("0").is_err());
+        assert!(parse_money("-5").is_err());
+        assert!(parse_money("-0.5").is_err());
+        assert!(parse_money("+45").is_err());
+        assert!(parse_money("4.505").is_err());
+        assert!(parse_money("lots").is_err());
+    }
+
+    #[test]
+    fn test_format_goal_progress() {
+        assert_eq!(
+            format_goal_progress(Seconds(3 * 3600 + 12 * 60), 6 * 3600),
+            "3h12m / 6h0m (53%)"
+        );
+    }
+
+    #[test]
+    fn test_format_log_footer() {
+        assert_eq!(
+            format_log_footer(10, 342, Seconds(58 * 3600 + 12 * 60)),
+            "showing 10 of 342 tasks, total 58h12m"
+        );
     }
 }
@@ -1,7 +1,7 @@
 use proc_macro2::TokenStream;
 use quote::quote;
 
-/// Implements `TryFrom` for an enum of payload.
+/// Implements `TryFrom` for an enum of payload, and `From` for the reverse direction.
 pub fn impl_try_from_for_payload(input: syn::DeriveInput) -> TokenStream {
     let name = input.ident;
     let implementations = match input.data {
@@ -10,12 +10,15 @@ pub fn impl_try_from_for_payload(input: syn::DeriveInput) -> TokenStream {
             .iter()
             .flat_map(|v| {
                 let variant_name = &v.ident;
-                println!("variant_name: {:?}", variant_name);
 
                 let fields = match v.fields {
                     syn::Fields::Unnamed(ref f) => &f.unnamed,
-                    // ignore other variants
-                    _ => return Vec::new(),
+                    syn::Fields::Named(ref f) if f.named.len() == 1 => &f.named,
+                    syn::Fields::Named(_) => {
+                        panic!("Only single-field named variants are supported")
+                    }
+                    // ignore unit variants
+                    syn::Fields::Unit => return Vec::new(),
                 };
 
                 fields
@@ -24,29 +27,40 @@ pub fn impl_try_from_for_payload(input: syn::DeriveInput) -> TokenStream {
                         let field_name;
                         let ft = &f.ty;
                         if let syn::Type::Path(ref p) = ft {
-                            println!("p: {:?}", p);
                             if let Some(ident) = p.path.get_ident() {
                                 field_name = ident;
-                                println!("ident: {:?}", ident);
                             } else {
                                 panic!("Only named fields are supported");
                             }
                         } else {
                             panic!("Only named fields are supported");
                         }
+                        // Same tokens serve as the match pattern and the constructor.
+                        let variant = match f.ident {
+                            Some(ref field_ident) => quote! {
+                                #name::#variant_name { #field_ident: payload }
+                            },
+                            None => quote! { #name::#variant_name(payload) },
+                        };
                         quote! {
                             impl std::convert::TryFrom<#name> for #field_name {
                                 type Error = RuntimeError;
 
                                 fn try_from(payload: #name) -> Result<Self, Self::Error> {
                                     match payload {
-                                        #name::#variant_name(payload) => Ok(payload),
+                                        #variant => Ok(payload),
                                         _ => Err(RuntimeError::UnprocessableEntity {
                                             name: "payload".to_string(),
                                         }),
                                     }
                                 }
                             }
+
+                            impl std::convert::From<#field_name> for #name {
+                                fn from(payload: #field_name) -> Self {
+                                    #variant
+                                }
+                            }
                         }
                     })
                     .collect::<Vec<proc_macro2::TokenStream>>()
@@ -101,6 +115,55 @@ fn test_should_generate_impl_try_from() {
                 }
             }
         }
+
+        impl std::convert::From<RegisterRecordPayload> for SudoUserRpcEventPayload {
+            fn from(payload: RegisterRecordPayload) -> Self {
+                SudoUserRpcEventPayload::RegisterRecord(payload)
+            }
+        }
+    };
+    assert_eq!(output.to_string(), expected.to_string());
+}
+
+#[test]
+fn test_should_generate_impl_try_from_for_named_field() {
+    let input = syn::parse_quote! {
+        enum UserRpcEventPayload {
+            AddTask { payload: StoreTaskPayload },
+            GetAllRecord,
+        }
+    };
+    let output = impl_try_from_for_payload(input);
+    let expected = quote! {
+        impl std::convert::TryFrom<UserRpcEventPayload> for StoreTaskPayload {
+            type Error = RuntimeError;
+
+            fn try_from(payload: UserRpcEventPayload) -> Result<Self, Self::Error> {
+                match payload {
+                    UserRpcEventPayload::AddTask { payload: payload } => Ok(payload),
+                    _ => Err(RuntimeError::UnprocessableEntity {
+                        name: "payload".to_string(),
+                    }),
+                }
+            }
+        }
+
+        impl std::convert::From<StoreTaskPayload> for UserRpcEventPayload {
+            fn from(payload: StoreTaskPayload) -> Self {
+                UserRpcEventPayload::AddTask { payload: payload }
+            }
+        }
     };
     assert_eq!(output.to_string(), expected.to_string());
 }
+
+#[test]
+#[should_panic(expected = "Only single-field named variants are supported")]
+fn test_should_reject_multi_field_named_variant() {
+    let input = syn::parse_quote! {
+        enum UserRpcEventPayload {
+            AddTask { key: String, task: Task },
+        }
+    };
+    impl_try_from_for_payload(input);
+}
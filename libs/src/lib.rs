@@ -3,8 +3,13 @@ use strum_macros::Display;
 
 pub mod payload;
 pub mod record;
+pub mod response;
+pub mod stats;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Header carrying the admin secret on admin-only endpoints.
+pub const ADMIN_SECRET_HEADER: &str = "x-imon-admin-secret";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OperatingInfo {
     pub latest_record_id: i32,
     pub latest_sudo_record_id: i32,
@@ -37,10 +42,30 @@ pub enum OperatingRedisKey {
 pub enum UserRecordRedisJsonPath {
     #[strum(serialize = "$")]
     Root,
+    #[strum(serialize = "$.id")]
+    Id,
+    #[strum(serialize = "$.user_name")]
+    UserName,
     #[strum(serialize = "$.task_history")]
     TaskHistory,
     #[strum(serialize = "$.current_task")]
     CurrentTask,
+    #[strum(serialize = "$.last_active_at")]
+    LastActiveAt,
+    #[strum(serialize = "$.daily_goal_seconds")]
+    DailyGoalSeconds,
+    #[strum(serialize = "$.hourly_rate")]
+    HourlyRate,
+    #[strum(serialize = "$.webhook_url")]
+    WebhookUrl,
+    #[strum(serialize = "$.timezone")]
+    Timezone,
+    #[strum(serialize = "$.schema_version")]
+    SchemaVersion,
+    #[strum(serialize = "$.current_task.state")]
+    CurrentTaskState,
+    #[strum(serialize = "$.task_history[?(@.state==\"End\")].begin_time")]
+    EndTaskBeginTimes,
 }
 
 #[derive(Debug, Display)]
@@ -51,10 +76,12 @@ pub enum SudoUserRecordRedisJsonPath {
     PublishedTasks,
 }
 
-#[derive(Debug, Display)]
+#[derive(Serialize, Deserialize, Debug, Display)]
+#[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub enum UserType {
     User,
+    #[serde(rename = "sudo")]
     #[strum(serialize = "sudo")]
     SudoUser,
 }
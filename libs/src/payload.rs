@@ -1,41 +1,518 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
-use crate::record::{Task, TaskState};
+use crate::{
+    record::{HourlyRate, Task, TaskState, UserRecord},
+    UserType,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct StoreTaskPayload {
     pub key: String,
     pub task: Task,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct RegisterRecordPayload {
     pub user_name: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct RegisterRecordsBulkPayload {
+    pub user_names: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct ResetRecordPayload {
     pub key: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct DeleteRecordPayload {
+    pub key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SetGoalPayload {
+    pub key: String,
+    /// `None` clears the goal.
+    pub daily_goal_seconds: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SetRatePayload {
+    pub key: String,
+    /// `None` clears the rate.
+    pub hourly_rate: Option<HourlyRate>,
+}
+
+/// Body of `POST /v1/record/settings`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct UpdateSettingsPayload {
+    pub key: String,
+    pub settings: RecordSettings,
+}
+
+/// Per-user settings to change. A field left out keeps its value, `null`
+/// clears it, so this is also the merge patch applied to the record.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct RecordSettings {
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub webhook_url: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub daily_goal_seconds: Option<Option<i64>>,
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub hourly_rate: Option<Option<HourlyRate>>,
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timezone: Option<Option<String>>,
+}
+
+impl RecordSettings {
+    pub fn is_empty(&self) -> bool {
+        self.webhook_url.is_none()
+            && self.daily_goal_seconds.is_none()
+            && self.hourly_rate.is_none()
+            && self.timezone.is_none()
+    }
+}
+
+/// Tell a field sent as `null` from one left out, which `default` leaves `None`.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct GetSingleRecordPayload {
     pub key: String,
+    /// When `false`, the record comes back with an empty `task_history`.
+    #[serde(default = "default_include_history")]
+    pub include_history: bool,
+}
+
+fn default_include_history() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct RenameTasksPayload {
+    pub key: String,
+    pub from_name: String,
+    pub to_name: String,
+}
+
+/// Query string naming a record, for routes without a JSON body.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct RecordKeyQuery {
+    pub key: String,
+}
+
+/// Query string of `POST /v1/task/import/csv`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct ImportQuery {
+    pub key: String,
+    /// Import tasks overlapping logged ones, for intentional parallel tracking.
+    #[serde(default)]
+    pub allow_overlap: bool,
+}
+
+/// Query string of `GET /v1/record/all`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct ListRecordsQuery {
+    /// When `false`, records come back with an empty `task_history`.
+    #[serde(default)]
+    pub include_history: bool,
+    /// Only list users whose name contains this, ignoring case.
+    #[serde(default)]
+    pub query: Option<String>,
+}
+
+/// Query string selecting a page of a list response.
+/// Without a `limit`, the rest of the list from `offset` is one page.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct PageQuery {
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+/// Where a page sits in its list, returned as `page` next to the `data`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct PageInfo {
+    /// Items in the whole list.
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+    /// The `offset` of the next page, `None` on the last one, or when the
+    /// page is empty so following it would never get further.
+    pub next_cursor: Option<usize>,
+}
+
+impl PageQuery {
+    /// Cut the page out of `items`, the whole list in a stable order.
+    pub fn paginate<T>(&self, items: Vec<T>) -> (Vec<T>, PageInfo) {
+        let total = items.len();
+        let offset = self.offset.min(total);
+        let limit = self.limit.unwrap_or(total - offset);
+        let page: Vec<T> = items.into_iter().skip(offset).take(limit).collect();
+        let end = offset + page.len();
+        let info = PageInfo {
+            total,
+            limit,
+            offset,
+            next_cursor: (end > offset && end < total).then_some(end),
+        };
+        (page, info)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct GetByIdPayload {
+    pub id: i32,
+    pub user_type: UserType,
+}
+
+/// Which records a listing of all records returns.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordFilter {
+    #[default]
+    All,
+    /// Users whose current task is being worked on.
+    ActiveOnly,
+    /// Users who wrote a task at or after the given time.
+    UpdatedSince(NaiveDateTime),
+}
+
+impl RecordFilter {
+    pub fn matches(&self, record: &UserRecord) -> bool {
+        match self {
+            RecordFilter::All => true,
+            RecordFilter::ActiveOnly => record.current_task.state.is_active(),
+            RecordFilter::UpdatedSince(since) => {
+                record.last_active_at.is_some_and(|at| at >= *since)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct GetAllRecordPayload {
+    #[serde(default)]
+    pub filter: RecordFilter,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct UpdateTaskPayload {
     pub key: String,
     pub state: TaskState,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct GetTaskPayload {
+    pub key: String,
+    pub task_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SplitTaskPayload {
+    pub key: String,
+    /// Id of the task to split, preferred over `task_index`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<i32>,
+    /// Position in the history as `/v1/record` lists it, newest first.
+    /// Shifts when the history changes, use `task_id` where possible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_index: Option<usize>,
+    pub at_time: NaiveDateTime,
+    pub second_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Day,
+    /// ISO week, e.g. `2024-W05`.
+    Week,
+    Task,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct GroupedStatsPayload {
+    pub key: String,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub group_by: GroupBy,
+    /// Only count billable (`true`) or non-billable (`false`) tasks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub billable: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct STaskIn {
     pub name: String,
     pub description: String,
 }
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct StoreSTaskPayload {
     pub key: String,
     pub task: STaskIn,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct UpdateSTaskPayload {
+    pub key: String,
+    pub id: i32,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::SCHEMA_VERSION;
+
+    #[test]
+    fn test_record_settings_keep_left_out_fields() {
+        let settings: RecordSettings = serde_json::from_str(
+            r#"{"daily_goal_seconds": 3600, "webhook_url": null, "hourly_rate": {"cents": 5000, "currency": "EUR"}}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.daily_goal_seconds, Some(Some(3600)));
+        assert_eq!(settings.webhook_url, Some(None));
+        assert!(settings.timezone.is_none());
+        assert!(!settings.is_empty());
+
+        // The merge patch has the cleared field as `null` and no left-out one.
+        assert_eq!(
+            serde_json::to_value(&settings).unwrap(),
+            serde_json::json!({
+                "daily_goal_seconds": 3600,
+                "webhook_url": null,
+                "hourly_rate": {"cents": 5000, "currency": "EUR"},
+            })
+        );
+        assert!(serde_json::from_str::<RecordSettings>("{}")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_get_single_record_includes_history_by_default() {
+        let payload: GetSingleRecordPayload =
+            serde_json::from_str(r#"{"key": "user:alice:0001"}"#).unwrap();
+        assert!(payload.include_history);
+
+        let payload: GetSingleRecordPayload =
+            serde_json::from_str(r#"{"key": "user:alice:0001", "include_history": false}"#)
+                .unwrap();
+        assert!(!payload.include_history);
+    }
+
+    #[test]
+    fn test_list_records_leaves_history_out_by_default() {
+        let query: ListRecordsQuery = serde_json::from_str("{}").unwrap();
+        assert!(!query.include_history);
+
+        let query: ListRecordsQuery = serde_json::from_str(r#"{"include_history": true}"#).unwrap();
+        assert!(query.include_history);
+    }
+
+    #[test]
+    fn test_paginate_across_pages() {
+        let items: Vec<i32> = (0..5).collect();
+        let page = |limit, offset| PageQuery { limit, offset };
+
+        assert_eq!(
+            page(Some(2), 0).paginate(items.clone()),
+            (
+                vec![0, 1],
+                PageInfo {
+                    total: 5,
+                    limit: 2,
+                    offset: 0,
+                    next_cursor: Some(2),
+                }
+            )
+        );
+        assert_eq!(
+            page(Some(2), 2).paginate(items.clone()).1.next_cursor,
+            Some(4)
+        );
+        assert_eq!(
+            page(Some(2), 4).paginate(items.clone()),
+            (
+                vec![4],
+                PageInfo {
+                    total: 5,
+                    limit: 2,
+                    offset: 4,
+                    next_cursor: None,
+                }
+            )
+        );
+        // Past the end is an empty last page.
+        assert_eq!(
+            page(Some(2), 9).paginate(items.clone()).0,
+            Vec::<i32>::new()
+        );
+        assert_eq!(page(Some(2), 9).paginate(items.clone()).1.offset, 5);
+    }
+
+    #[test]
+    fn test_paginate_ends_at_empty_page() {
+        let (page, info) = PageQuery {
+            limit: Some(0),
+            offset: 1,
+        }
+        .paginate(vec!["a", "b", "c"]);
+
+        assert!(page.is_empty());
+        assert_eq!(info.next_cursor, None);
+    }
+
+    #[test]
+    fn test_page_query_defaults_to_whole_list() {
+        let query: PageQuery = serde_json::from_str("{}").unwrap();
+        let (page, info) = query.paginate(vec!["a", "b", "c"]);
+
+        assert_eq!(page, vec!["a", "b", "c"]);
+        assert_eq!(
+            info,
+            PageInfo {
+                total: 3,
+                limit: 3,
+                offset: 0,
+                next_cursor: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_payload_field_names_are_pinned() {
+        let payload = SplitTaskPayload {
+            key: "user:alice:0001".to_string(),
+            task_id: Some(3),
+            task_index: Some(0),
+            at_time: chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(10, 0, 0)
+                .unwrap(),
+            second_name: "review".to_string(),
+        };
+
+        let json = serde_json::json!(payload);
+        let mut names: Vec<&String> = json.as_object().unwrap().keys().collect();
+        names.sort();
+
+        assert_eq!(
+            names,
+            vec!["at_time", "key", "second_name", "task_id", "task_index"]
+        );
+    }
+
+    #[test]
+    fn test_record_filters() {
+        let at = |h: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let record = |state: TaskState, last_active_at: Option<NaiveDateTime>| UserRecord {
+            id: 1,
+            user_name: "alice".to_string(),
+            task_history: vec![],
+            current_task: Task::placeholder("writing", state),
+            daily_goal_seconds: None,
+            last_active_at,
+            hourly_rate: None,
+            webhook_url: None,
+            timezone: None,
+            schema_version: SCHEMA_VERSION,
+        };
+        let working = record(TaskState::Break, Some(at(9)));
+        let done = record(TaskState::End, Some(at(12)));
+        let never = record(TaskState::Placeholder, None);
+
+        let kept = |filter: RecordFilter| -> Vec<bool> {
+            [&working, &done, &never]
+                .iter()
+                .map(|r| filter.matches(r))
+                .collect()
+        };
+        assert_eq!(kept(RecordFilter::All), vec![true, true, true]);
+        assert_eq!(kept(RecordFilter::ActiveOnly), vec![true, false, false]);
+        assert_eq!(
+            kept(RecordFilter::UpdatedSince(at(10))),
+            vec![false, true, false]
+        );
+    }
+
+    #[test]
+    fn test_record_filter_wire_format() {
+        let payload: GetAllRecordPayload = serde_json::from_str("{}").unwrap();
+        assert_eq!(payload.filter, RecordFilter::All);
+
+        let payload: GetAllRecordPayload =
+            serde_json::from_str(r#"{"filter": "active_only"}"#).unwrap();
+        assert_eq!(payload.filter, RecordFilter::ActiveOnly);
+
+        let payload: GetAllRecordPayload =
+            serde_json::from_str(r#"{"filter": {"updated_since": "2024-01-31T10:00:00"}}"#)
+                .unwrap();
+        assert!(matches!(payload.filter, RecordFilter::UpdatedSince(_)));
+    }
+
+    #[test]
+    fn test_user_type_matches_key_scheme() {
+        let payload: GetByIdPayload =
+            serde_json::from_str(r#"{"id": 2, "user_type": "sudo"}"#).unwrap();
+        assert!(matches!(payload.user_type, UserType::SudoUser));
+        assert_eq!(payload.user_type.to_string(), "sudo");
+    }
+}
@@ -2,6 +2,8 @@ use chrono::NaiveDateTime;
 use redis::FromRedisValue;
 use serde::{Deserialize, Serialize};
 
+use crate::stats::finished_in_range;
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub enum TaskState {
     Begin,
@@ -11,23 +13,214 @@ pub enum TaskState {
     Placeholder,
 }
 
+impl TaskState {
+    /// Whether a task in this state is still being worked on.
+    pub fn is_active(&self) -> bool {
+        matches!(self, TaskState::Begin | TaskState::Break | TaskState::Back)
+    }
+
+    /// Whether no further transition but starting a new task is possible.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, TaskState::End | TaskState::Placeholder)
+    }
+
+    /// Whether a task in this state may move to `next`.
+    ///
+    /// Only a new task can follow a terminal state, and `Placeholder` is
+    /// never a target.
+    pub fn can_transition_to(&self, next: &TaskState) -> bool {
+        use TaskState::*;
+        matches!(
+            (self, next),
+            (End | Placeholder, Begin)
+                | (Begin | Back, Break)
+                | (Break, Back)
+                | (Begin | Break | Back, End)
+        )
+    }
+}
+
+/// A number of seconds, sent and stored as a plain integer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Seconds(pub i64);
+
+impl Seconds {
+    /// Seconds from `from` to `to`, negative if `to` is earlier.
+    pub fn between(from: NaiveDateTime, to: NaiveDateTime) -> Self {
+        Seconds((to - from).num_seconds())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl std::ops::Add for Seconds {
+    type Output = Seconds;
+
+    fn add(self, other: Seconds) -> Seconds {
+        Seconds(self.0 + other.0)
+    }
+}
+
+impl std::ops::Sub for Seconds {
+    type Output = Seconds;
+
+    fn sub(self, other: Seconds) -> Seconds {
+        Seconds(self.0 - other.0)
+    }
+}
+
+impl std::ops::AddAssign for Seconds {
+    fn add_assign(&mut self, other: Seconds) {
+        self.0 += other.0;
+    }
+}
+
+impl std::ops::SubAssign for Seconds {
+    fn sub_assign(&mut self, other: Seconds) {
+        self.0 -= other.0;
+    }
+}
+
+impl std::iter::Sum for Seconds {
+    fn sum<I: Iterator<Item = Seconds>>(iter: I) -> Seconds {
+        Seconds(iter.map(|seconds| seconds.0).sum())
+    }
+}
+
+/// e.g. `2h30m`, `12m` or `45s`, padded to the requested width.
+impl std::fmt::Display for Seconds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (hours, minutes) = (self.0 / 3600, self.0 % 3600 / 60);
+        let text = match (hours, minutes) {
+            (0, 0) => format!("{}s", self.0),
+            (0, _) => format!("{}m", minutes),
+            _ => format!("{}h{}m", hours, minutes),
+        };
+        f.pad(&text)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
 pub struct Task {
+    /// Unique within the owning record and kept across state changes.
+    /// Assigned by the service when the task enters the history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
     pub name: String,
     pub state: TaskState,
+    #[serde(with = "timestamp")]
     pub begin_time: NaiveDateTime,
+    #[serde(with = "timestamp")]
     pub end_time: NaiveDateTime,
-    pub duration: i64,
+    /// Focused seconds, breaks excluded. Only up to date once the task
+    /// is on `Break` or `End`, see `worked_seconds`.
+    #[serde(default, skip_serializing_if = "Seconds::is_zero")]
+    pub duration: Seconds,
+    /// Name of the task this one is a subtask of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Seconds spent on breaks, up to the latest `Back`.
+    #[serde(
+        default,
+        alias = "break_seconds",
+        skip_serializing_if = "Seconds::is_zero"
+    )]
+    pub total_break_seconds: Seconds,
+    /// Time that can be invoiced. Tasks recorded before this existed aren't.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub billable: bool,
+    /// Every transition so far, in order. Empty for tasks recorded before
+    /// transitions were kept.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<TaskEvent>,
+}
+
+/// A task entering `state`, see `Task::events`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct TaskEvent {
+    pub state: TaskState,
+    #[serde(with = "timestamp")]
+    pub at: NaiveDateTime,
+}
+
+/// Task timestamps on the wire, in the one format chrono writes them, e.g.
+/// `2024-01-31T09:00:00` or `2024-01-31T09:00:00.25`. Anything else chrono
+/// would also read is refused, so histories sort and compare consistently.
+pub mod timestamp {
+    use chrono::NaiveDateTime;
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Start of the message of a refused timestamp.
+    const INVALID: &str = "invalid timestamp";
+
+    pub fn parse(raw: &str) -> Result<NaiveDateTime, String> {
+        let invalid = || format!("{} `{}`, expected e.g. 2024-01-31T09:00:00", INVALID, raw);
+        if !raw.is_ascii() {
+            return Err(invalid());
+        }
+        let (seconds, fraction) = raw.split_at(raw.len().min(19));
+        let is_pinned = seconds.len() == 19
+            && seconds.char_indices().all(|(i, c)| match i {
+                4 | 7 => c == '-',
+                10 => c == 'T',
+                13 | 16 => c == ':',
+                _ => c.is_ascii_digit(),
+            })
+            && match fraction.strip_prefix('.') {
+                Some(digits) => {
+                    (1..=9).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit())
+                }
+                None => fraction.is_empty(),
+            };
+        if !is_pinned {
+            return Err(invalid());
+        }
+        NaiveDateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S%.f").map_err(|_| invalid())
+    }
+
+    pub fn serialize<S: Serializer>(at: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+        at.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        parse(&raw).map_err(de::Error::custom)
+    }
+}
+
+/// Trim surrounding whitespace and, with `lowercase`, fold the case, so
+/// `" Writing "` and `"writing"` end up as the same task in stats.
+pub fn normalize_task_name(name: &str, lowercase: bool) -> String {
+    match lowercase {
+        true => name.trim().to_lowercase(),
+        false => name.trim().to_string(),
+    }
+}
+
+fn is_false(flag: &bool) -> bool {
+    !*flag
 }
 
 impl Default for Task {
     fn default() -> Self {
         Task {
+            id: None,
             name: String::new(),
             state: TaskState::Placeholder,
             begin_time: chrono::offset::Local::now().naive_local(),
             end_time: chrono::offset::Local::now().naive_local(),
-            duration: 0,
+            duration: Seconds(0),
+            parent: None,
+            total_break_seconds: Seconds(0),
+            billable: false,
+            events: vec![],
         }
     }
 }
@@ -45,80 +238,506 @@ impl Task {
         self.state == TaskState::Placeholder
     }
 
+    /// Begin a task named `name`, with surrounding whitespace trimmed.
     pub fn generate_begin_task(name: String) -> Self {
+        Task::begin_at(name, chrono::offset::Local::now().naive_local())
+    }
+
+    /// `generate_begin_task` at `at` rather than now. The `*_at` transitions
+    /// take the time they happen at, so a sequence can be replayed.
+    pub fn begin_at(name: String, at: NaiveDateTime) -> Self {
         Task {
-            name,
+            id: None,
+            name: normalize_task_name(&name, false),
             state: TaskState::Begin,
-            ..Task::default()
+            begin_time: at,
+            end_time: at,
+            duration: Seconds(0),
+            parent: None,
+            total_break_seconds: Seconds(0),
+            billable: false,
+            events: vec![TaskEvent {
+                state: TaskState::Begin,
+                at,
+            }],
         }
     }
 
-    pub fn generate_break_task(latest_task: &Task) -> Self {
-        let duration = Task::calculate_duration(latest_task);
-        Task {
-            name: latest_task.name.clone(),
-            state: TaskState::Break,
-            duration,
-            end_time: chrono::offset::Local::now().naive_local(),
-            ..*latest_task
+    /// Begin a subtask of `parent`. Returns `None` if `name` is its own parent.
+    pub fn generate_begin_subtask(name: String, parent: String) -> Option<Self> {
+        let parent = normalize_task_name(&parent, false);
+        let task = Task::generate_begin_task(name);
+        if task.name == parent {
+            return None;
         }
+        Some(Task {
+            parent: Some(parent),
+            ..task
+        })
+    }
+
+    pub fn is_self_parented(&self) -> bool {
+        self.parent.as_deref() == Some(self.name.as_str())
+    }
+
+    pub fn generate_break_task(latest_task: &Task) -> Self {
+        latest_task.break_at(chrono::offset::Local::now().naive_local())
     }
 
     pub fn generate_back_task(latest_task: &Task) -> Self {
+        latest_task.back_at(chrono::offset::Local::now().naive_local())
+    }
+
+    pub fn generate_done_task(latest_task: &Task) -> Self {
+        latest_task.done_at(chrono::offset::Local::now().naive_local())
+    }
+
+    /// This task entering `state` at `at`, with the timing fields, whose
+    /// meaning depends on the state, given by the caller. The rest carries over.
+    fn transition(
+        &self,
+        state: TaskState,
+        at: NaiveDateTime,
+        end_time: NaiveDateTime,
+        duration: Seconds,
+        total_break_seconds: Seconds,
+    ) -> Task {
         Task {
-            name: latest_task.name.clone(),
-            state: TaskState::Back,
-            begin_time: Task::default().begin_time,
-            ..*latest_task
+            id: self.id,
+            name: self.name.clone(),
+            state: state.clone(),
+            begin_time: self.begin_time,
+            end_time,
+            duration,
+            parent: self.parent.clone(),
+            total_break_seconds,
+            billable: self.billable,
+            events: self.events_with(state, at),
         }
     }
 
-    pub fn generate_done_task(latest_task: &Task) -> Self {
-        if latest_task.state == TaskState::Break {
-            Task {
-                name: latest_task.name.clone(),
-                state: TaskState::End,
-                ..*latest_task
-            }
-        } else if latest_task.state == TaskState::Back {
-            let duration = Task::calculate_duration(latest_task) + latest_task.duration;
-            Task {
-                name: latest_task.name.clone(),
-                state: TaskState::End,
-                duration,
-                begin_time: latest_task.begin_time,
-                ..Task::default()
+    /// On a break from `at`: `end_time` is when the break started and
+    /// `duration` all the work up to it.
+    pub fn break_at(&self, at: NaiveDateTime) -> Task {
+        self.transition(
+            TaskState::Break,
+            at,
+            at,
+            self.worked_seconds_at(at),
+            self.total_break_seconds,
+        )
+    }
+
+    /// Back to work at `at`: `end_time` is when this work segment started,
+    /// `duration` the work before it, and the break is added to the breaks.
+    /// `worked_seconds` goes on from `begin_time` minus those.
+    pub fn back_at(&self, at: NaiveDateTime) -> Task {
+        // A `Break` task's `end_time` is when the break started.
+        let break_seconds = Seconds::between(self.end_time, at).max(Seconds(0));
+        self.transition(
+            TaskState::Back,
+            at,
+            at,
+            self.duration,
+            self.total_break_seconds + break_seconds,
+        )
+    }
+
+    /// Done at `at`: `end_time` is when the work stopped and `duration` all
+    /// of it. Ending during a break, the work stopped when the break started
+    /// and that break isn't counted in either.
+    pub fn done_at(&self, at: NaiveDateTime) -> Task {
+        let (end_time, duration) = match self.state {
+            TaskState::Break => (self.end_time, self.duration),
+            _ => (at, self.worked_seconds_at(at)),
+        };
+        self.transition(
+            TaskState::End,
+            at,
+            end_time,
+            duration,
+            self.total_break_seconds,
+        )
+    }
+
+    /// `events` with the task entering `state` at `at` added.
+    fn events_with(&self, state: TaskState, at: NaiveDateTime) -> Vec<TaskEvent> {
+        let mut events = self.events.clone();
+        events.push(TaskEvent { state, at });
+        events
+    }
+
+    /// Seconds worked and on break according to `events`, counted up to
+    /// `until` while the task is still going. `None` for tasks recorded
+    /// before transitions were kept.
+    pub fn seconds_from_events(&self, until: NaiveDateTime) -> Option<(Seconds, Seconds)> {
+        if self.events.is_empty() {
+            return None;
+        }
+        let (mut worked, mut breaks) = (Seconds(0), Seconds(0));
+        let ends = self.events.iter().skip(1).map(|e| e.at).chain([until]);
+        for (event, end) in self.events.iter().zip(ends) {
+            let seconds = Seconds::between(event.at, end).max(Seconds(0));
+            match event.state {
+                TaskState::Begin | TaskState::Back => worked += seconds,
+                TaskState::Break => breaks += seconds,
+                TaskState::End | TaskState::Placeholder => break,
             }
-        } else {
-            let duration = Task::calculate_duration(latest_task);
-            Task {
-                name: latest_task.name.clone(),
-                state: TaskState::End,
-                duration,
-                ..Task::default()
+        }
+        Some((worked, breaks))
+    }
+
+    /// Split an `End` task at `at` into two consecutive `End` tasks.
+    ///
+    /// The first one keeps the original name and id, the second one is named
+    /// `second_name` and has no id yet. Work and breaks go to the half they
+    /// happened in, see `split_seconds`. Returns `None` unless `at` is
+    /// strictly inside the task.
+    pub fn split_at(&self, at: NaiveDateTime, second_name: String) -> Option<(Task, Task)> {
+        if self.state != TaskState::End || at <= self.begin_time || at >= self.end_time {
+            return None;
+        }
+
+        let mut first = Task {
+            id: self.id,
+            name: self.name.clone(),
+            state: TaskState::End,
+            begin_time: self.begin_time,
+            end_time: at,
+            duration: Seconds(0),
+            parent: self.parent.clone(),
+            total_break_seconds: Seconds(0),
+            billable: self.billable,
+            events: self.events_before(at, TaskState::End),
+        };
+        let mut second = Task {
+            id: None,
+            name: second_name,
+            state: TaskState::End,
+            begin_time: at,
+            end_time: self.end_time,
+            duration: Seconds(0),
+            parent: self.parent.clone(),
+            total_break_seconds: Seconds(0),
+            billable: self.billable,
+            events: self.events_from(at),
+        };
+        let (first_seconds, second_seconds) = match (
+            first.seconds_from_events(first.end_time),
+            second.seconds_from_events(second.end_time),
+        ) {
+            (Some(first_seconds), Some(second_seconds)) => (first_seconds, second_seconds),
+            _ => self.split_seconds(at),
+        };
+        (first.duration, first.total_break_seconds) = first_seconds;
+        (second.duration, second.total_break_seconds) = second_seconds;
+
+        Some((first, second))
+    }
+
+    /// `duration` and `total_break_seconds` divided at `at` in proportion
+    /// to the time on either side, for tasks recorded without events, where
+    /// it isn't known when the breaks were taken.
+    fn split_seconds(&self, at: NaiveDateTime) -> ((Seconds, Seconds), (Seconds, Seconds)) {
+        let span = Seconds::between(self.begin_time, self.end_time).0.max(1);
+        let before = Seconds::between(self.begin_time, at).0;
+        let share = |total: Seconds| Seconds(total.0 * before / span);
+        let (worked, breaks) = (share(self.duration), share(self.total_break_seconds));
+        (
+            (worked, breaks),
+            (self.duration - worked, self.total_break_seconds - breaks),
+        )
+    }
+
+    /// The events before `at`, ending with the task entering `state` then.
+    fn events_before(&self, at: NaiveDateTime, state: TaskState) -> Vec<TaskEvent> {
+        if self.events.is_empty() {
+            return vec![];
+        }
+        let mut events: Vec<TaskEvent> =
+            self.events.iter().filter(|e| e.at < at).cloned().collect();
+        events.push(TaskEvent { state, at });
+        events
+    }
+
+    /// The events after `at`, starting with the task in the state it was
+    /// in then: on a break if `at` falls inside one, otherwise beginning.
+    fn events_from(&self, at: NaiveDateTime) -> Vec<TaskEvent> {
+        if self.events.is_empty() {
+            return vec![];
+        }
+        let state = match self.events.iter().rev().find(|e| e.at <= at) {
+            Some(TaskEvent {
+                state: TaskState::Break,
+                ..
+            }) => TaskState::Break,
+            _ => TaskState::Begin,
+        };
+        std::iter::once(TaskEvent { state, at })
+            .chain(self.events.iter().filter(|e| e.at > at).cloned())
+            .collect()
+    }
+
+    /// Seconds worked on this task so far, breaks excluded.
+    ///
+    /// While working, that is the wall-clock time since `begin_time` minus
+    /// the breaks taken; otherwise it is the stored `duration`.
+    pub fn worked_seconds(&self) -> Seconds {
+        self.worked_seconds_at(chrono::offset::Local::now().naive_local())
+    }
+
+    /// `worked_seconds` as of `at`.
+    pub fn worked_seconds_at(&self, at: NaiveDateTime) -> Seconds {
+        match self.state {
+            TaskState::Begin | TaskState::Back => {
+                self.calculate_duration(at) - self.total_break_seconds
             }
+            _ => self.duration,
+        }
+    }
+
+    /// Rename this task, or its parent, from `from` to `to`.
+    /// Returns whether the task itself was renamed.
+    fn rename(&mut self, from: &str, to: &str) -> bool {
+        if self.parent.as_deref() == Some(from) {
+            self.parent = Some(to.to_string());
+        }
+        if self.name != from {
+            return false;
+        }
+        self.name = to.to_string();
+        true
+    }
+
+    /// When the current focus streak started: the task's begin, or the end
+    /// of its latest break. `None` unless the task is being worked on.
+    pub fn focused_since(&self) -> Option<NaiveDateTime> {
+        match self.state {
+            TaskState::Begin => Some(self.begin_time),
+            // `duration` is the work done before the latest break, so that
+            // break started `duration` plus the earlier breaks after the begin.
+            TaskState::Back => Some(
+                self.begin_time
+                    + chrono::Duration::seconds((self.duration + self.total_break_seconds).0),
+            ),
+            _ => None,
         }
     }
 
-    fn calculate_duration(&self) -> i64 {
-        let duration = chrono::offset::Local::now().naive_local() - self.begin_time;
-        duration.num_seconds()
+    /// Seconds from `begin_time` to `until`, breaks included.
+    fn calculate_duration(&self, until: NaiveDateTime) -> Seconds {
+        Seconds::between(self.begin_time, until)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
 pub struct UserRecord {
     pub id: i32,
     pub user_name: String,
     pub task_history: Vec<Task>,
     pub current_task: Task,
+    /// Seconds the user aims to work per day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_goal_seconds: Option<i64>,
+    /// When a task was last written for this user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_active_at: Option<NaiveDateTime>,
+    /// What the user's billable time is worth.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hourly_rate: Option<HourlyRate>,
+    /// Where to notify about the user's task changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    /// IANA time zone name, e.g. `Europe/Berlin`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Shape the record was stored in, see `SCHEMA_VERSION`. Records from
+    /// before versioning have none and are version 0.
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+/// Version of the `UserRecord` shape written by this build. Older records
+/// are upgraded by the service when it reads them.
+///
+/// - 1: every task in the history and the current task have an `id`.
+/// - 2: breaks are stored as `total_break_seconds`, not `break_seconds`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// What an hour of billable work is worth, in minor units of `currency`,
+/// e.g. cents. The currency is only a label, nothing is ever converted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub struct HourlyRate {
+    pub cents: i64,
+    pub currency: String,
+}
+
+impl HourlyRate {
+    /// What `seconds` of billable work earn, rounded to the nearest minor unit.
+    pub fn earnings(&self, seconds: Seconds) -> Earnings {
+        Earnings {
+            cents: (seconds.0 * self.cents + 1800) / 3600,
+            currency: self.currency.clone(),
+        }
+    }
+}
+
+/// An amount in minor units of `currency`, see `HourlyRate`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Earnings {
+    pub cents: i64,
+    pub currency: String,
+}
+
+impl std::fmt::Display for Earnings {
+    /// E.g. `1234.50 EUR`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{:02} {}",
+            self.cents / 100,
+            self.cents % 100,
+            self.currency
+        )
+    }
+}
+
+impl UserRecord {
+    /// The record a newly registered user starts with.
+    pub fn new(id: i32, user_name: String) -> Self {
+        UserRecord {
+            id,
+            user_name,
+            task_history: vec![],
+            current_task: Task::placeholder("initialised", TaskState::Placeholder),
+            daily_goal_seconds: None,
+            last_active_at: None,
+            hourly_rate: None,
+            webhook_url: None,
+            timezone: None,
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+
+    /// Clear the task history and current task, keeping everything else
+    /// (id, name and per-user settings such as the daily goal).
+    pub fn into_reset(self) -> Self {
+        UserRecord {
+            task_history: vec![],
+            current_task: Task::placeholder("reset", TaskState::Placeholder),
+            ..self
+        }
+    }
+
+    /// The task with id `task_id`. An active current task wins over its
+    /// history entry, which may lag behind it. Once finished, the history
+    /// entry is the one edits such as splits keep up to date.
+    pub fn task_by_id(&self, task_id: i32) -> Option<&Task> {
+        let active = Some(&self.current_task).filter(|t| t.state.is_active());
+        active
+            .into_iter()
+            .chain(self.task_history.iter().rev())
+            .find(|t| t.id == Some(task_id))
+    }
+
+    /// Id for the next task entering the history.
+    pub fn next_task_id(&self) -> i32 {
+        self.task_history
+            .iter()
+            .filter_map(|t| t.id)
+            .map(|id| id + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Focused seconds over the finished tasks in the history.
+    pub fn total_duration(&self) -> Seconds {
+        self.finished_tasks().map(|t| t.duration).sum()
+    }
+
+    /// Focused seconds over the finished tasks that began within the
+    /// half-open `[from, to)`, the range `group_by_day` uses.
+    pub fn total_duration_in_range(&self, from: NaiveDateTime, to: NaiveDateTime) -> Seconds {
+        finished_in_range(&self.task_history, from, to)
+            .map(|t| t.duration)
+            .sum()
+    }
+
+    fn finished_tasks(&self) -> impl Iterator<Item = &Task> {
+        self.task_history
+            .iter()
+            .filter(|t| t.state == TaskState::End)
+    }
+
+    /// Rename every task named `from` to `to`, and the subtasks' `parent`
+    /// references with it. Returns how many history entries were renamed,
+    /// not counting the current task, which mirrors its history entry.
+    pub fn rename_tasks(&mut self, from: &str, to: &str) -> usize {
+        let mut renamed = 0;
+        for task in self.task_history.iter_mut() {
+            if task.rename(from, to) {
+                renamed += 1;
+            }
+        }
+        self.current_task.rename(from, to);
+        renamed
+    }
+
+    /// Every task the record holds, as an audit entry keeps them: the
+    /// history, and the current task unless it's a placeholder or the
+    /// history entry it mirrors.
+    pub fn audited_tasks(&self) -> Vec<Task> {
+        let current = &self.current_task;
+        let mirrored = self
+            .task_history
+            .iter()
+            .any(|t| t.name == current.name && t.begin_time == current.begin_time);
+        let mut tasks = self.task_history.clone();
+        if !current.is_placeholder() && !mirrored {
+            tasks.push(current.clone());
+        }
+        tasks
+    }
+
+    /// Give tasks recorded before ids existed one, in history order, and
+    /// the current task the id of its history entry. Returns whether
+    /// anything changed, i.e. whether the record should be written back.
+    pub fn backfill_task_ids(&mut self) -> bool {
+        let mut changed = false;
+        let next_id = self.next_task_id();
+        let without_id = self.task_history.iter_mut().filter(|t| t.id.is_none());
+        for (id, task) in (next_id..).zip(without_id) {
+            task.id = Some(id);
+            changed = true;
+        }
+
+        if self.current_task.id.is_none() {
+            let entry = self.task_history.iter().rev().find(|t| {
+                t.name == self.current_task.name && t.begin_time == self.current_task.begin_time
+            });
+            if let Some(entry) = entry {
+                self.current_task.id = entry.id;
+                changed = true;
+            }
+        }
+        changed
+    }
 }
 
 impl FromRedisValue for UserRecord {
     fn from_redis_value(v: &redis::Value) -> redis::RedisResult<UserRecord> {
         match *v {
             redis::Value::Data(ref bytes) => {
-                let user_data: UserRecord = serde_json::from_slice(bytes)?;
+                // RedisJSON wraps results read with a `$` path in an array.
+                let user_data: UserRecord = match serde_json::from_slice(bytes)? {
+                    serde_json::Value::Array(mut values) if values.len() == 1 => {
+                        serde_json::from_value(values.remove(0))?
+                    }
+                    value => serde_json::from_value(value)?,
+                };
                 Ok(user_data)
             }
             _ => Err((redis::ErrorKind::TypeError, "Invalid type").into()),
@@ -126,16 +745,1178 @@ impl FromRedisValue for UserRecord {
     }
 }
 
+/// One change to a record's tasks, as kept in its audit log.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct AuditEntry {
+    pub at: NaiveDateTime,
+    pub operation: AuditOperation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<i32>,
+    /// The tasks as they were, empty when they are new.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<Task>,
+    /// The tasks as they are now, e.g. both halves of a split.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<Task>,
+    /// Published tasks of a sudo record as they were, see `published`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub published_before: Vec<STask>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub published_after: Vec<STask>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    CreateTask,
+    UpdateTask,
+    SplitTask,
+    RenameTask,
+    ImportTasks,
+    ResetRecord,
+}
+
+impl AuditEntry {
+    /// An entry dated now, about the task `before` and `after` are versions of.
+    pub fn new(operation: AuditOperation, before: Vec<Task>, after: Vec<Task>) -> Self {
+        AuditEntry {
+            at: chrono::offset::Local::now().naive_local(),
+            operation,
+            task_id: before.iter().chain(&after).find_map(|t| t.id),
+            before,
+            after,
+            published_before: vec![],
+            published_after: vec![],
+        }
+    }
+
+    /// An entry dated now, about published tasks of a sudo record.
+    pub fn published(operation: AuditOperation, before: Vec<STask>, after: Vec<STask>) -> Self {
+        AuditEntry {
+            at: chrono::offset::Local::now().naive_local(),
+            operation,
+            task_id: before.iter().chain(&after).find_map(|t| t.id),
+            before: vec![],
+            after: vec![],
+            published_before: before,
+            published_after: after,
+        }
+    }
+}
+
+/// `UserRecord` as returned by listing endpoints, where a placeholder
+/// current task (freshly registered or reset) is reported as `null`.
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct UserRecordView {
+    pub id: i32,
+    pub user_name: String,
+    pub task_history: Vec<Task>,
+    pub current_task: Option<Task>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_goal_seconds: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_active_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hourly_rate: Option<HourlyRate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+impl From<UserRecord> for UserRecordView {
+    fn from(record: UserRecord) -> Self {
+        UserRecordView {
+            id: record.id,
+            user_name: record.user_name,
+            task_history: record.task_history,
+            current_task: (!record.current_task.is_placeholder()).then_some(record.current_task),
+            daily_goal_seconds: record.daily_goal_seconds,
+            last_active_at: record.last_active_at,
+            hourly_rate: record.hourly_rate,
+            webhook_url: record.webhook_url,
+            timezone: record.timezone,
+        }
+    }
+}
+
+/// Just enough of a user record to list users by recent activity.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct UserSummary {
+    pub key: String,
+    pub user_name: String,
+    pub last_active_at: Option<NaiveDateTime>,
+}
+
+/// Sort summaries by most recent activity first, never-active users last.
+pub fn sort_by_last_active(summaries: &mut [UserSummary]) {
+    summaries.sort_by_key(|s| std::cmp::Reverse(s.last_active_at));
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
 pub struct STask {
+    /// Unique within the owning record, never reused. Tasks published
+    /// before ids existed get one with `SudoUserRecord::backfill_task_ids`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
     pub name: String,
     pub description: String,
     pub created_at: NaiveDateTime,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
 pub struct SudoUserRecord {
     pub id: i32,
     pub user_name: String,
     pub published_tasks: Vec<STask>,
 }
+
+impl SudoUserRecord {
+    /// Id for the next published task: one past the highest, ids are never reused.
+    pub fn next_task_id(&self) -> i32 {
+        self.published_tasks
+            .iter()
+            .filter_map(|t| t.id)
+            .map(|id| id + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Give tasks published before ids existed one, in stored order.
+    /// Returns whether anything changed, i.e. whether the record should
+    /// be written back.
+    pub fn backfill_task_ids(&mut self) -> bool {
+        let next_id = self.next_task_id();
+        let without_id = self.published_tasks.iter_mut().filter(|t| t.id.is_none());
+        let mut changed = false;
+        for (id, task) in (next_id..).zip(without_id) {
+            task.id = Some(id);
+            changed = true;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const USER_RECORD_JSON: &str = r#"{
+        "id": 1,
+        "user_name": "alice",
+        "task_history": [],
+        "current_task": {
+            "name": "initialised",
+            "state": "Placeholder",
+            "begin_time": "2024-01-31T15:30:00",
+            "end_time": "2024-01-31T15:30:00",
+            "duration": 0
+        }
+    }"#;
+
+    fn field_names(value: serde_json::Value) -> Vec<String> {
+        let mut names: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn at(h: u32, m: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(h, m, 0)
+            .unwrap()
+    }
+
+    fn record(task_history: Vec<Task>, current_task: Task) -> UserRecord {
+        UserRecord {
+            task_history,
+            current_task,
+            ..UserRecord::new(1, "alice".to_string())
+        }
+    }
+
+    #[test]
+    fn test_seconds_are_sent_as_a_plain_integer() {
+        assert_eq!(serde_json::to_string(&Seconds(5400)).unwrap(), "5400");
+        assert_eq!(serde_json::from_str::<Seconds>("90").unwrap(), Seconds(90));
+
+        let task: Task = serde_json::from_str(
+            r#"{"name": "review", "state": "End", "begin_time": "2024-01-31T09:00:00",
+                "end_time": "2024-01-31T09:30:00", "duration": 1800}"#,
+        )
+        .unwrap();
+        assert_eq!(task.duration, Seconds(1800));
+        assert_eq!(serde_json::to_value(&task).unwrap()["duration"], 1800);
+    }
+
+    #[test]
+    fn test_seconds_display_as_hours_and_minutes() {
+        assert_eq!(Seconds(45).to_string(), "45s");
+        assert_eq!(Seconds(12 * 60 + 5).to_string(), "12m");
+        assert_eq!(Seconds(2 * 3600 + 30 * 60).to_string(), "2h30m");
+        assert_eq!(Seconds(3600).to_string(), "1h0m");
+        assert_eq!(format!("{:>6}", Seconds(1800)), "   30m");
+        assert_eq!(
+            [Seconds(600), Seconds(1200)].into_iter().sum::<Seconds>() - Seconds(300),
+            Seconds(1500)
+        );
+    }
+
+    #[test]
+    fn test_wire_field_names_are_pinned() {
+        let task = Task {
+            id: Some(7),
+            billable: true,
+            parent: Some("release".to_string()),
+            duration: Seconds(1800),
+            total_break_seconds: Seconds(300),
+            ..Task::generate_begin_task("writing".to_string())
+        };
+        assert_eq!(
+            field_names(serde_json::json!(task)),
+            vec![
+                "begin_time",
+                "billable",
+                "duration",
+                "end_time",
+                "events",
+                "id",
+                "name",
+                "parent",
+                "state",
+                "total_break_seconds",
+            ]
+        );
+
+        let record = UserRecord {
+            daily_goal_seconds: Some(3600),
+            last_active_at: Some(chrono::offset::Local::now().naive_local()),
+            hourly_rate: Some(HourlyRate {
+                cents: 5000,
+                currency: "EUR".to_string(),
+            }),
+            webhook_url: Some("https://example.com/hook".to_string()),
+            timezone: Some("Europe/Berlin".to_string()),
+            ..record(vec![], task)
+        };
+        let json = serde_json::json!(record);
+        assert_eq!(
+            field_names(json.clone()),
+            vec![
+                "current_task",
+                "daily_goal_seconds",
+                "hourly_rate",
+                "id",
+                "last_active_at",
+                "schema_version",
+                "task_history",
+                "timezone",
+                "user_name",
+                "webhook_url",
+            ]
+        );
+        let round_trip: UserRecord = serde_json::from_value(json).unwrap();
+        assert_eq!(round_trip.daily_goal_seconds, Some(3600));
+    }
+
+    #[test]
+    fn test_user_record_from_bare_object() {
+        let value = redis::Value::Data(USER_RECORD_JSON.as_bytes().to_vec());
+
+        let user_record = UserRecord::from_redis_value(&value).unwrap();
+
+        assert_eq!(user_record.id, 1);
+        assert_eq!(user_record.user_name, "alice");
+    }
+
+    #[test]
+    fn test_user_record_from_redis_json_array() {
+        let wrapped = format!("[{}]", USER_RECORD_JSON);
+        let value = redis::Value::Data(wrapped.into_bytes());
+
+        let user_record = UserRecord::from_redis_value(&value).unwrap();
+
+        assert_eq!(user_record.id, 1);
+        assert!(user_record.current_task.is_placeholder());
+    }
+
+    #[test]
+    fn test_user_record_from_invalid_value() {
+        let empty_array = redis::Value::Data(b"[]".to_vec());
+        assert!(UserRecord::from_redis_value(&empty_array).is_err());
+        assert!(UserRecord::from_redis_value(&redis::Value::Nil).is_err());
+    }
+
+    #[test]
+    fn test_can_transition_to_matrix() {
+        use TaskState::*;
+        let states = [Begin, Break, Back, End, Placeholder];
+        let allowed = [
+            (Begin, Break),
+            (Begin, End),
+            (Break, Back),
+            (Break, End),
+            (Back, Break),
+            (Back, End),
+            (End, Begin),
+            (Placeholder, Begin),
+        ];
+
+        for from in &states {
+            for to in &states {
+                let expected = allowed.contains(&(from.clone(), to.clone()));
+                assert_eq!(
+                    from.can_transition_to(to),
+                    expected,
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_terminal_states() {
+        assert!(TaskState::End.is_terminal());
+        assert!(TaskState::Placeholder.is_terminal());
+        assert!(!TaskState::Break.is_terminal());
+    }
+
+    #[test]
+    fn test_transitions_are_recorded_as_events() {
+        let begun = Task::generate_begin_task("writing".to_string());
+        let task = Task::generate_done_task(&Task::generate_back_task(&Task::generate_break_task(
+            &begun,
+        )));
+
+        let states: Vec<&TaskState> = task.events.iter().map(|e| &e.state).collect();
+        assert_eq!(
+            states,
+            [
+                &TaskState::Begin,
+                &TaskState::Break,
+                &TaskState::Back,
+                &TaskState::End
+            ]
+        );
+        assert_eq!(task.events[0].at, task.begin_time);
+        assert!(task.events.windows(2).all(|pair| pair[0].at <= pair[1].at));
+    }
+
+    #[test]
+    fn test_seconds_from_events() {
+        let event = |state: TaskState, h: u32, m: u32| TaskEvent {
+            state,
+            at: at(h, m),
+        };
+        let mut task = Task {
+            state: TaskState::End,
+            begin_time: at(9, 0),
+            events: vec![
+                event(TaskState::Begin, 9, 0),
+                event(TaskState::Break, 9, 30),
+                event(TaskState::Back, 9, 40),
+                event(TaskState::End, 10, 0),
+            ],
+            ..Task::default()
+        };
+        // Nothing counts after the end, whatever `until` is.
+        assert_eq!(
+            task.seconds_from_events(at(12, 0)),
+            Some((Seconds(3000), Seconds(600)))
+        );
+
+        // A task still going counts up to `until`.
+        task.events.truncate(3);
+        assert_eq!(
+            task.seconds_from_events(at(9, 50)),
+            Some((Seconds(2400), Seconds(600)))
+        );
+        task.events.truncate(2);
+        assert_eq!(
+            task.seconds_from_events(at(9, 50)),
+            Some((Seconds(1800), Seconds(1200)))
+        );
+
+        task.events.clear();
+        assert_eq!(task.seconds_from_events(at(12, 0)), None);
+    }
+
+    #[test]
+    fn test_split_divides_events() {
+        let event = |state: TaskState, h: u32, m: u32| TaskEvent {
+            state,
+            at: at(h, m),
+        };
+        let task = Task {
+            state: TaskState::End,
+            begin_time: at(9, 0),
+            end_time: at(12, 0),
+            duration: Seconds(3 * 3600),
+            events: vec![
+                event(TaskState::Begin, 9, 0),
+                event(TaskState::Break, 11, 0),
+                event(TaskState::Back, 11, 15),
+                event(TaskState::End, 12, 0),
+            ],
+            ..Task::default()
+        };
+
+        let (first, second) = task.split_at(at(10, 0), "review".to_string()).unwrap();
+        assert_eq!(
+            first.events,
+            [event(TaskState::Begin, 9, 0), event(TaskState::End, 10, 0)]
+        );
+        assert_eq!(
+            (first.duration, first.total_break_seconds),
+            (Seconds(3600), Seconds(0))
+        );
+        // The break was taken after the split, so it is the second half's.
+        assert_eq!(
+            (second.duration, second.total_break_seconds),
+            (Seconds(6300), Seconds(900))
+        );
+        assert_eq!(
+            second.events,
+            [
+                event(TaskState::Begin, 10, 0),
+                event(TaskState::Break, 11, 0),
+                event(TaskState::Back, 11, 15),
+                event(TaskState::End, 12, 0),
+            ]
+        );
+    }
+
+    fn task_begun_secs_ago(state: TaskState, secs: i64, duration: i64) -> Task {
+        Task {
+            id: None,
+            name: "test".to_string(),
+            state,
+            begin_time: chrono::offset::Local::now().naive_local()
+                - chrono::Duration::seconds(secs),
+            end_time: chrono::offset::Local::now().naive_local(),
+            duration: Seconds(duration),
+            parent: None,
+            total_break_seconds: Seconds(0),
+            billable: false,
+            events: vec![],
+        }
+    }
+
+    #[test]
+    fn test_done_from_break_keeps_break_duration() {
+        // Worked for 1h, then went on break 2h ago and never came back.
+        let mut break_task = task_begun_secs_ago(TaskState::Break, 3 * 3600, 3600);
+        break_task.end_time = break_task.begin_time + chrono::Duration::seconds(3600);
+
+        let done_task = Task::generate_done_task(&break_task);
+
+        assert_eq!(done_task.state, TaskState::End);
+        assert_eq!(done_task.duration, Seconds(3600));
+        assert_eq!(done_task.end_time, break_task.end_time);
+    }
+
+    /// A task begun `secs` ago that has been on breaks for `break_secs`.
+    fn task_with_breaks(state: TaskState, secs: i64, break_secs: i64) -> Task {
+        Task {
+            total_break_seconds: Seconds(break_secs),
+            ..task_begun_secs_ago(state, secs, 0)
+        }
+    }
+
+    #[test]
+    fn test_break_from_back_counts_only_focused_time() {
+        // Begun 2h ago, of which 30m were on a break.
+        let back_task = task_with_breaks(TaskState::Back, 2 * 3600, 1800);
+
+        let break_task = Task::generate_break_task(&back_task);
+
+        assert_eq!(break_task.state, TaskState::Break);
+        assert!((5400..5402).contains(&break_task.duration.0));
+        assert_eq!(break_task.begin_time, back_task.begin_time);
+    }
+
+    #[test]
+    fn test_done_after_second_break_keeps_all_work() {
+        let back_task = task_with_breaks(TaskState::Back, 2 * 3600, 1800);
+        let break_task = Task::generate_break_task(&back_task);
+
+        let done_task = Task::generate_done_task(&break_task);
+
+        assert_eq!(done_task.duration, break_task.duration);
+        assert!((5400..5402).contains(&done_task.duration.0));
+    }
+
+    #[test]
+    fn test_done_from_back_excludes_all_breaks() {
+        let back_task = task_with_breaks(TaskState::Back, 3 * 3600, 3600);
+
+        let done_task = Task::generate_done_task(&back_task);
+
+        assert!((7200..7202).contains(&done_task.duration.0));
+        assert_eq!(done_task.total_break_seconds, Seconds(3600));
+        assert_eq!(done_task.begin_time, back_task.begin_time);
+    }
+
+    #[test]
+    fn test_multiple_break_cycles() {
+        // Begun 1h ago: went on a 5m break, then on the current one 10m ago.
+        let mut break_task = task_with_breaks(TaskState::Break, 3600, 300);
+        break_task.end_time -= chrono::Duration::seconds(600);
+        break_task.duration = Seconds(3600 - 300 - 600);
+
+        let back_task = Task::generate_back_task(&break_task);
+        assert!((Seconds(900)..Seconds(902)).contains(&back_task.total_break_seconds));
+        assert!((Seconds(2699)..Seconds(2702)).contains(&back_task.worked_seconds()));
+
+        let done_task = Task::generate_done_task(&back_task);
+        assert_eq!(done_task.total_break_seconds, back_task.total_break_seconds);
+        let wall_seconds = (done_task.end_time - done_task.begin_time).num_seconds();
+        assert!(((done_task.duration + done_task.total_break_seconds).0 - wall_seconds).abs() <= 1);
+    }
+
+    #[test]
+    fn test_begin_break_back_done_counts_work_on_both_sides() {
+        let minutes = chrono::Duration::minutes;
+        // Begun 1h ago, on a break since 30m ago.
+        let begin_task = task_begun_secs_ago(TaskState::Begin, 3600, 0);
+        let mut break_task = Task::generate_break_task(&begin_task);
+        break_task.end_time -= minutes(30);
+        break_task.duration -= Seconds(30 * 60);
+
+        // Back now, then moved 10m into the past as a whole.
+        let mut back_task = Task::generate_back_task(&break_task);
+        assert_eq!(back_task.begin_time, begin_task.begin_time);
+        assert!(back_task.end_time > break_task.end_time);
+        back_task.begin_time -= minutes(10);
+        back_task.end_time -= minutes(10);
+
+        let done_task = Task::generate_done_task(&back_task);
+        let work_before_break = break_task.duration.0;
+        assert!((1800..1802).contains(&work_before_break));
+        assert!((done_task.duration.0 - (work_before_break + 10 * 60)).abs() <= 1);
+        assert!((Seconds(1800)..Seconds(1802)).contains(&done_task.total_break_seconds));
+    }
+
+    fn any_transition() -> impl Strategy<Value = TaskState> {
+        prop_oneof![
+            Just(TaskState::Begin),
+            Just(TaskState::Break),
+            Just(TaskState::Back),
+            Just(TaskState::End),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_random_break_cycles_sum_their_segments(
+            cycles in prop::collection::vec((1..=3 * 3600i64, 1..=3600i64), 0..6),
+            last_work in 0..3 * 3600i64,
+            trailing_break in prop::option::of(1..=3600i64),
+        ) {
+            let start = chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(9, 0, 0)
+                .unwrap();
+            let mut at = start;
+            let mut task = Task::begin_at("writing".to_string(), start);
+            let (mut worked, mut breaks) = (Seconds(0), Seconds(0));
+
+            for (work, pause) in cycles {
+                at += chrono::Duration::seconds(work);
+                worked += Seconds(work);
+                task = task.break_at(at);
+                prop_assert_eq!(task.duration, worked);
+
+                at += chrono::Duration::seconds(pause);
+                breaks += Seconds(pause);
+                task = task.back_at(at);
+                prop_assert_eq!(task.worked_seconds_at(at), worked);
+            }
+            at += chrono::Duration::seconds(last_work);
+            worked += Seconds(last_work);
+            // Finishing during a trailing break counts it as neither.
+            if let Some(pause) = trailing_break {
+                task = task.break_at(at);
+                at += chrono::Duration::seconds(pause);
+            }
+            let done = task.done_at(at);
+
+            prop_assert_eq!(done.duration, worked);
+            prop_assert_eq!(done.total_break_seconds, breaks);
+            prop_assert_eq!(
+                Seconds::between(done.begin_time, done.end_time),
+                done.duration + done.total_break_seconds
+            );
+        }
+
+        #[test]
+        fn test_random_transitions_keep_the_state_machine_invariants(
+            steps in prop::collection::vec((0..2 * 3600i64, any_transition()), 0..200),
+        ) {
+            let mut at = chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(9, 0, 0)
+                .unwrap();
+            let mut task = Task::placeholder("initialised", TaskState::Placeholder);
+            // Work seconds of the current task's segments, summed as they pass.
+            let mut worked = Seconds(0);
+
+            for (advance, next) in steps {
+                if task.state == TaskState::Begin || task.state == TaskState::Back {
+                    worked += Seconds(advance);
+                }
+                at += chrono::Duration::seconds(advance);
+
+                // Refused transitions leave the task as it is, like the CLI does.
+                if !task.state.can_transition_to(&next) {
+                    continue;
+                }
+                let previous = task.state.clone();
+                task = match next {
+                    TaskState::Begin => {
+                        worked = Seconds(0);
+                        Task::begin_at("writing".to_string(), at)
+                    }
+                    TaskState::Break => task.break_at(at),
+                    TaskState::Back => task.back_at(at),
+                    _ => task.done_at(at),
+                };
+                prop_assert_eq!(&task.state, &next, "from {:?}", previous);
+
+                let times: Vec<NaiveDateTime> = task.events.iter().map(|e| e.at).collect();
+                prop_assert!(times.windows(2).all(|w| w[0] <= w[1]));
+                prop_assert!(task.begin_time <= task.end_time && task.end_time <= at);
+                prop_assert_eq!(task.worked_seconds_at(at), worked);
+                if task.state == TaskState::End {
+                    prop_assert_eq!(task.duration, worked);
+                    let (from_events, _) = task.seconds_from_events(at).unwrap();
+                    prop_assert_eq!(from_events, worked);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_focused_since_latest_break() {
+        let begin_task = task_begun_secs_ago(TaskState::Begin, 3600, 0);
+        assert_eq!(begin_task.focused_since(), Some(begin_task.begin_time));
+
+        // Begun 1h ago: went on a 5m break, then on a 10m one that ended 20m ago.
+        let mut break_task = task_with_breaks(TaskState::Break, 3600, 300);
+        break_task.end_time -= chrono::Duration::seconds(1800);
+        break_task.duration = Seconds(3600 - 300 - 1800);
+        let mut back_task = Task::generate_back_task(&break_task);
+        back_task.total_break_seconds -= Seconds(1200);
+
+        let streak =
+            chrono::offset::Local::now().naive_local() - back_task.focused_since().unwrap();
+        assert!((1200..1202).contains(&streak.num_seconds()));
+
+        assert_eq!(break_task.focused_since(), None);
+        assert_eq!(Task::generate_done_task(&back_task).focused_since(), None);
+    }
+
+    #[test]
+    fn test_worked_seconds_excludes_breaks() {
+        let begin_task = task_begun_secs_ago(TaskState::Begin, 600, 0);
+        assert!((Seconds(600)..Seconds(602)).contains(&begin_task.worked_seconds()));
+
+        let break_task = task_begun_secs_ago(TaskState::Break, 3600, 600);
+        assert_eq!(break_task.worked_seconds(), Seconds(600));
+
+        let back_task = task_with_breaks(TaskState::Back, 900, 300);
+        assert!((Seconds(600)..Seconds(602)).contains(&back_task.worked_seconds()));
+    }
+
+    #[test]
+    fn test_split_at_divides_end_task() {
+        let begin_time = chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        let task = Task {
+            id: None,
+            name: "writing".to_string(),
+            state: TaskState::End,
+            begin_time,
+            end_time: begin_time + chrono::Duration::hours(4),
+            duration: Seconds(4 * 3600),
+            parent: None,
+            total_break_seconds: Seconds(0),
+            billable: false,
+            events: vec![],
+        };
+        let at = begin_time + chrono::Duration::hours(1);
+
+        let (first, second) = task.split_at(at, "review".to_string()).unwrap();
+
+        assert_eq!(first.name, "writing");
+        assert_eq!((first.begin_time, first.end_time), (begin_time, at));
+        assert_eq!(first.duration, Seconds(3600));
+        assert_eq!(second.name, "review");
+        assert_eq!((second.begin_time, second.end_time), (at, task.end_time));
+        assert_eq!(second.duration, Seconds(3 * 3600));
+    }
+
+    #[test]
+    fn test_split_inside_break_starts_second_half_on_break() {
+        let event = |state: TaskState, h: u32, m: u32| TaskEvent {
+            state,
+            at: at(h, m),
+        };
+        let task = Task {
+            state: TaskState::End,
+            begin_time: at(9, 0),
+            end_time: at(12, 0),
+            duration: Seconds(150 * 60),
+            total_break_seconds: Seconds(1800),
+            events: vec![
+                event(TaskState::Begin, 9, 0),
+                event(TaskState::Break, 11, 0),
+                event(TaskState::Back, 11, 30),
+                event(TaskState::End, 12, 0),
+            ],
+            ..Task::default()
+        };
+
+        let (first, second) = task.split_at(at(11, 10), "review".to_string()).unwrap();
+
+        assert_eq!(
+            first.events,
+            [
+                event(TaskState::Begin, 9, 0),
+                event(TaskState::Break, 11, 0),
+                event(TaskState::End, 11, 10),
+            ]
+        );
+        assert_eq!(
+            second.events,
+            [
+                event(TaskState::Break, 11, 10),
+                event(TaskState::Back, 11, 30),
+                event(TaskState::End, 12, 0),
+            ]
+        );
+        assert_eq!(
+            (first.duration, first.total_break_seconds),
+            (Seconds(7200), Seconds(600))
+        );
+        assert_eq!(
+            (second.duration, second.total_break_seconds),
+            (Seconds(1800), Seconds(1200))
+        );
+    }
+
+    #[test]
+    fn test_split_at_shares_breaks_without_events() {
+        let begin_time = chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        let task = Task {
+            state: TaskState::End,
+            begin_time,
+            end_time: begin_time + chrono::Duration::hours(4),
+            duration: Seconds(3 * 3600),
+            total_break_seconds: Seconds(3600),
+            ..Task::default()
+        };
+
+        let (first, second) = task
+            .split_at(
+                begin_time + chrono::Duration::hours(1),
+                "review".to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            (first.duration, first.total_break_seconds),
+            (Seconds(2700), Seconds(900))
+        );
+        assert_eq!(
+            (second.duration, second.total_break_seconds),
+            (Seconds(8100), Seconds(2700))
+        );
+    }
+
+    #[test]
+    fn test_split_at_rejects_boundaries_and_active_tasks() {
+        let mut task = task_begun_secs_ago(TaskState::End, 3600, 3600);
+
+        assert!(task.split_at(task.begin_time, "x".to_string()).is_none());
+        assert!(task.split_at(task.end_time, "x".to_string()).is_none());
+
+        task.state = TaskState::Begin;
+        let middle = task.begin_time + chrono::Duration::minutes(30);
+        assert!(task.split_at(middle, "x".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_subtask_keeps_parent_through_transitions() {
+        let task =
+            Task::generate_begin_subtask("tests".to_string(), "release".to_string()).unwrap();
+        let done_task = Task::generate_done_task(&Task::generate_break_task(&task));
+
+        assert_eq!(done_task.parent.as_deref(), Some("release"));
+        assert!(
+            Task::generate_begin_subtask("release".to_string(), "release".to_string()).is_none()
+        );
+    }
+
+    #[test]
+    fn test_begin_task_names_are_trimmed() {
+        let task = Task::generate_begin_subtask(" tests ".to_string(), "release ".to_string());
+        let task = task.unwrap();
+        assert_eq!(task.name, "tests");
+        assert_eq!(task.parent.as_deref(), Some("release"));
+
+        assert!(
+            Task::generate_begin_subtask("release".to_string(), " release".to_string()).is_none()
+        );
+        assert_eq!(normalize_task_name(" Writing ", true), "writing");
+        assert_eq!(normalize_task_name(" Writing ", false), "Writing");
+    }
+
+    #[test]
+    fn test_zero_seconds_are_left_out() {
+        let json = serde_json::json!(Task::placeholder("initialised", TaskState::Placeholder));
+        assert_eq!(
+            field_names(json.clone()),
+            vec!["begin_time", "end_time", "name", "state"]
+        );
+
+        let task: Task = serde_json::from_value(json).unwrap();
+        assert_eq!(task.duration, Seconds(0));
+        assert_eq!(task.total_break_seconds, Seconds(0));
+    }
+
+    #[test]
+    fn test_task_without_parent_deserializes() {
+        let task = serde_json::from_str::<Task>(
+            r#"{
+                "name": "writing",
+                "state": "Begin",
+                "begin_time": "2024-01-31T09:00:00",
+                "end_time": "2024-01-31T09:00:00",
+                "duration": 0
+            }"#,
+        )
+        .unwrap();
+
+        assert!(task.parent.is_none());
+        assert!(!task.billable);
+    }
+
+    #[test]
+    fn test_reset_keeps_settings() {
+        let record = UserRecord {
+            daily_goal_seconds: Some(6 * 3600),
+            ..record(
+                vec![task_begun_secs_ago(TaskState::End, 3600, 3600)],
+                Task::generate_begin_task("writing".to_string()),
+            )
+        };
+
+        let reset_record = record.into_reset();
+
+        assert_eq!(reset_record.id, 1);
+        assert_eq!(reset_record.user_name, "alice");
+        assert_eq!(reset_record.daily_goal_seconds, Some(6 * 3600));
+        assert!(reset_record.task_history.is_empty());
+        assert!(reset_record.current_task.is_placeholder());
+    }
+
+    #[test]
+    fn test_timestamps_are_pinned() {
+        let at = chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_milli_opt(9, 0, 0, 250)
+            .unwrap();
+        assert_eq!(timestamp::parse("2024-01-31T09:00:00.250"), Ok(at));
+        assert_eq!(
+            timestamp::parse(serde_json::json!(at).as_str().unwrap()),
+            Ok(at)
+        );
+        assert!(timestamp::parse("2024-01-31T09:00:00").is_ok());
+
+        for raw in [
+            "2024-01-31 09:00:00",
+            "2024-1-31T09:00:00",
+            "2024-01-31T09:00",
+            "2024-01-31T09:00:00Z",
+            "2024-01-31T09:00:00.",
+            "2024-02-30T09:00:00",
+            "+2024-01-31T09:00:00",
+            "2024-01-31T09:00:00é",
+        ] {
+            assert!(timestamp::parse(raw).is_err(), "{} was accepted", raw);
+        }
+    }
+
+    #[test]
+    fn test_rename_tasks_across_history() {
+        let old = Task::generate_done_task(&Task::generate_begin_task("imon".to_string()));
+        let sub = Task::generate_begin_subtask("docs".to_string(), "imon".to_string()).unwrap();
+        let other = Task::generate_done_task(&Task::generate_begin_task("review".to_string()));
+        let mut record = record(vec![old.clone(), other, old, sub.clone()], sub);
+
+        assert_eq!(record.rename_tasks("imon", "imon-rs"), 2);
+
+        let names: Vec<&str> = record
+            .task_history
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(names, ["imon-rs", "review", "imon-rs", "docs"]);
+        assert_eq!(record.task_history[3].parent.as_deref(), Some("imon-rs"));
+        assert_eq!(record.current_task.parent.as_deref(), Some("imon-rs"));
+        assert_eq!(record.rename_tasks("imon", "imon-rs"), 0);
+    }
+
+    #[test]
+    fn test_total_duration_counts_finished_tasks_only() {
+        let task = |state: TaskState, begin_time: NaiveDateTime, duration: i64| Task {
+            state,
+            begin_time,
+            end_time: begin_time + chrono::Duration::seconds(duration),
+            duration: Seconds(duration),
+            ..Task::generate_begin_task("writing".to_string())
+        };
+        let record = record(
+            vec![
+                task(TaskState::End, at(9, 0), 1800),
+                task(TaskState::Break, at(10, 0), 600),
+                task(TaskState::End, at(10, 0), 900),
+                task(TaskState::Placeholder, at(11, 0), 0),
+                task(TaskState::End, at(12, 0), 300),
+                task(TaskState::Begin, at(13, 0), 0),
+            ],
+            task(TaskState::Begin, at(13, 0), 0),
+        );
+
+        assert_eq!(record.total_duration(), Seconds(3000));
+        assert_eq!(
+            record.total_duration_in_range(at(9, 0), at(12, 0)),
+            Seconds(2700)
+        );
+        // `from` is inclusive and `to` exclusive.
+        assert_eq!(
+            record.total_duration_in_range(at(10, 0), at(12, 0)),
+            Seconds(900)
+        );
+        assert_eq!(
+            record.total_duration_in_range(at(9, 1), at(12, 1)),
+            Seconds(1200)
+        );
+        assert_eq!(
+            record.total_duration_in_range(at(12, 0), at(12, 0)),
+            Seconds(0)
+        );
+    }
+
+    #[test]
+    fn test_task_by_id_prefers_current_task() {
+        let done = Task {
+            id: Some(0),
+            ..task_begun_secs_ago(TaskState::End, 7200, 3600)
+        };
+        let stale = Task {
+            id: Some(1),
+            ..task_begun_secs_ago(TaskState::Begin, 60, 0)
+        };
+        let current_task = Task::generate_break_task(&stale);
+        let record = record(vec![done, stale], current_task);
+
+        assert_eq!(record.task_by_id(0).unwrap().state, TaskState::End);
+        assert_eq!(record.task_by_id(1).unwrap().state, TaskState::Break);
+        assert!(record.task_by_id(2).is_none());
+    }
+
+    #[test]
+    fn test_task_by_id_follows_history_once_finished() {
+        let done = Task {
+            id: Some(0),
+            ..task_begun_secs_ago(TaskState::End, 7200, 3600)
+        };
+        let at = done.begin_time + chrono::Duration::seconds(1800);
+        let (first, mut second) = done.split_at(at, done.name.clone()).unwrap();
+        second.id = Some(1);
+        let record = record(vec![first.clone(), second], done);
+
+        assert_eq!(record.task_by_id(0).unwrap().end_time, first.end_time);
+        assert_eq!(record.task_by_id(1).unwrap().begin_time, at);
+    }
+
+    #[test]
+    fn test_backfill_task_ids() {
+        let current_task = Task {
+            id: None,
+            ..task_begun_secs_ago(TaskState::Begin, 60, 0)
+        };
+        let mut record = record(
+            vec![
+                task_begun_secs_ago(TaskState::End, 7200, 3600),
+                Task {
+                    id: Some(4),
+                    ..task_begun_secs_ago(TaskState::End, 3600, 1800)
+                },
+                current_task.clone(),
+            ],
+            current_task,
+        );
+
+        assert!(record.backfill_task_ids());
+        let ids: Vec<Option<i32>> = record.task_history.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![Some(5), Some(4), Some(6)]);
+        assert_eq!(record.current_task.id, Some(6));
+        assert_eq!(record.next_task_id(), 7);
+
+        assert!(!record.backfill_task_ids());
+    }
+
+    #[test]
+    fn test_audited_tasks_include_unmirrored_current_task() {
+        let done = task_begun_secs_ago(TaskState::End, 7200, 3600);
+        let active = task_begun_secs_ago(TaskState::Begin, 60, 0);
+        let states = |record: UserRecord| -> Vec<TaskState> {
+            record
+                .audited_tasks()
+                .into_iter()
+                .map(|t| t.state)
+                .collect()
+        };
+
+        let mirrored = record(vec![done.clone(), active.clone()], active.clone());
+        assert_eq!(states(mirrored), vec![TaskState::End, TaskState::Begin]);
+        let unmirrored = record(vec![done.clone()], active);
+        assert_eq!(states(unmirrored), vec![TaskState::End, TaskState::Begin]);
+        let reset = record(
+            vec![done],
+            Task::placeholder("reset", TaskState::Placeholder),
+        );
+        assert_eq!(states(reset), vec![TaskState::End]);
+    }
+
+    #[test]
+    fn test_backfill_published_task_ids() {
+        let published = |id: Option<i32>, name: &str| STask {
+            id,
+            name: name.to_string(),
+            description: String::new(),
+            created_at: chrono::NaiveDateTime::default(),
+        };
+        let mut record: SudoUserRecord = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "user_name": "bob",
+            "published_tasks": [
+                { "name": "old", "description": "", "created_at": "2024-01-30T09:00:00" },
+                { "name": "older", "description": "", "created_at": "2024-01-29T09:00:00" },
+            ],
+        }))
+        .unwrap();
+        record.published_tasks.push(published(Some(3), "new"));
+
+        assert!(record.backfill_task_ids());
+        let ids: Vec<Option<i32>> = record.published_tasks.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![Some(4), Some(5), Some(3)]);
+        assert_eq!(record.next_task_id(), 6);
+        assert!(!record.backfill_task_ids());
+    }
+
+    #[test]
+    fn test_transitions_keep_task_id() {
+        let task = Task {
+            id: Some(3),
+            ..Task::generate_begin_task("writing".to_string())
+        };
+        let done_task = Task::generate_done_task(&Task::generate_break_task(&task));
+        assert_eq!(done_task.id, Some(3));
+
+        let end_time = done_task.end_time;
+        let (first, second) = Task {
+            begin_time: end_time - chrono::Duration::hours(1),
+            ..done_task
+        }
+        .split_at(
+            end_time - chrono::Duration::minutes(30),
+            "review".to_string(),
+        )
+        .unwrap();
+        assert_eq!(first.id, Some(3));
+        assert_eq!(second.id, None);
+    }
+
+    #[test]
+    fn test_earnings_round_partial_hours() {
+        let rate = HourlyRate {
+            cents: 4550,
+            currency: "EUR".to_string(),
+        };
+
+        assert_eq!(rate.earnings(Seconds(3600)).cents, 4550);
+        assert_eq!(rate.earnings(Seconds(90 * 60)).cents, 6825);
+        // 10 minutes are 758.33 cents.
+        assert_eq!(rate.earnings(Seconds(600)).cents, 758);
+        // 1 second is 1.26 cents, 2 seconds 2.53.
+        assert_eq!(rate.earnings(Seconds(1)).cents, 1);
+        assert_eq!(rate.earnings(Seconds(2)).cents, 3);
+        assert_eq!(rate.earnings(Seconds(0)).cents, 0);
+
+        assert_eq!(rate.earnings(Seconds(90 * 60)).to_string(), "68.25 EUR");
+        assert_eq!(rate.earnings(Seconds(600)).to_string(), "7.58 EUR");
+    }
+
+    #[test]
+    fn test_sort_by_last_active_puts_inactive_last() {
+        let summary = |user_name: &str, hour: Option<u32>| UserSummary {
+            key: format!("user:{}:0001", user_name),
+            user_name: user_name.to_string(),
+            last_active_at: hour.map(|h| {
+                chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                    .unwrap()
+                    .and_hms_opt(h, 0, 0)
+                    .unwrap()
+            }),
+        };
+        let mut summaries = vec![
+            summary("idle", None),
+            summary("early", Some(9)),
+            summary("late", Some(17)),
+        ];
+
+        sort_by_last_active(&mut summaries);
+
+        let names: Vec<&str> = summaries.iter().map(|s| s.user_name.as_str()).collect();
+        assert_eq!(names, vec!["late", "early", "idle"]);
+    }
+
+    #[test]
+    fn test_user_record_view_hides_placeholder_task() {
+        let reset_record = record(vec![], Task::placeholder("reset", TaskState::Placeholder));
+
+        let view = UserRecordView::from(reset_record);
+
+        assert!(view.current_task.is_none());
+        assert_eq!(
+            serde_json::json!(view)["current_task"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn test_user_record_view_keeps_active_task() {
+        let record = record(vec![], Task::generate_begin_task("writing".to_string()));
+
+        let view = UserRecordView::from(record);
+
+        assert_eq!(view.current_task.unwrap().name, "writing");
+    }
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+use crate::payload::PageInfo;
+use crate::record::{UserRecord, UserRecordView};
+
+/// Status of every successful response.
+pub const STATUS_OK: &str = "ok";
+
+/// A successful response without data, e.g. from `/v1/task/new`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct StatusResponse {
+    pub status: String,
+}
+
+impl StatusResponse {
+    pub fn ok() -> Self {
+        StatusResponse {
+            status: STATUS_OK.to_string(),
+        }
+    }
+}
+
+/// A successful response carrying `data`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DataResponse<T> {
+    pub status: String,
+    pub data: T,
+}
+
+impl<T> DataResponse<T> {
+    pub fn ok(data: T) -> Self {
+        DataResponse {
+            status: STATUS_OK.to_string(),
+            data,
+        }
+    }
+}
+
+/// One page of a list response, see `PageQuery`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PageResponse<T> {
+    pub status: String,
+    pub data: T,
+    pub page: PageInfo,
+}
+
+impl<T> PageResponse<T> {
+    pub fn ok(data: T, page: PageInfo) -> Self {
+        PageResponse {
+            status: STATUS_OK.to_string(),
+            data,
+            page,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct UserKeyData {
+    pub user_key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct TaskLogData {
+    pub task_log: UserRecord,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct UserRecordsData {
+    pub user_records: Vec<UserRecordView>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct ServerTimeData {
+    pub time: DateTime<FixedOffset>,
+    /// The server's offset from UTC at `time`, e.g. `+09:00`.
+    pub utc_offset: String,
+}
+
+/// From `/v1/record/new`.
+pub type RegisterResponse = DataResponse<UserKeyData>;
+/// From `/v1/record`.
+pub type RecordResponse = DataResponse<TaskLogData>;
+/// From `/v1/record/all`.
+pub type AllRecordsResponse = PageResponse<UserRecordsData>;
+/// From `/v1/time`.
+pub type ServerTimeResponse = DataResponse<ServerTimeData>;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::record::{Task, SCHEMA_VERSION};
+
+    #[test]
+    fn test_should_round_trip_envelopes() {
+        let status = StatusResponse::ok();
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            json!({ "status": "ok" })
+        );
+
+        let register = RegisterResponse::ok(UserKeyData {
+            user_key: "user:alice:0001".to_string(),
+        });
+        let wire = serde_json::to_value(&register).unwrap();
+        assert_eq!(
+            wire,
+            json!({ "status": "ok", "data": { "user_key": "user:alice:0001" } })
+        );
+        assert_eq!(
+            serde_json::from_value::<RegisterResponse>(wire).unwrap(),
+            register
+        );
+
+        let time = ServerTimeResponse::ok(ServerTimeData {
+            time: DateTime::parse_from_rfc3339("2024-01-31T09:00:00+09:00").unwrap(),
+            utc_offset: "+09:00".to_string(),
+        });
+        let wire = serde_json::to_string(&time).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ServerTimeResponse>(&wire).unwrap(),
+            time
+        );
+    }
+
+    #[test]
+    fn test_should_round_trip_record_response() {
+        let record = UserRecord {
+            id: 1,
+            user_name: "alice".to_string(),
+            task_history: vec![],
+            current_task: Task::generate_begin_task("writing".to_string()),
+            daily_goal_seconds: Some(21600),
+            last_active_at: None,
+            hourly_rate: None,
+            webhook_url: None,
+            timezone: None,
+            schema_version: SCHEMA_VERSION,
+        };
+        let wire =
+            serde_json::to_string(&RecordResponse::ok(TaskLogData { task_log: record })).unwrap();
+
+        let response: RecordResponse = serde_json::from_str(&wire).unwrap();
+        assert_eq!(response.status, STATUS_OK);
+        assert_eq!(response.data.task_log.user_name, "alice");
+        assert_eq!(response.data.task_log.current_task.name, "writing");
+        // Only the matching type reads it.
+        assert!(serde_json::from_str::<RegisterResponse>(&wire).is_err());
+    }
+}
@@ -0,0 +1,424 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::record::{Earnings, Seconds, Task, TaskState};
+
+/// Cheap totals across all users, e.g. for a status badge.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct StatsOverview {
+    pub users: usize,
+    pub active_users: usize,
+    pub tasks_today: usize,
+    /// Entries across all task histories.
+    #[serde(default)]
+    pub total_tasks: usize,
+}
+
+/// Focused time of the finished tasks in one group of `/v1/stats/grouped`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GroupTotal {
+    pub group: String,
+    pub seconds: Seconds,
+    /// The part of `seconds` spent on billable tasks.
+    pub billable_seconds: Seconds,
+    pub tasks: usize,
+    /// What `billable_seconds` earn, for users with an hourly rate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earnings: Option<Earnings>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryIssueKind {
+    /// The second task began before the first one ended.
+    Overlap,
+    /// The second task comes later in the history but began earlier.
+    OutOfOrder,
+}
+
+/// Two finished tasks that don't line up, by their index in the history.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct HistoryIssue {
+    pub kind: HistoryIssueKind,
+    pub first_index: usize,
+    pub first_begin_time: NaiveDateTime,
+    pub first_end_time: NaiveDateTime,
+    pub second_index: usize,
+    pub second_begin_time: NaiveDateTime,
+    pub second_end_time: NaiveDateTime,
+}
+
+impl HistoryIssue {
+    fn new(kind: HistoryIssueKind, first: (usize, &Task), second: (usize, &Task)) -> Self {
+        HistoryIssue {
+            kind,
+            first_index: first.0,
+            first_begin_time: first.1.begin_time,
+            first_end_time: first.1.end_time,
+            second_index: second.0,
+            second_begin_time: second.1.begin_time,
+            second_end_time: second.1.end_time,
+        }
+    }
+}
+
+/// Overlapping and out-of-order finished tasks, out-of-order ones first.
+///
+/// A task overlapping several later ones is reported once per overlap.
+pub fn find_history_issues(tasks: &[Task]) -> Vec<HistoryIssue> {
+    let finished: Vec<(usize, &Task)> = tasks
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.state == TaskState::End)
+        .collect();
+
+    let mut issues: Vec<HistoryIssue> = finished
+        .windows(2)
+        .filter(|pair| pair[1].1.begin_time < pair[0].1.begin_time)
+        .map(|pair| HistoryIssue::new(HistoryIssueKind::OutOfOrder, pair[0], pair[1]))
+        .collect();
+
+    let mut by_begin = finished;
+    by_begin.sort_by_key(|(_, t)| t.begin_time);
+    for (i, &later) in by_begin.iter().enumerate() {
+        for &earlier in &by_begin[..i] {
+            if later.1.begin_time < earlier.1.end_time {
+                issues.push(HistoryIssue::new(HistoryIssueKind::Overlap, earlier, later));
+            }
+        }
+    }
+    issues
+}
+
+/// The first finished task whose time overlaps `begin` up to `end`, or up
+/// to any later time with no `end` for a task still running. Tasks that only
+/// touch at an end don't overlap, as in `find_history_issues`.
+pub fn find_overlap<'a>(
+    tasks: impl IntoIterator<Item = &'a Task>,
+    begin: NaiveDateTime,
+    end: Option<NaiveDateTime>,
+) -> Option<&'a Task> {
+    tasks.into_iter().find(|t| {
+        t.state == TaskState::End && begin < t.end_time && end.is_none_or(|end| t.begin_time < end)
+    })
+}
+
+/// An idle gap between two finished tasks, proposed as a break. Nothing in
+/// the history is changed, see `infer_breaks`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct InferredBreak {
+    /// History index of the task the gap follows.
+    pub after_index: usize,
+    /// History index of the task the gap ends at.
+    pub before_index: usize,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub seconds: Seconds,
+}
+
+/// Gaps of at least `min_gap_seconds` between finished tasks, in time order.
+///
+/// Only gaps within a day count, the night between two days isn't a break.
+/// A task running inside another one doesn't end the gap-free stretch early.
+pub fn infer_breaks(tasks: &[Task], min_gap_seconds: Seconds) -> Vec<InferredBreak> {
+    let mut finished: Vec<(usize, &Task)> = tasks
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.state == TaskState::End)
+        .collect();
+    finished.sort_by_key(|(_, t)| t.begin_time);
+
+    let mut breaks = vec![];
+    let mut latest_end: Option<(usize, NaiveDateTime)> = None;
+    for (index, task) in finished {
+        if let Some((after_index, from)) = latest_end {
+            let seconds = Seconds::between(from, task.begin_time);
+            if seconds >= min_gap_seconds && from.date() == task.begin_time.date() {
+                breaks.push(InferredBreak {
+                    after_index,
+                    before_index: index,
+                    from,
+                    to: task.begin_time,
+                    seconds,
+                });
+            }
+        }
+        if latest_end.is_none_or(|(_, end)| task.end_time > end) {
+            latest_end = Some((index, task.end_time));
+        }
+    }
+    breaks
+}
+
+/// Finished tasks that began within the half-open `[from, to)`, the range
+/// every per-range total counts, see `UserRecord::total_duration_in_range`.
+pub fn finished_in_range(
+    tasks: &[Task],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> impl Iterator<Item = &Task> {
+    tasks
+        .iter()
+        .filter(move |t| t.state == TaskState::End && t.begin_time >= from && t.begin_time < to)
+}
+
+/// Seconds spent on breaks on `date`, counting finished tasks that began that day.
+pub fn break_seconds_on(tasks: &[Task], date: NaiveDate) -> Seconds {
+    tasks
+        .iter()
+        .filter(|t| t.state == TaskState::End && t.begin_time.date() == date)
+        .map(|t| t.total_break_seconds)
+        .sum()
+}
+
+/// Finished tasks that began within the half-open `[from, to)`, grouped by
+/// the day they began on, in history order.
+pub fn group_by_day(
+    tasks: &[Task],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> BTreeMap<NaiveDate, Vec<Task>> {
+    let mut days: BTreeMap<NaiveDate, Vec<Task>> = BTreeMap::new();
+    for task in finished_in_range(tasks, from, to) {
+        days.entry(task.begin_time.date())
+            .or_default()
+            .push(task.clone());
+    }
+    days
+}
+
+/// Total duration per task name over finished tasks.
+///
+/// With `roll_up`, a subtask's time is also added to every ancestor
+/// named through `parent`, so a parent's total covers its children.
+pub fn total_durations(tasks: &[Task], roll_up: bool) -> BTreeMap<String, Seconds> {
+    let parents: HashMap<&str, &str> = tasks
+        .iter()
+        .filter_map(|t| Some((t.name.as_str(), t.parent.as_deref()?)))
+        .filter(|(name, parent)| name != parent)
+        .collect();
+
+    let mut totals = BTreeMap::new();
+    for task in tasks.iter().filter(|t| t.state == TaskState::End) {
+        *totals.entry(task.name.clone()).or_default() += task.duration;
+        if !roll_up {
+            continue;
+        }
+
+        // Track visited names so a parent cycle can't loop forever.
+        let mut visited = HashSet::from([task.name.as_str()]);
+        let mut parent = task.parent.as_deref();
+        while let Some(name) = parent.filter(|name| visited.insert(name)) {
+            *totals.entry(name.to_string()).or_default() += task.duration;
+            parent = parents.get(name).copied();
+        }
+    }
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::normalize_task_name;
+
+    fn end_task(name: &str, parent: Option<&str>, duration: i64) -> Task {
+        Task {
+            name: name.to_string(),
+            state: TaskState::End,
+            duration: Seconds(duration),
+            parent: parent.map(str::to_string),
+            ..Task::default()
+        }
+    }
+
+    fn finished_between(name: &str, begin_time: NaiveDateTime, end_time: NaiveDateTime) -> Task {
+        Task {
+            begin_time,
+            end_time,
+            ..end_task(name, None, Seconds::between(begin_time, end_time).0)
+        }
+    }
+
+    #[test]
+    fn test_break_seconds_on_counts_only_that_day() {
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let mut yesterday_task = end_task("yesterday", None, 3600);
+        yesterday_task.begin_time = today.pred_opt().unwrap().and_hms_opt(9, 0, 0).unwrap();
+        let mut today_task = end_task("today", None, 1800);
+        today_task.begin_time = today.and_hms_opt(9, 0, 0).unwrap();
+        today_task.total_break_seconds = Seconds(600);
+        let mut active_task = today_task.clone();
+        active_task.state = TaskState::Begin;
+
+        let tasks = vec![yesterday_task, today_task, active_task];
+
+        assert_eq!(break_seconds_on(&tasks, today), Seconds(600));
+    }
+
+    #[test]
+    fn test_group_by_day_keeps_range_and_finished_tasks() {
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 1, 29).unwrap();
+        let at = |d: u64, h: u32| (day + chrono::Days::new(d)).and_hms_opt(h, 0, 0).unwrap();
+        let task_at = |name: &str, begin_time| {
+            finished_between(name, begin_time, begin_time + chrono::Duration::hours(1))
+        };
+        let mut active_task = task_at("active", at(1, 15));
+        active_task.state = TaskState::Begin;
+
+        let tasks = vec![
+            task_at("before", at(0, 9) - chrono::Duration::days(1)),
+            task_at("monday", at(0, 9)),
+            task_at("tuesday am", at(1, 9)),
+            task_at("tuesday pm", at(1, 14)),
+            active_task,
+        ];
+
+        let days = group_by_day(&tasks, at(0, 0), at(7, 0));
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[&day].len(), 1);
+        let tuesday: Vec<&str> = days[&at(1, 0).date()]
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(tuesday, vec!["tuesday am", "tuesday pm"]);
+    }
+
+    #[test]
+    fn test_total_durations_rolls_up_into_ancestors() {
+        let tasks = vec![
+            end_task("release", None, 600),
+            end_task("tests", Some("release"), 1200),
+            end_task("flaky test", Some("tests"), 300),
+        ];
+
+        let flat = total_durations(&tasks, false);
+        assert_eq!(flat["release"], Seconds(600));
+        assert_eq!(flat["tests"], Seconds(1200));
+
+        let rolled = total_durations(&tasks, true);
+        assert_eq!(rolled["release"], Seconds(2100));
+        assert_eq!(rolled["tests"], Seconds(1500));
+        assert_eq!(rolled["flaky test"], Seconds(300));
+    }
+
+    #[test]
+    fn test_total_durations_ignores_parent_cycles() {
+        let tasks = vec![
+            end_task("a", Some("b"), 60),
+            end_task("b", Some("a"), 60),
+            end_task("c", Some("c"), 60),
+        ];
+
+        let rolled = total_durations(&tasks, true);
+        assert_eq!(rolled["a"], Seconds(120));
+        assert_eq!(rolled["b"], Seconds(120));
+        assert_eq!(rolled["c"], Seconds(60));
+    }
+
+    #[test]
+    fn test_find_history_issues() {
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let at = |h: u32| day.and_hms_opt(h, 0, 0).unwrap();
+        let mut active = finished_between("active", at(9), at(9));
+        active.state = TaskState::Begin;
+
+        let tasks = vec![
+            finished_between("morning", at(8), at(12)),
+            active,
+            finished_between("standup", at(9), at(10)),
+            finished_between("late", at(13), at(14)),
+            finished_between("lunch", at(11), at(13)),
+        ];
+        let issues: Vec<(HistoryIssueKind, usize, usize)> = find_history_issues(&tasks)
+            .into_iter()
+            .map(|i| (i.kind, i.first_index, i.second_index))
+            .collect();
+
+        assert_eq!(
+            issues,
+            vec![
+                (HistoryIssueKind::OutOfOrder, 3, 4),
+                (HistoryIssueKind::Overlap, 0, 2),
+                (HistoryIssueKind::Overlap, 0, 4),
+            ]
+        );
+        assert!(find_history_issues(&tasks[2..4]).is_empty());
+    }
+
+    #[test]
+    fn test_find_overlap() {
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let at = |h: u32| day.and_hms_opt(h, 0, 0).unwrap();
+        let mut active = finished_between("active", at(9), at(9));
+        active.state = TaskState::Begin;
+        let tasks = vec![
+            active,
+            finished_between("morning", at(9), at(12)),
+            finished_between("late", at(14), at(15)),
+        ];
+
+        let name = |found: Option<&Task>| found.map(|t| t.name.clone());
+        assert_eq!(
+            name(find_overlap(&tasks, at(11), Some(at(13)))),
+            Some("morning".to_string())
+        );
+        // Touching ends and running tasks don't count.
+        assert_eq!(name(find_overlap(&tasks, at(12), Some(at(14)))), None);
+        assert_eq!(name(find_overlap(&tasks, at(8), Some(at(9)))), None);
+        // A task still running overlaps anything ending after its begin.
+        assert_eq!(
+            name(find_overlap(&tasks, at(13), None)),
+            Some("late".to_string())
+        );
+        assert_eq!(name(find_overlap(&tasks, at(15), None)), None);
+    }
+
+    #[test]
+    fn test_infer_breaks_from_gaps() {
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let at = |h: u32, m: u32| day.and_hms_opt(h, m, 0).unwrap();
+
+        let tasks = vec![
+            finished_between(
+                "yesterday",
+                at(17, 0) - chrono::Duration::days(1),
+                at(18, 0) - chrono::Duration::days(1),
+            ),
+            finished_between("morning", at(9, 0), at(12, 0)),
+            finished_between("inside", at(10, 0), at(10, 30)),
+            finished_between("coffee gap", at(12, 10), at(13, 0)),
+            finished_between("after lunch", at(14, 0), at(15, 0)),
+        ];
+        let breaks = infer_breaks(&tasks, Seconds(15 * 60));
+
+        assert_eq!(
+            breaks,
+            vec![InferredBreak {
+                after_index: 3,
+                before_index: 4,
+                from: at(13, 0),
+                to: at(14, 0),
+                seconds: Seconds(3600),
+            }]
+        );
+        assert_eq!(infer_breaks(&tasks, Seconds(10 * 60)).len(), 2);
+    }
+
+    #[test]
+    fn test_normalized_names_share_a_bucket() {
+        let tasks: Vec<Task> = [" Foo ", "foo", "Foo"]
+            .into_iter()
+            .map(|name| Task {
+                name: normalize_task_name(name, true),
+                ..end_task("", None, 600)
+            })
+            .collect();
+
+        let totals = total_durations(&tasks, false);
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals["foo"], Seconds(1800));
+    }
+}
@@ -0,0 +1,119 @@
+use bb8_redis::redis::{self, AsyncCommands};
+use libs::record::AuditEntry;
+
+use super::{namespace::KeyPrefix, RuntimeError};
+
+/// Opt-in, append-only log of the changes made to each record's tasks,
+/// shared through `AppState`. Each record's log is a Redis list of JSON
+/// entries, see `audit_key`, so it costs storage for every change.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AuditLog {
+    enabled: bool,
+}
+
+impl AuditLog {
+    pub fn new(enabled: bool) -> Self {
+        AuditLog { enabled }
+    }
+
+    /// Enabled by `IMON_AUDIT_LOG=true`, disabled by default.
+    pub fn from_env() -> Self {
+        AuditLog::new(
+            std::env::var("IMON_AUDIT_LOG")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+        )
+    }
+
+    /// Append `entries` to the log of the record at the unprefixed `key`,
+    /// unless the log is disabled.
+    pub async fn append(
+        &self,
+        key: &str,
+        key_prefix: &KeyPrefix,
+        entries: Vec<AuditEntry>,
+        con: &mut redis::aio::Connection,
+    ) -> Result<(), RuntimeError> {
+        if !self.enabled || entries.is_empty() {
+            return Ok(());
+        }
+        let values = entries
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<String>, _>>()?;
+        let _: () = con.rpush(audit_key(key_prefix, key), values).await?;
+        tracing::debug!("audit -> {} entries", entries.len());
+
+        Ok(())
+    }
+
+    /// `append` for entries about a change already written: the change
+    /// stands either way, so a failure is logged rather than returned.
+    pub async fn append_or_log(
+        &self,
+        key: &str,
+        key_prefix: &KeyPrefix,
+        entries: Vec<AuditEntry>,
+        con: &mut redis::aio::Connection,
+    ) {
+        if let Err(e) = self.append(key, key_prefix, entries, con).await {
+            tracing::error!("audit log of {:?} not appended: {:?}", key, e);
+        }
+    }
+}
+
+/// Redis key of the audit log of the record at the unprefixed `key`.
+pub fn audit_key(key_prefix: &KeyPrefix, key: &str) -> String {
+    key_prefix.apply(&format!("audit:{}", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use libs::record::{AuditOperation, STask, Task};
+
+    use super::*;
+
+    #[test]
+    fn test_should_keep_audit_log_next_to_record() {
+        assert_eq!(
+            audit_key(&KeyPrefix::new("staging"), "user:alice:0001"),
+            "staging:audit:user:alice:0001"
+        );
+    }
+
+    #[test]
+    fn test_should_take_task_id_from_either_side() {
+        let task = Task {
+            id: Some(4),
+            ..Task::generate_begin_task("writing".to_string())
+        };
+        let entry = AuditEntry::new(AuditOperation::CreateTask, vec![], vec![task.clone()]);
+        assert_eq!(entry.task_id, Some(4));
+
+        let wire = serde_json::json!(entry);
+        assert_eq!(wire["operation"], "create_task");
+        assert!(wire.get("before").is_none());
+
+        let entry = AuditEntry::new(AuditOperation::ResetRecord, vec![], vec![]);
+        assert_eq!(entry.task_id, None);
+    }
+
+    #[test]
+    fn test_should_keep_published_tasks_apart() {
+        let task = STask {
+            id: Some(2),
+            name: "review".to_string(),
+            description: "weekly".to_string(),
+            created_at: chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(9, 0, 0)
+                .unwrap(),
+        };
+        let entry = AuditEntry::published(AuditOperation::CreateTask, vec![], vec![task]);
+        assert_eq!(entry.task_id, Some(2));
+
+        let wire = serde_json::json!(entry);
+        assert_eq!(wire["published_after"][0]["name"], "review");
+        assert!(wire.get("after").is_none());
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use libs::{record::UserRecord, OperatingInfo};
+
+/// How long a cached `OperatingInfo` is considered fresh.
+const OPERATING_INFO_TTL: Duration = Duration::from_secs(5);
+
+/// How long a cached `UserRecord` is considered fresh.
+const USER_RECORD_TTL: Duration = Duration::from_secs(3);
+
+/// Short-lived in-process copy of `operating_info`, shared through `AppState`.
+///
+/// Only used to serve reads. Anything that writes to `operating_info`
+/// must call `invalidate` afterwards.
+#[derive(Clone, Default)]
+pub struct OperatingInfoCache(Arc<Mutex<Option<(OperatingInfo, Instant)>>>);
+
+impl OperatingInfoCache {
+    pub fn get(&self) -> Option<OperatingInfo> {
+        let guard = self.0.lock().unwrap();
+        match guard.as_ref() {
+            Some((info, cached_at)) if cached_at.elapsed() < OPERATING_INFO_TTL => {
+                Some(info.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn set(&self, info: OperatingInfo) {
+        *self.0.lock().unwrap() = Some((info, Instant::now()));
+    }
+
+    pub fn invalidate(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+/// Short-lived in-process copies of user records with their full history,
+/// by prefixed key, shared through `AppState`.
+///
+/// Only the stored record is kept. Anything derived from the current time,
+/// such as the seconds worked on an active task, is computed by the reader.
+/// Anything that writes to a user record must call `invalidate` for its key.
+#[derive(Clone, Default)]
+pub struct UserRecordCache(Arc<Mutex<HashMap<String, (UserRecord, Instant)>>>);
+
+impl UserRecordCache {
+    pub fn get(&self, key: &str) -> Option<UserRecord> {
+        let guard = self.0.lock().unwrap();
+        match guard.get(key) {
+            Some((record, cached_at)) if cached_at.elapsed() < USER_RECORD_TTL => {
+                Some(record.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Cache `record` for `key`, dropping expired entries on the way so
+    /// the map doesn't grow with every key ever read.
+    pub fn set(&self, key: &str, record: UserRecord) {
+        let mut guard = self.0.lock().unwrap();
+        guard.retain(|_, (_, cached_at)| cached_at.elapsed() < USER_RECORD_TTL);
+        guard.insert(key.to_string(), (record, Instant::now()));
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        self.0.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libs::record::{Seconds, Task};
+
+    use super::*;
+
+    fn operating_info() -> OperatingInfo {
+        OperatingInfo {
+            latest_record_id: 1,
+            latest_sudo_record_id: 0,
+            user_list: vec!["user:alice:0001".to_string()],
+            sudo_user_list: vec![],
+        }
+    }
+
+    #[test]
+    fn test_should_serve_cached_info_until_invalidated() {
+        let cache = OperatingInfoCache::default();
+        assert!(cache.get().is_none());
+
+        cache.set(operating_info());
+        assert_eq!(cache.get().unwrap().user_list, operating_info().user_list);
+
+        cache.invalidate();
+        assert!(cache.get().is_none());
+    }
+
+    #[test]
+    fn test_should_serve_cached_record_per_key_until_invalidated() {
+        let cache = UserRecordCache::default();
+        let record = UserRecord {
+            current_task: Task::generate_begin_task("writing".to_string()),
+            ..UserRecord::new(1, "alice".to_string())
+        };
+        cache.set("user:alice:0001", record);
+
+        assert!(cache.get("user:bob:0002").is_none());
+        let cached = cache.get("user:alice:0001").unwrap();
+        assert_eq!(cached.user_name, "alice");
+        // Live values are still computed from the cached record.
+        assert!(cached.current_task.worked_seconds() >= Seconds(0));
+
+        cache.invalidate("user:alice:0001");
+        assert!(cache.get("user:alice:0001").is_none());
+    }
+}
@@ -0,0 +1,186 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use libs::record::UserRecord;
+
+use super::RuntimeError;
+
+/// Strong validator of a user record response: FNV-1a over the record as it
+/// is served, so it is the same across builds and changes with every field.
+pub fn record_etag(record: &UserRecord) -> String {
+    let json = serde_json::to_vec(record).unwrap_or_default();
+    format!("\"{:016x}\"", fnv1a(&json))
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Entity tags listed in every `name` header, `None` when there is none.
+fn listed_tags(headers: &HeaderMap, name: header::HeaderName) -> Option<Vec<String>> {
+    let tags: Vec<String> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().to_string())
+        .collect();
+    (!tags.is_empty()).then_some(tags)
+}
+
+/// Whether `If-None-Match` lists `etag`. Weak comparison, as GET-like reads allow.
+fn is_not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim_start_matches("W/").to_string();
+    listed_tags(headers, header::IF_NONE_MATCH)
+        .unwrap_or_default()
+        .iter()
+        .any(|tag| tag == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Whether an `If-Match` header rules out every one of `etags`. Strong
+/// comparison, so a weak tag never matches.
+fn is_precondition_failed(headers: &HeaderMap, etags: &[String]) -> bool {
+    let Some(tags) = listed_tags(headers, header::IF_MATCH) else {
+        return false;
+    };
+    !tags
+        .iter()
+        .any(|tag| tag == "*" || (!tag.starts_with("W/") && etags.contains(tag)))
+}
+
+/// Refuse a write to `record` unless `If-Match`, when sent, lists its current
+/// `ETag`, as read with or without history.
+pub fn check_if_match(headers: &HeaderMap, record: &UserRecord) -> Result<(), RuntimeError> {
+    let without_history = UserRecord {
+        task_history: vec![],
+        ..record.clone()
+    };
+    let etag = record_etag(record);
+    let etags = [etag.clone(), record_etag(&without_history)];
+    if is_precondition_failed(headers, &etags) {
+        return Err(RuntimeError::PreconditionFailed { etag });
+    }
+    Ok(())
+}
+
+/// `412 Precondition Failed` when `If-Match` rules out `etag`, `304 Not
+/// Modified` when the client already has it, otherwise `body`. All carry
+/// the `ETag` header.
+pub fn respond_with_etag(headers: &HeaderMap, etag: &str, body: serde_json::Value) -> Response {
+    let mut response = if is_precondition_failed(headers, &[etag.to_string()]) {
+        StatusCode::PRECONDITION_FAILED.into_response()
+    } else if is_not_modified(headers, etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        Json(body).into_response()
+    };
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use libs::record::{Seconds, Task, TaskState};
+
+    use super::*;
+
+    fn record() -> UserRecord {
+        UserRecord::new(1, "alice".to_string())
+    }
+
+    fn if_match(tag: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MATCH, HeaderValue::from_str(tag).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_should_change_etag_with_record() {
+        let before = record();
+        let etag = record_etag(&before);
+        assert_eq!(etag, record_etag(&before));
+
+        let after = UserRecord {
+            task_history: vec![before.current_task.clone()],
+            ..record()
+        };
+        assert_ne!(etag, record_etag(&after));
+
+        let worked = UserRecord {
+            current_task: Task {
+                duration: Seconds(60),
+                ..before.current_task.clone()
+            },
+            ..record()
+        };
+        assert_ne!(etag, record_etag(&worked));
+    }
+
+    #[test]
+    fn test_should_keep_etag_across_builds() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn test_should_answer_not_modified_to_matching_etag() {
+        let etag = record_etag(&record());
+        let body = serde_json::json!({ "status": "ok" });
+
+        let first = respond_with_etag(&HeaderMap::new(), &etag, body.clone());
+        assert_eq!(first.status(), StatusCode::OK);
+        let returned = first.headers()[header::ETAG].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, returned);
+        let second = respond_with_etag(&headers, &etag, body.clone());
+        assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(second.headers()[header::ETAG], etag.as_str());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("W/\"0000000000000000\""),
+        );
+        assert_eq!(
+            respond_with_etag(&headers, &etag, body.clone()).status(),
+            StatusCode::OK
+        );
+
+        let failed = respond_with_etag(&if_match("\"0000000000000000\""), &etag, body);
+        assert_eq!(failed.status(), StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[test]
+    fn test_should_check_if_match_before_writes() {
+        let current = UserRecord {
+            task_history: vec![Task::placeholder("writing", TaskState::End)],
+            ..record()
+        };
+        let without_history = UserRecord {
+            task_history: vec![],
+            ..current.clone()
+        };
+
+        assert!(check_if_match(&HeaderMap::new(), &current).is_ok());
+        assert!(check_if_match(&if_match("*"), &current).is_ok());
+        assert!(check_if_match(&if_match(&record_etag(&current)), &current).is_ok());
+        assert!(check_if_match(&if_match(&record_etag(&without_history)), &current).is_ok());
+
+        let weak = format!("W/{}", record_etag(&current));
+        assert!(matches!(
+            check_if_match(&if_match(&weak), &current),
+            Err(RuntimeError::PreconditionFailed { .. })
+        ));
+        assert!(matches!(
+            check_if_match(&if_match(&record_etag(&record())), &current),
+            Err(RuntimeError::PreconditionFailed { etag }) if etag == record_etag(&current)
+        ));
+    }
+}
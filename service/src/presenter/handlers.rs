@@ -1,27 +1,49 @@
 use axum::{
     async_trait,
-    extract::{rejection::JsonRejection, FromRequest, Request as AxumExtractRequest, State},
-    http::StatusCode,
+    extract::{
+        rejection::JsonRejection, FromRequest, FromRequestParts, Query,
+        Request as AxumExtractRequest, State,
+    },
+    http::{header, request::Parts, HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
 
 use super::{
     construct_err_resp_invalid_incoming_json,
+    etag::{check_if_match, record_etag, respond_with_etag},
     logic::{
-        perform_create_task, perform_get_all_user_records, perform_get_user_record,
-        perform_register_record, perform_reset_record, perform_sudo_create_task,
+        perform_create_task, perform_delete_record, perform_get_all_user_records,
+        perform_get_audit_log, perform_get_by_id, perform_get_grouped_stats,
+        perform_get_stats_overview, perform_get_task, perform_get_user_record,
+        perform_get_user_summaries, perform_import_tasks_csv, perform_register_record,
+        perform_register_records_bulk, perform_rename_tasks, perform_reset_record,
+        perform_set_goal, perform_set_rate, perform_split_task, perform_sudo_create_task,
         perform_sudo_get_record, perform_sudo_register_record, perform_sudo_reset_record,
-        perform_update_task,
+        perform_sudo_update_task, perform_update_settings, perform_update_task,
+        perform_validate_history,
     },
-    RpcPayloadType, RuntimeError, SudoUserRpcEventPayload, SudoUserRpcRequest, UserRpcEventPayload,
-    UserRpcRequest,
+    span::{record_count, record_identity, RequestIdentity},
+    validate::Validate,
+    RpcPayloadType, RuntimeError, SudoUserRpcEventPayload, SudoUserRpcEventType,
+    SudoUserRpcRequest, UserRpcEventPayload, UserRpcEventType, UserRpcRequest,
 };
 use crate::{presenter::logic::perform_get_all_sudo_records, AppState};
-use libs::payload::{
-    GetSingleRecordPayload, RegisterRecordPayload, ResetRecordPayload, StoreTaskPayload,
-    UpdateTaskPayload,
+use libs::{
+    payload::{
+        DeleteRecordPayload, GetByIdPayload, GetSingleRecordPayload, GetTaskPayload,
+        GroupedStatsPayload, ImportQuery, ListRecordsQuery, PageQuery, RecordFilter,
+        RecordKeyQuery, RegisterRecordPayload, RegisterRecordsBulkPayload, RenameTasksPayload,
+        ResetRecordPayload, SetGoalPayload, SetRatePayload, SplitTaskPayload, StoreTaskPayload,
+        UpdateSettingsPayload, UpdateTaskPayload,
+    },
+    response::{
+        AllRecordsResponse, RecordResponse, RegisterResponse, ServerTimeData, ServerTimeResponse,
+        StatusResponse, TaskLogData, UserKeyData, UserRecordsData,
+    },
+    ADMIN_SECRET_HEADER,
 };
+use tracing::Span;
 
 #[derive(Debug)]
 pub struct ValidatedJson<T>(pub T);
@@ -30,37 +52,162 @@ pub struct ValidatedJson<T>(pub T);
 impl<S, T> FromRequest<S> for ValidatedJson<T>
 where
     axum::Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    T: RequestIdentity + Validate,
     S: Send + Sync,
 {
-    type Rejection = (StatusCode, axum::Json<serde_json::Value>);
+    type Rejection = axum::response::Response;
 
     async fn from_request(req: AxumExtractRequest, state: &S) -> Result<Self, Self::Rejection> {
         match axum::Json::<T>::from_request(req, state).await {
-            Ok(json) => Ok(Self(json.0)),
+            Ok(json) => {
+                record_identity(&Span::current(), &json.0);
+                json.0.validate().map_err(IntoResponse::into_response)?;
+                Ok(Self(json.0))
+            }
             Err(rejection) => {
                 tracing::error!("{:?}", rejection);
-                let err_resp = construct_err_resp_invalid_incoming_json(&rejection);
-                Err(err_resp)
+                let err_resp =
+                    construct_err_resp_invalid_incoming_json(&rejection, &T::event_types());
+                Err(err_resp.into_response())
             }
         }
     }
 }
 
+/// Guards admin-only endpoints with the secret configured in `IMON_ADMIN_SECRET`.
+/// When no secret is configured, the endpoints stay open.
+#[derive(Debug)]
+pub struct RequireAdmin;
+
+#[async_trait]
+impl FromRequestParts<AppState> for RequireAdmin {
+    type Rejection = RuntimeError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        check_admin_secret(&parts.headers, state.admin_secret.as_deref())?;
+        Ok(Self)
+    }
+}
+
+/// What `RequireAdmin` checks, for handlers where only some events are
+/// admin-only.
+fn check_admin_secret(headers: &HeaderMap, admin_secret: Option<&str>) -> Result<(), RuntimeError> {
+    let Some(admin_secret) = admin_secret else {
+        return Ok(());
+    };
+
+    match headers.get(ADMIN_SECRET_HEADER) {
+        Some(secret) if secret.as_bytes() == admin_secret.as_bytes() => Ok(()),
+        _ => {
+            tracing::error!("rejected admin request: missing or invalid secret");
+            Err(RuntimeError::Unauthorized)
+        }
+    }
+}
+
+/// Refuse a write when `If-Match` is sent and no longer lists the record's
+/// `ETag`. The record is only read when the header is there.
+async fn check_record_precondition(
+    headers: &HeaderMap,
+    app_state: &AppState,
+    key: &str,
+) -> Result<(), RuntimeError> {
+    if !headers.contains_key(header::IF_MATCH) {
+        return Ok(());
+    }
+    let record = perform_get_user_record(
+        GetSingleRecordPayload {
+            key: key.to_string(),
+            include_history: true,
+        },
+        app_state.redis_pool.clone(),
+        app_state.key_prefix.clone(),
+        app_state.user_record_cache.clone(),
+    )
+    .await?;
+    check_if_match(headers, &record)
+}
+
 pub async fn create_task(
     State(app_state): State<AppState>,
+    headers: HeaderMap,
     ValidatedJson(payload): ValidatedJson<StoreTaskPayload>,
 ) -> Result<impl IntoResponse, RuntimeError> {
-    perform_create_task(payload, app_state.redis_pool).await?;
-    Ok(Json(serde_json::json!({
-    "status": "ok",
-    })))
+    check_record_precondition(&headers, &app_state, &payload.key).await?;
+    perform_create_task(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+        app_state.lowercase_task_names,
+        app_state.appends_per_minute,
+        app_state.audit_log,
+    )
+    .await?;
+    Ok(Json(StatusResponse::ok()))
+}
+
+pub async fn import_tasks_csv(
+    State(app_state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<impl IntoResponse, RuntimeError> {
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/csv"));
+    if !is_csv {
+        return Ok((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": "Missing CSV body, set `Content-Type: text/csv`",
+            })),
+        ));
+    }
+
+    Span::current().record("key", query.key.as_str());
+    check_record_precondition(&headers, &app_state, &query.key).await?;
+    let imported = perform_import_tasks_csv(
+        query,
+        &body,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+        app_state.lowercase_task_names,
+        app_state.audit_log,
+    )
+    .await?;
+    record_count(imported);
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "status": "ok",
+            "data": {
+                "imported": imported,
+            }
+        })),
+    ))
 }
 
 pub async fn reset_task(
     State(app_state): State<AppState>,
+    headers: HeaderMap,
     ValidatedJson(payload): ValidatedJson<ResetRecordPayload>,
 ) -> Result<impl IntoResponse, RuntimeError> {
-    let user_data = perform_reset_record(payload, app_state.redis_pool).await?;
+    check_record_precondition(&headers, &app_state, &payload.key).await?;
+    let user_data = perform_reset_record(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+        app_state.audit_log,
+    )
+    .await?;
     Ok(Json(serde_json::json!({
         "status": "ok",
         "data": {
@@ -73,83 +220,448 @@ pub async fn register_record(
     State(app_state): State<AppState>,
     ValidatedJson(payload): ValidatedJson<RegisterRecordPayload>,
 ) -> Result<impl IntoResponse, RuntimeError> {
-    let user_key = perform_register_record(payload, app_state.redis_pool).await?;
+    let user_key = perform_register_record(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.operating_info_cache,
+    )
+    .await?;
+    Ok(Json(RegisterResponse::ok(UserKeyData { user_key })))
+}
+
+pub async fn delete_record(
+    _: RequireAdmin,
+    State(app_state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<DeleteRecordPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    perform_delete_record(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.operating_info_cache,
+        app_state.user_record_cache,
+    )
+    .await?;
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+    })))
+}
+
+pub async fn register_records_bulk(
+    _: RequireAdmin,
+    State(app_state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<RegisterRecordsBulkPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    let user_keys = perform_register_records_bulk(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.operating_info_cache,
+    )
+    .await?;
+    record_count(user_keys.len());
     Ok(Json(serde_json::json!({
         "status": "ok",
         "data": {
-            "user_key": user_key,
+            "user_keys": user_keys,
         }
     })))
 }
 
 pub async fn get_all_user_records(
+    _: RequireAdmin,
+    State(app_state): State<AppState>,
+    Query(query): Query<ListRecordsQuery>,
+    Query(page): Query<PageQuery>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    let user_records = perform_get_all_user_records(
+        RecordFilter::All,
+        query.include_history,
+        query.query.as_deref(),
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.operating_info_cache,
+    )
+    .await?;
+    let (user_records, page) = page.paginate(user_records);
+    record_count(user_records.len());
+    Ok(Json(AllRecordsResponse::ok(
+        UserRecordsData { user_records },
+        page,
+    )))
+}
+
+pub async fn get_user_summaries(
+    _: RequireAdmin,
+    State(app_state): State<AppState>,
+    Query(page): Query<PageQuery>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    let user_summaries = perform_get_user_summaries(
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.operating_info_cache,
+    )
+    .await?;
+    let (user_summaries, page) = page.paginate(user_summaries);
+    record_count(user_summaries.len());
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "data": {
+            "user_summaries": user_summaries,
+        },
+        "page": page,
+    })))
+}
+
+/// Liveness probe, doesn't touch Redis.
+pub async fn health() -> impl IntoResponse {
+    Json(serde_json::json!({
+        "status": "ok",
+    }))
+}
+
+/// Version of the running service, from its manifest.
+pub async fn version() -> impl IntoResponse {
+    Json(serde_json::json!({
+        "status": "ok",
+        "data": {
+            "version": env!("CARGO_PKG_VERSION"),
+        }
+    }))
+}
+
+/// The service's clock, which stamps the tasks it changes, for clients to
+/// compare theirs with.
+pub async fn server_time() -> impl IntoResponse {
+    let now = chrono::Local::now();
+    Json(ServerTimeResponse::ok(ServerTimeData {
+        time: now.fixed_offset(),
+        utc_offset: now.format("%:z").to_string(),
+    }))
+}
+
+/// Event names each RPC endpoint understands, by the `of` it expects.
+pub async fn list_rpc_events() -> impl IntoResponse {
+    use strum::IntoEnumIterator;
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "data": {
+            "user": UserRpcEventType::iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            "sudo": SudoUserRpcEventType::iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        },
+    }))
+}
+
+pub async fn get_stats_overview(
+    State(app_state): State<AppState>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    let overview = perform_get_stats_overview(
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.operating_info_cache,
+    )
+    .await?;
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "data": overview,
+    })))
+}
+
+pub async fn get_grouped_stats(
+    State(app_state): State<AppState>,
+    Query(page): Query<PageQuery>,
+    ValidatedJson(payload): ValidatedJson<GroupedStatsPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    let groups =
+        perform_get_grouped_stats(payload, app_state.redis_pool, app_state.key_prefix).await?;
+    let (groups, page) = page.paginate(groups);
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "data": {
+            "groups": groups,
+        },
+        "page": page,
+    })))
+}
+
+pub async fn validate_history(
     State(app_state): State<AppState>,
+    Query(query): Query<RecordKeyQuery>,
 ) -> Result<impl IntoResponse, RuntimeError> {
-    let user_records = perform_get_all_user_records(app_state.redis_pool).await?;
+    Span::current().record("key", query.key.as_str());
+    let issues =
+        perform_validate_history(query, app_state.redis_pool, app_state.key_prefix).await?;
     Ok(Json(serde_json::json!({
         "status": "ok",
         "data": {
-            "user_records": user_records,
+            "issues": issues,
         }
     })))
 }
 
+pub async fn get_audit_log(
+    State(app_state): State<AppState>,
+    Query(query): Query<RecordKeyQuery>,
+    Query(page): Query<PageQuery>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    Span::current().record("key", query.key.as_str());
+    let entries = perform_get_audit_log(query, app_state.redis_pool, app_state.key_prefix).await?;
+    let (entries, page) = page.paginate(entries);
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "data": {
+            "entries": entries,
+        },
+        "page": page,
+    })))
+}
+
 pub async fn get_user_record(
     State(app_state): State<AppState>,
+    headers: HeaderMap,
     ValidatedJson(payload): ValidatedJson<GetSingleRecordPayload>,
 ) -> Result<impl IntoResponse, RuntimeError> {
-    let task_log = perform_get_user_record(payload, app_state.redis_pool).await?;
+    let task_log = perform_get_user_record(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+    )
+    .await?;
+    let etag = record_etag(&task_log);
+    Ok(respond_with_etag(
+        &headers,
+        &etag,
+        serde_json::json!(RecordResponse::ok(TaskLogData { task_log })),
+    ))
+}
+
+pub async fn get_record_by_id(
+    _: RequireAdmin,
+    State(app_state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<GetByIdPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    let (key, record) = perform_get_by_id(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.operating_info_cache,
+        app_state.user_record_cache,
+    )
+    .await?;
     Ok(Json(serde_json::json!({
         "status": "ok",
         "data": {
-            "task_log": task_log,
+            "key": key,
+            "record": record,
         }
     })))
 }
 
+pub async fn set_goal(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<SetGoalPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    check_record_precondition(&headers, &app_state, &payload.key).await?;
+    perform_set_goal(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+    )
+    .await?;
+    Ok(Json(StatusResponse::ok()))
+}
+
+pub async fn update_settings(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<UpdateSettingsPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    check_record_precondition(&headers, &app_state, &payload.key).await?;
+    perform_update_settings(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+    )
+    .await?;
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+    })))
+}
+
+pub async fn set_rate(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<SetRatePayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    check_record_precondition(&headers, &app_state, &payload.key).await?;
+    perform_set_rate(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+    )
+    .await?;
+    Ok(Json(StatusResponse::ok()))
+}
+
 pub async fn update_task_log(
     State(app_state): State<AppState>,
+    headers: HeaderMap,
     ValidatedJson(payload): ValidatedJson<UpdateTaskPayload>,
 ) -> Result<impl IntoResponse, RuntimeError> {
-    perform_update_task(payload, app_state.redis_pool).await?;
+    check_record_precondition(&headers, &app_state, &payload.key).await?;
+    perform_update_task(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+        app_state.audit_log,
+    )
+    .await?;
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+    })))
+}
+
+pub async fn get_task(
+    State(app_state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<GetTaskPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    let task = perform_get_task(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+    )
+    .await?;
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "data": {
+            "task": task,
+        }
+    })))
+}
+
+pub async fn split_task(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<SplitTaskPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    check_record_precondition(&headers, &app_state, &payload.key).await?;
+    let (first, second) = perform_split_task(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+        app_state.audit_log,
+    )
+    .await?;
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "data": {
+            "tasks": [first, second],
+        }
+    })))
+}
+
+pub async fn rename_tasks(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<RenameTasksPayload>,
+) -> Result<impl IntoResponse, RuntimeError> {
+    check_record_precondition(&headers, &app_state, &payload.key).await?;
+    let renamed = perform_rename_tasks(
+        payload,
+        app_state.redis_pool,
+        app_state.key_prefix,
+        app_state.user_record_cache,
+        app_state.lowercase_task_names,
+        app_state.audit_log,
+    )
+    .await?;
     Ok(Json(serde_json::json!({
         "status": "ok",
+        "data": {
+            "renamed": renamed,
+        }
     })))
 }
 
 pub async fn user_rpc(
     State(app_state): State<AppState>,
+    headers: HeaderMap,
     ValidatedJson(request): ValidatedJson<UserRpcRequest>,
 ) -> Result<impl IntoResponse, RuntimeError> {
     tracing::debug!("request: {:?}", request);
     match request.metadata.of {
         RpcPayloadType::User => match request.payload {
             UserRpcEventPayload::RegisterRecord(payload) => {
-                perform_register_record(payload, app_state.redis_pool).await?;
+                perform_register_record(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.operating_info_cache,
+                )
+                .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                 })))
             }
             UserRpcEventPayload::AddTask(payload) => {
-                perform_create_task(payload, app_state.redis_pool).await?;
+                perform_create_task(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.user_record_cache,
+                    app_state.lowercase_task_names,
+                    app_state.appends_per_minute,
+                    app_state.audit_log,
+                )
+                .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                 })))
             }
             UserRpcEventPayload::UpdateTask(payload) => {
-                perform_update_task(payload, app_state.redis_pool).await?;
+                perform_update_task(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.user_record_cache,
+                    app_state.audit_log,
+                )
+                .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                 })))
             }
             UserRpcEventPayload::ResetRecord(payload) => {
-                perform_reset_record(payload, app_state.redis_pool).await?;
+                perform_reset_record(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.user_record_cache,
+                    app_state.audit_log,
+                )
+                .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                 })))
             }
             UserRpcEventPayload::GetSingleRecord(payload) => {
-                let record = perform_get_user_record(payload, app_state.redis_pool).await?;
+                let record = perform_get_user_record(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.user_record_cache,
+                )
+                .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                     "data": {
@@ -157,8 +669,19 @@ pub async fn user_rpc(
                 }
                 })))
             }
-            UserRpcEventPayload::GetAllRecord => {
-                let records = perform_get_all_user_records(app_state.redis_pool).await?;
+            UserRpcEventPayload::GetAllRecord(payload) => {
+                check_admin_secret(&headers, app_state.admin_secret.as_deref())?;
+                // Kept with history, as before `include_history` existed on REST.
+                let records = perform_get_all_user_records(
+                    payload.filter,
+                    true,
+                    None,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.operating_info_cache,
+                )
+                .await?;
+                record_count(records.len());
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                     "data": {
@@ -175,38 +698,79 @@ pub async fn user_rpc(
 
 pub async fn sudo_user_rpc(
     State(app_state): State<AppState>,
+    headers: HeaderMap,
     ValidatedJson(request): ValidatedJson<SudoUserRpcRequest>,
 ) -> Result<impl IntoResponse, RuntimeError> {
     tracing::debug!("request: {:?}", request);
     match request.metadata.of {
         RpcPayloadType::Sudo => match request.payload {
             SudoUserRpcEventPayload::RegisterRecord(payload) => {
-                perform_sudo_register_record(payload, app_state.redis_pool).await?;
+                perform_sudo_register_record(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.operating_info_cache,
+                )
+                .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                 })))
             }
             SudoUserRpcEventPayload::AddTask(payload) => {
-                perform_sudo_create_task(payload, app_state.redis_pool).await?;
+                perform_sudo_create_task(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.audit_log,
+                )
+                .await?;
+                Ok(Json(serde_json::json!({
+                    "status": "ok",
+                })))
+            }
+            SudoUserRpcEventPayload::UpdateTask(payload) => {
+                let task = perform_sudo_update_task(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.audit_log,
+                )
+                .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
+                    "data": task
                 })))
             }
             SudoUserRpcEventPayload::ResetRecord(payload) => {
-                perform_sudo_reset_record(payload, app_state.redis_pool).await?;
+                perform_sudo_reset_record(
+                    payload,
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.audit_log,
+                )
+                .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                 })))
             }
             SudoUserRpcEventPayload::GetSingleRecord(payload) => {
-                let record = perform_sudo_get_record(payload, app_state.redis_pool).await?;
+                let record =
+                    perform_sudo_get_record(payload, app_state.redis_pool, app_state.key_prefix)
+                        .await?;
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                     "data": record
                 })))
             }
             SudoUserRpcEventPayload::GetAllRecord => {
-                let records = perform_get_all_sudo_records(app_state.redis_pool).await?;
+                check_admin_secret(&headers, app_state.admin_secret.as_deref())?;
+                let records = perform_get_all_sudo_records(
+                    app_state.redis_pool,
+                    app_state.key_prefix,
+                    app_state.operating_info_cache,
+                )
+                .await?;
+                record_count(records.len());
                 Ok(Json(serde_json::json!({
                     "status": "ok",
                     "data": {
@@ -0,0 +1,152 @@
+use chrono::NaiveDateTime;
+use libs::record::{Seconds, Task, TaskState};
+use serde::{Deserialize, Serialize};
+
+/// Columns of an imported task, in this order with a header row.
+pub const TASK_CSV_HEADER: [&str; 5] = ["name", "state", "begin_time", "end_time", "duration"];
+
+/// Why a CSV line was rejected. `line` is 1-based and counts the header.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CsvRowError {
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct TaskRow {
+    name: String,
+    state: TaskState,
+    begin_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    duration: i64,
+}
+
+impl TaskRow {
+    /// Only finished tasks can be imported: anything else would need to be
+    /// ended later, and the history only grows at its end.
+    fn into_task(self) -> Result<Task, String> {
+        if self.name.trim().is_empty() {
+            return Err("name is empty".to_string());
+        }
+        if self.state != TaskState::End {
+            return Err(format!("state must be End, got {:?}", self.state));
+        }
+        if self.end_time < self.begin_time {
+            return Err("end_time is before begin_time".to_string());
+        }
+        let span = (self.end_time - self.begin_time).num_seconds();
+        if !(0..=span).contains(&self.duration) {
+            return Err(format!("duration must be between 0 and {} seconds", span));
+        }
+
+        Ok(Task {
+            name: self.name,
+            state: self.state,
+            begin_time: self.begin_time,
+            end_time: self.end_time,
+            duration: Seconds(self.duration),
+            ..Task::default()
+        })
+    }
+}
+
+fn describe_csv_error(err: csv::Error) -> String {
+    match err.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+        _ => err.to_string(),
+    }
+}
+
+/// Parse every row of a task CSV, or report all the rows that are wrong.
+pub fn parse_task_csv(body: &str) -> Result<Vec<Task>, Vec<CsvRowError>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+
+    let headers = match reader.headers() {
+        Ok(headers) if headers.iter().eq(TASK_CSV_HEADER) => headers.clone(),
+        _ => {
+            return Err(vec![CsvRowError {
+                line: 1,
+                message: format!("header must be `{}`", TASK_CSV_HEADER.join(",")),
+            }])
+        }
+    };
+
+    let mut tasks = vec![];
+    let mut errors = vec![];
+    for result in reader.records() {
+        let (position, row) = match result {
+            Ok(record) => (
+                record.position().cloned(),
+                record
+                    .deserialize::<TaskRow>(Some(&headers))
+                    .map_err(describe_csv_error),
+            ),
+            Err(e) => (e.position().cloned(), Err(describe_csv_error(e))),
+        };
+        let line = position.map_or(0, |p| p.line());
+        match row.and_then(TaskRow::into_task) {
+            Ok(task) => tasks.push(task),
+            Err(message) => errors.push(CsvRowError { line, message }),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    if tasks.is_empty() {
+        return Err(vec![CsvRowError {
+            line: 1,
+            message: "no rows to import".to_string(),
+        }]);
+    }
+    Ok(tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_parse_finished_tasks() {
+        let tasks = parse_task_csv(
+            "name,state,begin_time,end_time,duration\n\
+             writing,End,2024-01-31T09:00:00,2024-01-31T10:00:00,3600\n\
+             review, End ,2024-01-31T11:00:00,2024-01-31T11:30:00,1500\n",
+        )
+        .unwrap();
+
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].name, "writing");
+        assert_eq!(tasks[0].duration, Seconds(3600));
+        assert_eq!(tasks[1].name, "review");
+        assert_eq!(tasks[1].total_break_seconds, Seconds(0));
+    }
+
+    #[test]
+    fn test_should_report_every_bad_row_with_its_line() {
+        let errors = parse_task_csv(
+            "name,state,begin_time,end_time,duration\n\
+             writing,End,2024-01-31T09:00:00,2024-01-31T10:00:00,3600\n\
+             ,End,2024-01-31T09:00:00,2024-01-31T10:00:00,60\n\
+             review,Begin,2024-01-31T11:00:00,2024-01-31T11:30:00,0\n\
+             review,End,yesterday,2024-01-31T11:30:00,0\n\
+             review,End,2024-01-31T11:30:00,2024-01-31T11:00:00,0\n\
+             review,End,2024-01-31T11:00:00,2024-01-31T11:30:00,3600\n",
+        )
+        .unwrap_err();
+
+        let lines: Vec<u64> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6, 7]);
+        assert_eq!(errors[0].message, "name is empty");
+        assert!(errors[1].message.contains("End"));
+    }
+
+    #[test]
+    fn test_should_reject_unexpected_header() {
+        let errors = parse_task_csv("task,started\nwriting,2024-01-31T09:00:00\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 1);
+    }
+}
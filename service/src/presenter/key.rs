@@ -0,0 +1,92 @@
+use libs::UserType;
+
+use super::RuntimeError;
+
+/// Format of a record key, as shown to clients rejecting a malformed one.
+pub const KEY_FORMAT: &str = "<user|sudo>:<user_name>:<id>";
+
+/// The parts of a record key, see `KEY_FORMAT`. Keys don't carry the
+/// namespace prefix.
+#[derive(Debug)]
+pub struct ParsedKey<'a> {
+    pub user_type: UserType,
+    pub user_name: &'a str,
+    pub id: i32,
+}
+
+impl<'a> TryFrom<&'a str> for ParsedKey<'a> {
+    type Error = RuntimeError;
+
+    fn try_from(key: &'a str) -> Result<Self, Self::Error> {
+        let invalid = || RuntimeError::InvalidKey {
+            key: key.to_string(),
+        };
+
+        let mut segments = key.split(':');
+        let (Some(user_type), Some(user_name), Some(id), None) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) else {
+            return Err(invalid());
+        };
+
+        let user_type = if user_type == UserType::User.to_string() {
+            UserType::User
+        } else if user_type == UserType::SudoUser.to_string() {
+            UserType::SudoUser
+        } else {
+            return Err(invalid());
+        };
+        if user_name.is_empty() {
+            return Err(invalid());
+        }
+        let id = id
+            .parse::<i32>()
+            .ok()
+            .filter(|id| *id >= 0)
+            .ok_or_else(invalid)?;
+
+        Ok(ParsedKey {
+            user_type,
+            user_name,
+            id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_parse_record_keys() {
+        let key = ParsedKey::try_from("sudo:bob:0042").unwrap();
+        assert!(matches!(key.user_type, UserType::SudoUser));
+        assert_eq!(key.user_name, "bob");
+        assert_eq!(key.id, 42);
+
+        let key = ParsedKey::try_from("user:carol:10000").unwrap();
+        assert!(matches!(key.user_type, UserType::User));
+        assert_eq!(key.id, 10000);
+    }
+
+    #[test]
+    fn test_should_reject_malformed_keys() {
+        for key in [
+            "user:alice",
+            "user:alice:",
+            "user::0001",
+            "admin:alice:0001",
+            "user:alice:-1",
+            "user:alice:0001:extra",
+            "",
+        ] {
+            match ParsedKey::try_from(key) {
+                Err(RuntimeError::InvalidKey { key: rejected }) => assert_eq!(rejected, key),
+                other => panic!("unexpected result for {:?}: {:?}", key, other),
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use std::iter::successors;
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::RangeInclusive,
+};
 
 use bb8_redis::{
     bb8::Pool,
@@ -6,26 +9,70 @@ use bb8_redis::{
     RedisConnectionManager,
 };
 
-use super::RuntimeError;
+use chrono::NaiveDateTime;
+use serde::de::DeserializeOwned;
+
+use super::{
+    audit::{audit_key, AuditLog},
+    cache::{OperatingInfoCache, UserRecordCache},
+    import::parse_task_csv,
+    key::ParsedKey,
+    migrations,
+    namespace::KeyPrefix,
+    RuntimeError,
+};
 use libs::{
     payload::{
-        GetSingleRecordPayload, RegisterRecordPayload, ResetRecordPayload, StoreSTaskPayload,
-        StoreTaskPayload, UpdateTaskPayload,
+        DeleteRecordPayload, GetByIdPayload, GetSingleRecordPayload, GetTaskPayload, GroupBy,
+        GroupedStatsPayload, ImportQuery, RecordFilter, RecordKeyQuery, RegisterRecordPayload,
+        RegisterRecordsBulkPayload, RenameTasksPayload, ResetRecordPayload, SetGoalPayload,
+        SetRatePayload, SplitTaskPayload, StoreSTaskPayload, StoreTaskPayload, UpdateSTaskPayload,
+        UpdateSettingsPayload, UpdateTaskPayload,
+    },
+    record::{
+        normalize_task_name, sort_by_last_active, AuditEntry, AuditOperation, HourlyRate, STask,
+        Seconds, SudoUserRecord, Task, TaskState, UserRecord, UserRecordView, UserSummary,
+    },
+    stats::{
+        find_history_issues, find_overlap, finished_in_range, GroupTotal, HistoryIssue,
+        StatsOverview,
     },
-    record::{STask, SudoUserRecord, Task, TaskState, UserRecord},
-    OperatingInfoRedisJsonPath, OperatingRedisKey, SudoUserRecordRedisJsonPath,
+    OperatingInfo, OperatingInfoRedisJsonPath, OperatingRedisKey, SudoUserRecordRedisJsonPath,
     UserRecordRedisJsonPath, UserType,
 };
 
 pub(super) async fn perform_create_task(
-    payload: StoreTaskPayload,
+    mut payload: StoreTaskPayload,
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+    lowercase_task_names: bool,
+    appends_per_minute: u32,
+    audit_log: AuditLog,
 ) -> Result<(), RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    payload.task.name = normalize_task_name(&payload.task.name, lowercase_task_names);
+    payload.task.parent = payload
+        .task
+        .parent
+        .map(|parent| normalize_task_name(&parent, lowercase_task_names));
+    if payload.task.name.is_empty() {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.task.name".to_string(),
+        });
+    }
+    if payload.task.is_self_parented() {
+        tracing::debug!("task is its own parent: {:?}", payload.task.name);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.task.parent".to_string(),
+        });
+    }
 
     let Some(data_str) = con
         .json_get::<&std::string::String, &str, Option<String>>(
-            &payload.key,
+            &key,
             UserRecordRedisJsonPath::Root.to_string().as_str(),
         )
         .await?
@@ -36,121 +83,368 @@ pub(super) async fn perform_create_task(
         });
     };
 
-    let mut user_data_vec: Vec<UserRecord> = serde_json::from_str(&data_str)?;
+    let (mut user_data, _) = parse_user_record(&data_str, &key)?;
+    user_data.backfill_task_ids();
 
-    // Remove the latest task from the history
-    // to append the updated version later.
-    if user_data_vec[0].current_task.state == TaskState::Begin
-        || user_data_vec[0].current_task.state == TaskState::Break
-        || user_data_vec[0].current_task.state == TaskState::Back
-    {
-        user_data_vec[0].task_history.pop();
+    if payload.task.state == TaskState::Begin && user_data.current_task.state.is_active() {
+        tracing::debug!("task already in progress: {:?}", payload.key);
+        return Err(RuntimeError::Conflict {
+            message: "Another task is in progress".to_string(),
+            data: Some(serde_json::json!({
+                "current_task": user_data.current_task,
+            })),
+        });
+    }
+    if payload.task.state != TaskState::Begin {
+        check_transition(&user_data.current_task, &payload.task.state)?;
+    }
+    if payload.task.state == TaskState::Begin {
+        if let Some(existing) = find_overlap(&user_data.task_history, payload.task.begin_time, None)
+        {
+            tracing::debug!("task overlaps a logged one: {:?}", payload.key);
+            return Err(overlap_conflict(&payload.task, existing));
+        }
+    }
+
+    // Remove the latest task from the history to append the updated
+    // version later, which keeps its id. A new task gets the next one.
+    let replaced = if user_data.current_task.state.is_active() {
+        user_data.task_history.pop()
+    } else {
+        None
+    };
+    let new_task = Task {
+        id: Some(
+            replaced
+                .as_ref()
+                .and_then(|t| t.id)
+                .unwrap_or_else(|| user_data.next_task_id()),
+        ),
+        ..payload.task
     };
 
-    let task_history = user_data_vec.into_iter().next().unwrap().task_history;
-    con.json_set(
-        &payload.key,
-        UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
-        &serde_json::json!(task_history),
-    )
-    .await?;
+    if appends_per_minute > 0 {
+        let now = chrono::offset::Local::now().naive_local();
+        let counter_key = key_prefix.apply(&append_counter_key(&payload.key, now));
+        let (count, _): (u64, ()) = redis::pipe()
+            .atomic()
+            .incr(&counter_key, 1)
+            .expire(&counter_key, 60)
+            .query_async(&mut *con)
+            .await?;
+        check_append_limit(count, appends_per_minute, now)?;
+    }
+
+    let task_history = user_data.task_history;
+    let _: () = con
+        .json_set(
+            &key,
+            UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
+            &serde_json::json!(task_history),
+        )
+        .await?;
 
     tracing::debug!("appending");
-    con.json_arr_append(
-        &payload.key,
-        UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
-        &serde_json::json!(&payload.task),
-    )
-    .await?;
+    let _: () = con
+        .json_arr_append(
+            &key,
+            UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
+            &serde_json::json!(&new_task),
+        )
+        .await?;
 
     tracing::debug!("setting current task");
-    con.json_set(
-        &payload.key,
-        UserRecordRedisJsonPath::CurrentTask.to_string().as_str(),
-        &serde_json::json!(&payload.task),
-    )
-    .await?;
+    let _: () = con
+        .json_set(
+            &key,
+            UserRecordRedisJsonPath::CurrentTask.to_string().as_str(),
+            &serde_json::json!(&new_task),
+        )
+        .await?;
+    touch_last_active(&key, &mut con).await?;
+    user_record_cache.invalidate(&key);
 
+    let operation = match replaced {
+        Some(_) => AuditOperation::UpdateTask,
+        None => AuditOperation::CreateTask,
+    };
+    let entry = AuditEntry::new(operation, replaced.into_iter().collect(), vec![new_task]);
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, vec![entry], &mut con)
+        .await;
     Ok(())
 }
 
 pub(super) async fn perform_register_record(
     payload: RegisterRecordPayload,
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
 ) -> Result<String, RuntimeError> {
-    let id = get_new_record_id(UserType::User, redis_pool.clone()).await?;
+    let id = get_new_record_id(
+        UserType::User,
+        redis_pool.clone(),
+        &key_prefix,
+        &operating_info_cache,
+    )
+    .await?;
     let user_key = generate_key(UserType::User, &payload.user_name, id);
-    let user_data = UserRecord {
-        id,
-        user_name: payload.user_name,
-        task_history: vec![],
-        current_task: Task::placeholder("initialised", TaskState::Placeholder),
-    };
+    let user_data = UserRecord::new(id, payload.user_name);
 
     let mut con = redis_pool.get().await.unwrap();
-    con.json_set(
-        &user_key,
-        UserRecordRedisJsonPath::Root.to_string().as_str(),
+    let reply: Option<String> = json_set_nx_cmd(
+        &key_prefix.apply(&user_key),
+        &UserRecordRedisJsonPath::Root.to_string(),
         &serde_json::json!(user_data),
     )
+    .query_async(&mut *con)
     .await?;
+    expect_created(reply, &user_key)?;
     tracing::debug!("new_user: {:?}", user_data.user_name);
 
-    store_to_record_list(UserType::User, &user_data.user_name, redis_pool.clone()).await?;
+    store_to_record_list(
+        UserType::User,
+        &user_key,
+        redis_pool.clone(),
+        &key_prefix,
+        &operating_info_cache,
+    )
+    .await?;
 
     Ok(user_key)
 }
 
+/// Register all `user_names` in one go, returning their keys in order.
+///
+/// IDs are reserved with a single increment, then the records and their
+/// `user_list` entries are written in one transaction.
+pub(super) async fn perform_register_records_bulk(
+    payload: RegisterRecordsBulkPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
+) -> Result<Vec<String>, RuntimeError> {
+    validate_user_names(&payload.user_names)?;
+
+    let ids = reserve_record_ids(
+        UserType::User,
+        payload.user_names.len() as i32,
+        redis_pool.clone(),
+        &key_prefix,
+        &operating_info_cache,
+    )
+    .await?;
+
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    let mut user_keys = vec![];
+    for (id, user_name) in ids.zip(payload.user_names) {
+        let user_key = generate_key(UserType::User, &user_name, id);
+        let user_data = UserRecord::new(id, user_name);
+        pipe.json_set(
+            key_prefix.apply(&user_key),
+            UserRecordRedisJsonPath::Root.to_string(),
+            &serde_json::json!(user_data),
+        )?
+        .ignore();
+        pipe.json_arr_append(
+            key_prefix.apply(&OperatingRedisKey::OperatingInfo.to_string()),
+            OperatingInfoRedisJsonPath::UserList.to_string(),
+            &user_key,
+        )?
+        .ignore();
+        user_keys.push(user_key);
+    }
+
+    let mut con = redis_pool.get().await.unwrap();
+    let _: () = pipe.query_async(&mut *con).await?;
+    operating_info_cache.invalidate();
+    tracing::debug!("new_users: {}", user_keys.len());
+
+    Ok(user_keys)
+}
+
 pub(super) async fn perform_reset_record(
     payload: ResetRecordPayload,
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+    audit_log: AuditLog,
 ) -> Result<UserRecord, RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
 
-    let key_exists = con
+    let Some(data_str) = con
         .json_get::<&std::string::String, &str, Option<String>>(
-            &payload.key,
+            &key,
             UserRecordRedisJsonPath::Root.to_string().as_str(),
         )
         .await?
-        .is_some();
-    if !key_exists {
+    else {
         tracing::debug!("non-exist record: {:?}", payload);
         return Err(RuntimeError::UnprocessableEntity {
             name: "payload.key".to_string(),
         });
+    };
+
+    // The whole record is written back below, migrated or not.
+    let (original, _) = parse_user_record(&data_str, &key)?;
+    let reset_tasks = original.audited_tasks();
+    let user_data = original.into_reset();
+    let _: () = con
+        .json_set(
+            &key,
+            UserRecordRedisJsonPath::Root.to_string().as_str(),
+            &serde_json::json!(user_data),
+        )
+        .await?;
+    user_record_cache.invalidate(&key);
+
+    let entry = AuditEntry::new(AuditOperation::ResetRecord, reset_tasks, vec![]);
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, vec![entry], &mut con)
+        .await;
+
+    Ok(user_data)
+}
+
+/// Append tasks parsed from a CSV body to the history, all or none.
+pub(super) async fn perform_import_tasks_csv(
+    payload: ImportQuery,
+    body: &str,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+    lowercase_task_names: bool,
+    audit_log: AuditLog,
+) -> Result<usize, RuntimeError> {
+    let mut tasks = parse_task_csv(body).map_err(|errors| RuntimeError::InvalidCsv { errors })?;
+    for task in tasks.iter_mut() {
+        task.name = normalize_task_name(&task.name, lowercase_task_names);
     }
 
-    let vec_payload_key = payload.key.split(':').collect::<Vec<&str>>();
-    let user_data = UserRecord {
-        id: vec_payload_key[1]
-            .parse::<i32>()
-            .map_err(|_| RuntimeError::UnprocessableEntity {
-                name: "id".to_string(),
-            })?,
-        user_name: vec_payload_key[0].to_string(),
-        task_history: vec![],
-        current_task: Task::placeholder("reset", TaskState::Placeholder),
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    let Some(data_str) = con
+        .json_get::<&std::string::String, &str, Option<String>>(
+            &key,
+            UserRecordRedisJsonPath::Root.to_string().as_str(),
+        )
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
     };
-    con.json_set(
+    let (mut user_data, _) = parse_user_record(&data_str, &key)?;
+    if !payload.allow_overlap {
+        check_import_overlaps(&user_data.task_history, &tasks)?;
+    }
+
+    import_into_history(&mut user_data, tasks.clone());
+    let _: () = con
+        .json_set(
+            &key,
+            UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
+            &serde_json::json!(user_data.task_history),
+        )
+        .await?;
+    tracing::debug!("imported -> task history: {}", tasks.len());
+    user_record_cache.invalidate(&key);
+
+    let imported = tasks.len();
+    let entry = AuditEntry::new(AuditOperation::ImportTasks, vec![], tasks);
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, vec![entry], &mut con)
+        .await;
+
+    Ok(imported)
+}
+
+/// Remove a record for good: its key and its entry in the list of its user type.
+pub(super) async fn perform_delete_record(
+    payload: DeleteRecordPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
+    user_record_cache: UserRecordCache,
+) -> Result<(), RuntimeError> {
+    let user_type = ParsedKey::try_from(payload.key.as_str())?.user_type;
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    let deleted: usize = con.del(&key).await?;
+    if deleted == 0 {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    }
+    tracing::debug!("deleted: {:?}", payload.key);
+    user_record_cache.invalidate(&key);
+    let _: () = con.del(audit_key(&key_prefix, &payload.key)).await?;
+
+    remove_from_record_list(
+        user_type,
         &payload.key,
-        UserRecordRedisJsonPath::Root.to_string().as_str(),
-        &serde_json::json!(user_data),
+        redis_pool.clone(),
+        &key_prefix,
+        &operating_info_cache,
+    )
+    .await
+}
+
+/// One task of a record by id, as stored or, while active, as it is now.
+pub(super) async fn perform_get_task(
+    payload: GetTaskPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+) -> Result<Task, RuntimeError> {
+    let record = perform_get_user_record(
+        GetSingleRecordPayload {
+            key: payload.key.clone(),
+            include_history: true,
+        },
+        redis_pool,
+        key_prefix,
+        user_record_cache,
     )
     .await?;
 
-    Ok(user_data)
+    let Some(task) = record.task_by_id(payload.task_id) else {
+        tracing::debug!("non-exist task: {:?}", payload);
+        return Err(RuntimeError::RecordNotFound {
+            name: "payload.task_id".to_string(),
+        });
+    };
+    Ok(task.clone())
 }
 
 pub(super) async fn perform_get_user_record(
     payload: GetSingleRecordPayload,
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
 ) -> Result<UserRecord, RuntimeError> {
+    let key = key_prefix.apply(&payload.key);
+    if let Some(mut user_data) = user_record_cache.get(&key) {
+        tracing::debug!("cached record: {:?}", key);
+        if !payload.include_history {
+            user_data.task_history.clear();
+        }
+        return Ok(user_data);
+    }
     let mut con = redis_pool.get().await.unwrap();
+    if !payload.include_history {
+        return get_user_record_without_history(&key, &mut con).await;
+    }
 
     let Some(data_str) = con
         .json_get::<&std::string::String, &str, Option<String>>(
-            &payload.key,
+            &key,
             UserRecordRedisJsonPath::Root.to_string().as_str(),
         )
         .await?
@@ -161,88 +455,197 @@ pub(super) async fn perform_get_user_record(
         });
     };
 
-    let user_data_vec = serde_json::from_str::<Vec<UserRecord>>(&data_str)?;
-    let mut user_data = user_data_vec.into_iter().next().unwrap();
+    // Records stored in an older shape are upgraded on first read, and
+    // tasks imported without an id get one.
+    let (mut user_data, migrated) = parse_user_record(&data_str, &key)?;
+    if user_data.backfill_task_ids() || migrated {
+        write_user_record(&key, &user_data, &mut con).await?;
+    }
     user_data
         .task_history
-        .sort_by(|a, b| b.begin_time.cmp(&a.begin_time));
+        .sort_by_key(|t| std::cmp::Reverse(t.begin_time));
+    user_record_cache.set(&key, user_data.clone());
 
     Ok(user_data)
 }
 
+/// The keys in `keys` of users whose name contains `query`, ignoring case.
+/// Bare user names left in old lists are matched as a whole.
+fn keys_matching_name(keys: Vec<String>, query: Option<&str>) -> Vec<String> {
+    let Some(query) = query.map(str::to_lowercase) else {
+        return keys;
+    };
+    keys.into_iter()
+        .filter(|key| {
+            let user_name = ParsedKey::try_from(key.as_str()).map_or(key.as_str(), |k| k.user_name);
+            user_name.to_lowercase().contains(&query)
+        })
+        .collect()
+}
+
+/// List every user's record, with an empty `task_history` unless
+/// `include_history`, which is only read then. With a `query`, only the
+/// records of users whose name contains it are read at all.
 pub(super) async fn perform_get_all_user_records(
+    filter: RecordFilter,
+    include_history: bool,
+    query: Option<&str>,
     redis_pool: Pool<RedisConnectionManager>,
-) -> Result<Vec<UserRecord>, RuntimeError> {
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
+) -> Result<Vec<UserRecordView>, RuntimeError> {
+    let keys = get_operating_info(redis_pool.clone(), &key_prefix, &operating_info_cache)
+        .await?
+        .user_list;
+    let keys = keys_matching_name(keys, query);
+
     let mut con = redis_pool.get().await.unwrap();
-    let keys_resp_str: String = con
-        .json_get(
-            OperatingRedisKey::OperatingInfo.to_string().as_str(),
-            OperatingInfoRedisJsonPath::UserList.to_string().as_str(),
-        )
-        .await?;
-    let keys_resp = serde_json::from_str::<Vec<Vec<String>>>(&keys_resp_str)?;
-    let keys = keys_resp.into_iter().next().unwrap();
 
-    let mut user_records: Vec<UserRecord> = vec![];
+    let mut user_records: Vec<UserRecordView> = vec![];
 
     for key in keys {
+        if !include_history {
+            match get_user_record_without_history(&key_prefix.apply(&key), &mut con).await {
+                Ok(user_data) if filter.matches(&user_data) => user_records.push(user_data.into()),
+                Ok(_) => {}
+                Err(RuntimeError::UnprocessableEntity { .. }) => {
+                    tracing::error!("invalid record found: {:?}", key);
+                }
+                Err(e) => return Err(e),
+            }
+            continue;
+        }
+
         let Some(data_str) = con
             .json_get::<&std::string::String, &str, Option<String>>(
-                &key,
+                &key_prefix.apply(&key),
                 UserRecordRedisJsonPath::Root.to_string().as_str(),
             )
             .await?
         else {
-            // NOTE: This technically will not happen, since
-            // the keys are generated from the pre-defined pattern.
-            // TODO: Handle when there exists keys that
-            // follow the pattern but do not have the data.
-            panic!("invalid record found: {:?}", key);
+            // Lists written before they held full keys contain bare
+            // user names, which don't point at any record.
+            tracing::error!("invalid record found: {:?}", key);
+            continue;
         };
 
-        let user_data_vec: Vec<UserRecord> = serde_json::from_str(&data_str)?;
-        let user_data = user_data_vec.into_iter().next().unwrap();
+        let (user_data, migrated) = parse_user_record(&data_str, &key)?;
+        if migrated {
+            write_user_record(&key_prefix.apply(&key), &user_data, &mut con).await?;
+        }
         tracing::debug!("retrieved_user_data: {:?}", user_data.user_name);
 
-        user_records.push(user_data);
+        if filter.matches(&user_data) {
+            user_records.push(user_data.into());
+        }
     }
 
     Ok(user_records)
 }
 
+/// List every user with only `user_name` and `last_active_at` read,
+/// most recently active first.
+pub(super) async fn perform_get_user_summaries(
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
+) -> Result<Vec<UserSummary>, RuntimeError> {
+    let keys = get_operating_info(redis_pool.clone(), &key_prefix, &operating_info_cache)
+        .await?
+        .user_list;
+    if keys.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut con = redis_pool.get().await.unwrap();
+    let prefixed_keys = keys
+        .iter()
+        .map(|key| key_prefix.apply(key))
+        .collect::<Vec<String>>();
+
+    // JSON.MGET takes a single path, so each field is one round trip
+    // across all keys, rather than one per user.
+    let user_path = UserRecordRedisJsonPath::UserName.to_string();
+    let user_names: Vec<Option<String>> = redis::cmd("JSON.MGET")
+        .arg(&prefixed_keys)
+        .arg(&user_path)
+        .query_async(&mut *con)
+        .await?;
+    let active_path = UserRecordRedisJsonPath::LastActiveAt.to_string();
+    let last_active_ats: Vec<Option<String>> = redis::cmd("JSON.MGET")
+        .arg(&prefixed_keys)
+        .arg(&active_path)
+        .query_async(&mut *con)
+        .await?;
+
+    let mut summaries = vec![];
+    for ((key, user_name), last_active_at) in keys.into_iter().zip(user_names).zip(last_active_ats)
+    {
+        let Some(user_name) = user_name else {
+            tracing::error!("invalid record found: {:?}", key);
+            continue;
+        };
+        let Some(user_name) = parse_json::<Vec<String>>(&user_name, &key, &user_path)?
+            .into_iter()
+            .next()
+        else {
+            tracing::error!("invalid record found: {:?}", key);
+            continue;
+        };
+        // Records that never had a task write have no `last_active_at`.
+        let last_active_at = match last_active_at {
+            Some(data_str) => {
+                parse_json::<Vec<Option<NaiveDateTime>>>(&data_str, &key, &active_path)?
+                    .into_iter()
+                    .next()
+                    .flatten()
+            }
+            None => None,
+        };
+
+        summaries.push(UserSummary {
+            key,
+            user_name,
+            last_active_at,
+        });
+    }
+    sort_by_last_active(&mut summaries);
+
+    Ok(summaries)
+}
+
 pub(super) async fn perform_get_all_sudo_records(
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
 ) -> Result<Vec<SudoUserRecord>, RuntimeError> {
+    let keys = get_operating_info(redis_pool.clone(), &key_prefix, &operating_info_cache)
+        .await?
+        .sudo_user_list;
+
     let mut con = redis_pool.get().await.unwrap();
-    let keys_resp_str: String = con
-        .json_get(
-            OperatingRedisKey::OperatingInfo.to_string().as_str(),
-            OperatingInfoRedisJsonPath::SudoUserList
-                .to_string()
-                .as_str(),
-        )
-        .await?;
-    let keys_resp = serde_json::from_str::<Vec<Vec<String>>>(&keys_resp_str)?;
-    let keys = keys_resp.into_iter().next().unwrap();
 
     let mut sudo_records: Vec<SudoUserRecord> = vec![];
 
     for key in keys {
         let Some(data_str) = con
             .json_get::<&std::string::String, &str, Option<String>>(
-                &key,
+                &key_prefix.apply(&key),
                 SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
             )
             .await?
         else {
-            // NOTE: This technically will not happen, since
-            // the keys are generated from the pre-defined pattern.
-            // TODO: Handle when there exists keys that
-            // follow the pattern but do not have the data.
-            panic!("invalid record found: {:?}", key);
+            // Lists written before they held full keys contain bare
+            // user names, which don't point at any record.
+            tracing::error!("invalid record found: {:?}", key);
+            continue;
         };
 
-        let sudo_user_data_vec: Vec<SudoUserRecord> = serde_json::from_str(&data_str)?;
+        let sudo_user_data_vec: Vec<SudoUserRecord> = parse_json(
+            &data_str,
+            &key,
+            &SudoUserRecordRedisJsonPath::Root.to_string(),
+        )?;
         let sudo_user_data = sudo_user_data_vec.into_iter().next().unwrap();
         tracing::debug!("retrieved_sudo_user: {:?}", sudo_user_data.user_name);
 
@@ -252,53 +655,91 @@ pub(super) async fn perform_get_all_sudo_records(
     Ok(sudo_records)
 }
 
-// pub(super) async fn perform_get_all_user_records(
-//     redis_pool: Pool<RedisConnectionManager>,
-// ) -> Result<Vec<UserRecord>, RuntimeError> {
-//     let mut con = redis_pool.get().await.unwrap();
-//     let mut keys = con
-//         .scan_match::<&str, std::string::String>("user:*:????")
-//         .await?;
-//
-//     let mut user_records: Vec<UserRecord> = vec![];
-//
-//     while let Some(key) = keys.next_item().await {
-//         let mut new_con = redis_pool.get().await.unwrap();
-//
-//         let Some(data_str) = new_con
-//             .json_get::<&std::string::String, &str, Option<String>>(
-//                 &key,
-//                 UserRecordRedisJsonPath::Root.to_string().as_str(),
-//             )
-//             .await?
-//         else {
-//             // NOTE: This technically will not happen, since
-//             // the keys are generated from the pre-defined pattern.
-//             // TODO: Handle when there exists keys that
-//             // follow the pattern but do not have the data.
-//             panic!("invalid record found: {:?}", key);
-//         };
-//
-//         let user_data: Vec<UserRecord> = serde_json::from_str(&data_str)?;
-//         tracing::debug!("user_data: {:?}", user_data);
-//
-//         user_records.push(user_data.into_iter().next().unwrap());
-//     }
-//
-//     Ok(user_records)
-// }
+/// Response of the multi-path `JSON.GET` used for the stats overview.
+#[derive(serde::Deserialize)]
+struct StatsProjection {
+    #[serde(rename = "$.current_task.state")]
+    current_task_state: Vec<TaskState>,
+    #[serde(rename = "$.task_history[?(@.state==\"End\")].begin_time")]
+    end_task_begin_times: Vec<chrono::NaiveDateTime>,
+}
 
-pub(super) async fn perform_update_task(
-    payload: UpdateTaskPayload,
+pub(super) async fn perform_get_stats_overview(
     redis_pool: Pool<RedisConnectionManager>,
-) -> Result<(), RuntimeError> {
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
+) -> Result<StatsOverview, RuntimeError> {
+    let keys = get_operating_info(redis_pool.clone(), &key_prefix, &operating_info_cache)
+        .await?
+        .user_list;
+
+    let mut con = redis_pool.get().await.unwrap();
+    let today = chrono::offset::Local::now().date_naive();
+
+    let mut overview = StatsOverview {
+        users: keys.len(),
+        active_users: 0,
+        tasks_today: 0,
+        total_tasks: 0,
+    };
+
+    for key in keys {
+        // Only the current state and the begin times of finished tasks
+        // are read, never the full record.
+        let paths = vec![
+            UserRecordRedisJsonPath::CurrentTaskState.to_string(),
+            UserRecordRedisJsonPath::EndTaskBeginTimes.to_string(),
+        ];
+        let Some(data_str) = con
+            .json_get::<&std::string::String, &Vec<String>, Option<String>>(
+                &key_prefix.apply(&key),
+                &paths,
+            )
+            .await?
+        else {
+            tracing::error!("invalid record found: {:?}", key);
+            continue;
+        };
+
+        let projection = parse_json::<StatsProjection>(&data_str, &key, &paths.join(", "))?;
+        if projection
+            .current_task_state
+            .first()
+            .is_some_and(TaskState::is_active)
+        {
+            overview.active_users += 1;
+        }
+        overview.total_tasks += get_task_history_len(&key_prefix.apply(&key), &mut con).await?;
+        overview.tasks_today += projection
+            .end_task_begin_times
+            .iter()
+            .filter(|t| t.date() == today)
+            .count();
+    }
+
+    Ok(overview)
+}
+
+pub(super) async fn perform_get_grouped_stats(
+    payload: GroupedStatsPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+) -> Result<Vec<GroupTotal>, RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    if payload.from >= payload.to {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.to".to_string(),
+        });
+    }
 
+    let paths = vec![
+        UserRecordRedisJsonPath::TaskHistory.to_string(),
+        UserRecordRedisJsonPath::HourlyRate.to_string(),
+    ];
     let Some(data_str) = con
-        .json_get::<&std::string::String, &str, Option<String>>(
-            &payload.key,
-            UserRecordRedisJsonPath::Root.to_string().as_str(),
-        )
+        .json_get::<&std::string::String, &Vec<String>, Option<String>>(&key, &paths)
         .await?
     else {
         tracing::debug!("non-exist record: {:?}", payload);
@@ -306,114 +747,148 @@ pub(super) async fn perform_update_task(
             name: "payload.key".to_string(),
         });
     };
-
-    let user_record_vec: Vec<UserRecord> = serde_json::from_str(&data_str)?;
-    let user_record = user_record_vec.into_iter().next().unwrap();
-
-    if user_record.current_task.state != TaskState::End && payload.state == TaskState::End {
-        let new_end_task = Task::generate_done_task(&user_record.current_task);
-        tracing::debug!("new_end_task: {:?}", new_end_task);
-
-        con.json_set(
-            &payload.key,
-            UserRecordRedisJsonPath::CurrentTask.to_string().as_str(),
-            &serde_json::json!(&new_end_task),
-        )
-        .await?;
-        tracing::debug!("set -> current task");
-
-        con.json_arr_append(
-            &payload.key,
-            UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
-            &serde_json::json!(&new_end_task),
-        )
-        .await?;
-        tracing::debug!("appended -> task history");
-
-        Ok(())
-    } else {
-        // TODO: Handle the rest of the cases.
-        Ok(())
+    let projection = parse_json::<BillingProjection>(&data_str, &key, &paths.join(", "))?;
+    let task_history = projection
+        .task_history
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    let hourly_rate = projection.hourly_rate.into_iter().next().flatten();
+
+    let mut groups = group_totals(
+        &task_history,
+        payload.from,
+        payload.to,
+        payload.group_by,
+        payload.billable,
+    );
+    if let Some(hourly_rate) = hourly_rate {
+        for group in groups.iter_mut() {
+            group.earnings = Some(hourly_rate.earnings(group.billable_seconds));
+        }
     }
+    Ok(groups)
 }
 
-pub(super) async fn perform_sudo_register_record(
-    payload: RegisterRecordPayload,
+/// Read-only check of a user's finished tasks, see `find_history_issues`.
+pub(super) async fn perform_validate_history(
+    payload: RecordKeyQuery,
     redis_pool: Pool<RedisConnectionManager>,
-) -> Result<(), RuntimeError> {
+    key_prefix: KeyPrefix,
+) -> Result<Vec<HistoryIssue>, RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
 
-    let id = get_new_record_id(UserType::SudoUser, redis_pool.clone()).await?;
-    con.set(OperatingRedisKey::CurrentId.to_string(), id)
-        .await?;
-
-    let user_data = SudoUserRecord {
-        id,
-        user_name: payload.user_name.clone(),
-        published_tasks: vec![],
+    let path = UserRecordRedisJsonPath::TaskHistory.to_string();
+    let Some(data_str) = con
+        .json_get::<&std::string::String, &str, Option<String>>(&key, path.as_str())
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
     };
-    let user_key = generate_key(UserType::SudoUser, &payload.user_name, id);
-    con.json_set(
-        user_key,
-        SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
-        &serde_json::json!(user_data),
-    )
-    .await?;
-    tracing::debug!("new_sudo_user: {:?}", user_data.user_name);
+    let task_history = parse_json::<Vec<Vec<Task>>>(&data_str, &key, &path)?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
 
-    store_to_record_list(UserType::SudoUser, &user_data.user_name, redis_pool.clone()).await?;
+    Ok(find_history_issues(&task_history))
+}
 
-    Ok(())
+/// Sum the finished tasks that began within `[from, to)` per group,
+/// ordered by group label. With `billable`, only tasks marked that way count.
+fn group_totals(
+    tasks: &[Task],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    group_by: GroupBy,
+    billable: Option<bool>,
+) -> Vec<GroupTotal> {
+    let mut groups: BTreeMap<String, GroupTotal> = BTreeMap::new();
+    for task in finished_in_range(tasks, from, to)
+        .filter(|t| billable.is_none_or(|billable| t.billable == billable))
+    {
+        let label = match group_by {
+            GroupBy::Day => task.begin_time.format("%Y-%m-%d").to_string(),
+            GroupBy::Week => task.begin_time.format("%G-W%V").to_string(),
+            GroupBy::Task => task.name.clone(),
+        };
+        let group = groups.entry(label.clone()).or_insert(GroupTotal {
+            group: label,
+            seconds: Seconds(0),
+            billable_seconds: Seconds(0),
+            tasks: 0,
+            earnings: None,
+        });
+        group.seconds += task.duration;
+        if task.billable {
+            group.billable_seconds += task.duration;
+        }
+        group.tasks += 1;
+    }
+    groups.into_values().collect()
 }
 
-pub(super) async fn perform_sudo_create_task(
-    payload: StoreSTaskPayload,
+pub(super) async fn perform_set_goal(
+    payload: SetGoalPayload,
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
 ) -> Result<(), RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    if payload.daily_goal_seconds.is_some_and(|goal| goal <= 0) {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.daily_goal_seconds".to_string(),
+        });
+    }
 
-    let Some(_data_str) = con
+    let key_exists = con
         .json_get::<&std::string::String, &str, Option<String>>(
-            &payload.key,
-            SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
+            &key,
+            UserRecordRedisJsonPath::Id.to_string().as_str(),
         )
         .await?
-    else {
+        .is_some();
+    if !key_exists {
         tracing::debug!("non-exist record: {:?}", payload);
         return Err(RuntimeError::UnprocessableEntity {
             name: "payload.key".to_string(),
         });
-    };
-
-    let new_task = STask {
-        name: payload.task.name,
-        description: payload.task.description,
-        created_at: chrono::offset::Local::now().naive_local(),
-    };
+    }
 
-    tracing::debug!("appending");
-    con.json_arr_append(
-        &payload.key,
-        SudoUserRecordRedisJsonPath::PublishedTasks
-            .to_string()
-            .as_str(),
-        &serde_json::json!(new_task),
-    )
-    .await?;
+    let _: () = con
+        .json_set(
+            &key,
+            UserRecordRedisJsonPath::DailyGoalSeconds
+                .to_string()
+                .as_str(),
+            &serde_json::json!(payload.daily_goal_seconds),
+        )
+        .await?;
+    user_record_cache.invalidate(&key);
 
     Ok(())
 }
 
-pub(super) async fn perform_sudo_reset_record(
-    payload: ResetRecordPayload,
+/// Merge only the settings present in the payload into the record.
+pub(super) async fn perform_update_settings(
+    payload: UpdateSettingsPayload,
     redis_pool: Pool<RedisConnectionManager>,
-) -> Result<SudoUserRecord, RuntimeError> {
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+) -> Result<(), RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
 
+    // `JSON.MERGE` at the root would create a missing key instead of failing.
     let key_exists = con
         .json_get::<&std::string::String, &str, Option<String>>(
-            &payload.key,
-            SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
+            &key,
+            UserRecordRedisJsonPath::Id.to_string().as_str(),
         )
         .await?
         .is_some();
@@ -424,38 +899,561 @@ pub(super) async fn perform_sudo_reset_record(
         });
     }
 
-    let vec_payload_key = payload.key.split(':').collect::<Vec<&str>>();
-    let user_data = SudoUserRecord {
-        id: vec_payload_key[2]
-            .parse::<i32>()
-            // NOTE: Although it may appears that this check is obsolete,
-            // it is still necessary to ensure that uses would only get
-            // responses from correct key, even from the db.
-            .map_err(|_| RuntimeError::UnprocessableEntity {
-                name: "payload.key".to_string(),
-            })?,
-        user_name: vec_payload_key[1].to_string(),
-        published_tasks: vec![],
-    };
-    con.json_set(
-        &payload.key,
-        SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
+    let _: () = json_merge_cmd(
+        &key,
+        UserRecordRedisJsonPath::Root.to_string().as_str(),
+        &serde_json::json!(payload.settings),
+    )
+    .query_async(&mut *con)
+    .await?;
+    tracing::debug!("merged settings: {:?}", payload.settings);
+    user_record_cache.invalidate(&key);
+
+    Ok(())
+}
+
+pub(super) async fn perform_set_rate(
+    payload: SetRatePayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+) -> Result<(), RuntimeError> {
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    if payload
+        .hourly_rate
+        .as_ref()
+        .is_some_and(|rate| rate.cents <= 0 || rate.currency.trim().is_empty())
+    {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.hourly_rate".to_string(),
+        });
+    }
+
+    let key_exists = con
+        .json_get::<&std::string::String, &str, Option<String>>(
+            &key,
+            UserRecordRedisJsonPath::Id.to_string().as_str(),
+        )
+        .await?
+        .is_some();
+    if !key_exists {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    }
+
+    let _: () = con
+        .json_set(
+            &key,
+            UserRecordRedisJsonPath::HourlyRate.to_string().as_str(),
+            &serde_json::json!(payload.hourly_rate),
+        )
+        .await?;
+    user_record_cache.invalidate(&key);
+
+    Ok(())
+}
+
+pub(super) async fn perform_update_task(
+    payload: UpdateTaskPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+    audit_log: AuditLog,
+) -> Result<(), RuntimeError> {
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    let Some(data_str) = con
+        .json_get::<&std::string::String, &str, Option<String>>(
+            &key,
+            UserRecordRedisJsonPath::Root.to_string().as_str(),
+        )
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    };
+
+    let (mut user_record, migrated) = parse_user_record(&data_str, &key)?;
+    // The replaced history entry below must already have an id.
+    if user_record.backfill_task_ids() || migrated {
+        write_user_record(&key, &user_record, &mut con).await?;
+    }
+    let current_task = user_record.current_task;
+
+    check_transition(&current_task, &payload.state)?;
+
+    let new_task = match payload.state {
+        TaskState::Break => Task::generate_break_task(&current_task),
+        TaskState::Back => Task::generate_back_task(&current_task),
+        TaskState::End => Task::generate_done_task(&current_task),
+        // A new task needs a name, so it has to go through `perform_create_task`.
+        TaskState::Begin | TaskState::Placeholder => {
+            return Err(RuntimeError::UnprocessableEntity {
+                name: "payload.state".to_string(),
+            });
+        }
+    };
+    tracing::debug!("new_task: {:?}", new_task);
+
+    let _: () = con
+        .json_set(
+            &key,
+            UserRecordRedisJsonPath::CurrentTask.to_string().as_str(),
+            &serde_json::json!(&new_task),
+        )
+        .await?;
+    tracing::debug!("set -> current task");
+
+    // The latest history entry is the task being updated, replace it.
+    let _: () = con
+        .json_arr_pop(
+            &key,
+            UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
+            -1,
+        )
+        .await?;
+    let _: () = con
+        .json_arr_append(
+            &key,
+            UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
+            &serde_json::json!(&new_task),
+        )
+        .await?;
+    tracing::debug!("replaced -> task history");
+    touch_last_active(&key, &mut con).await?;
+    user_record_cache.invalidate(&key);
+
+    let entry = AuditEntry::new(
+        AuditOperation::UpdateTask,
+        vec![current_task],
+        vec![new_task],
+    );
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, vec![entry], &mut con)
+        .await;
+    Ok(())
+}
+
+pub(super) async fn perform_split_task(
+    payload: SplitTaskPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+    audit_log: AuditLog,
+) -> Result<(Task, Task), RuntimeError> {
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    let Some(data_str) = con
+        .json_get::<&std::string::String, &str, Option<String>>(
+            &key,
+            UserRecordRedisJsonPath::Root.to_string().as_str(),
+        )
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    };
+
+    let (mut user_record, _) = parse_user_record(&data_str, &key)?;
+    user_record.backfill_task_ids();
+    let next_task_id = user_record.next_task_id();
+    let mut task_history = user_record.task_history;
+
+    let task_index = match (payload.task_id, payload.task_index) {
+        (Some(task_id), _) => task_history.iter().position(|t| t.id == Some(task_id)),
+        (None, Some(task_index)) => stored_index(&task_history, task_index),
+        (None, None) => None,
+    };
+    let Some(task_index) = task_index else {
+        tracing::debug!("non-exist task: {:?}", payload);
+        let name = match payload.task_id {
+            Some(_) => "payload.task_id",
+            None => "payload.task_index",
+        };
+        return Err(RuntimeError::UnprocessableEntity {
+            name: name.to_string(),
+        });
+    };
+    let Some((first, mut second)) =
+        task_history[task_index].split_at(payload.at_time, payload.second_name)
+    else {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.at_time".to_string(),
+        });
+    };
+    second.id = Some(next_task_id);
+
+    let original = task_history[task_index].clone();
+    task_history.splice(task_index..=task_index, [first.clone(), second.clone()]);
+    let _: () = con
+        .json_set(
+            &key,
+            UserRecordRedisJsonPath::TaskHistory.to_string().as_str(),
+            &serde_json::json!(task_history),
+        )
+        .await?;
+    tracing::debug!("split -> task history");
+    user_record_cache.invalidate(&key);
+
+    let entry = AuditEntry::new(
+        AuditOperation::SplitTask,
+        vec![original],
+        vec![first.clone(), second.clone()],
+    );
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, vec![entry], &mut con)
+        .await;
+
+    Ok((first, second))
+}
+
+/// The audit log of a record, oldest entry first. Empty unless the log
+/// was enabled while the record changed.
+pub(super) async fn perform_get_audit_log(
+    payload: RecordKeyQuery,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+) -> Result<Vec<AuditEntry>, RuntimeError> {
+    let mut con = redis_pool.get().await.unwrap();
+    let log_key = audit_key(&key_prefix, &payload.key);
+
+    let values: Vec<String> = con.lrange(&log_key, 0, -1).await?;
+    values
+        .iter()
+        .map(|value| parse_json::<AuditEntry>(value, &log_key, "$"))
+        .collect()
+}
+
+/// Rename all tasks named `from_name` in a record, see `UserRecord::rename_tasks`.
+/// Returns how many were renamed.
+pub(super) async fn perform_rename_tasks(
+    payload: RenameTasksPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    user_record_cache: UserRecordCache,
+    lowercase_task_names: bool,
+    audit_log: AuditLog,
+) -> Result<usize, RuntimeError> {
+    let from_name = payload.from_name.trim();
+    let to_name = normalize_task_name(&payload.to_name, lowercase_task_names);
+    if from_name.is_empty() {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.from_name".to_string(),
+        });
+    }
+    if to_name.is_empty() || to_name.contains(':') {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.to_name".to_string(),
+        });
+    }
+
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    let Some(data_str) = con
+        .json_get::<&std::string::String, &str, Option<String>>(
+            &key,
+            UserRecordRedisJsonPath::Root.to_string().as_str(),
+        )
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    };
+
+    // Only the tasks are written below, so a migrated record is written first.
+    let (mut user_record, migrated) = parse_user_record(&data_str, &key)?;
+    if migrated {
+        write_user_record(&key, &user_record, &mut con).await?;
+    }
+    let original_history = user_record.task_history.clone();
+    let renamed = user_record.rename_tasks(from_name, &to_name);
+    // The history and the current task are written together, so readers
+    // never see one renamed without the other.
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    pipe.json_set(
+        &key,
+        UserRecordRedisJsonPath::TaskHistory.to_string(),
+        &serde_json::json!(user_record.task_history),
+    )?
+    .ignore();
+    pipe.json_set(
+        &key,
+        UserRecordRedisJsonPath::CurrentTask.to_string(),
+        &serde_json::json!(user_record.current_task),
+    )?
+    .ignore();
+    let _: () = pipe.query_async(&mut *con).await?;
+    tracing::debug!("renamed {} tasks", renamed);
+    user_record_cache.invalidate(&key);
+
+    let entries = original_history
+        .into_iter()
+        .zip(&user_record.task_history)
+        .filter(|(before, after)| before.name != after.name || before.parent != after.parent)
+        .map(|(before, after)| {
+            AuditEntry::new(
+                AuditOperation::RenameTask,
+                vec![before],
+                vec![after.clone()],
+            )
+        })
+        .collect();
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, entries, &mut con)
+        .await;
+
+    Ok(renamed)
+}
+
+pub(super) async fn perform_sudo_register_record(
+    payload: RegisterRecordPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
+) -> Result<(), RuntimeError> {
+    let mut con = redis_pool.get().await.unwrap();
+
+    let id = get_new_record_id(
+        UserType::SudoUser,
+        redis_pool.clone(),
+        &key_prefix,
+        &operating_info_cache,
+    )
+    .await?;
+    let _: () = con
+        .set(
+            key_prefix.apply(&OperatingRedisKey::CurrentId.to_string()),
+            id,
+        )
+        .await?;
+
+    let user_data = SudoUserRecord {
+        id,
+        user_name: payload.user_name.clone(),
+        published_tasks: vec![],
+    };
+    let user_key = generate_key(UserType::SudoUser, &payload.user_name, id);
+    let reply: Option<String> = json_set_nx_cmd(
+        &key_prefix.apply(&user_key),
+        &SudoUserRecordRedisJsonPath::Root.to_string(),
         &serde_json::json!(user_data),
     )
+    .query_async(&mut *con)
     .await?;
+    expect_created(reply, &user_key)?;
+    tracing::debug!("new_sudo_user: {:?}", user_data.user_name);
+
+    store_to_record_list(
+        UserType::SudoUser,
+        &user_key,
+        redis_pool.clone(),
+        &key_prefix,
+        &operating_info_cache,
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub(super) async fn perform_sudo_create_task(
+    payload: StoreSTaskPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    audit_log: AuditLog,
+) -> Result<(), RuntimeError> {
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    let Some(data_str) = con
+        .json_get::<&std::string::String, &str, Option<String>>(
+            &key,
+            SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
+        )
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    };
+
+    let user_data_vec = parse_json::<Vec<SudoUserRecord>>(
+        &data_str,
+        &key,
+        &SudoUserRecordRedisJsonPath::Root.to_string(),
+    )?;
+    let mut user_data = user_data_vec.into_iter().next().unwrap();
+    // Tasks published before ids existed get theirs ahead of the new one.
+    if user_data.backfill_task_ids() {
+        write_published_tasks(&key, &user_data.published_tasks, &mut con).await?;
+    }
+
+    let new_task = STask {
+        id: Some(user_data.next_task_id()),
+        name: payload.task.name,
+        description: payload.task.description,
+        created_at: chrono::offset::Local::now().naive_local(),
+    };
+
+    tracing::debug!("appending");
+    let _: () = con
+        .json_arr_append(
+            &key,
+            SudoUserRecordRedisJsonPath::PublishedTasks
+                .to_string()
+                .as_str(),
+            &serde_json::json!(new_task),
+        )
+        .await?;
+
+    let entry = AuditEntry::published(AuditOperation::CreateTask, vec![], vec![new_task]);
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, vec![entry], &mut con)
+        .await;
+    Ok(())
+}
+
+pub(super) async fn perform_sudo_update_task(
+    payload: UpdateSTaskPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    audit_log: AuditLog,
+) -> Result<STask, RuntimeError> {
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    let Some(data_str) = con
+        .json_get::<&std::string::String, &str, Option<String>>(
+            &key,
+            SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
+        )
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    };
+
+    let user_data_vec = parse_json::<Vec<SudoUserRecord>>(
+        &data_str,
+        &key,
+        &SudoUserRecordRedisJsonPath::Root.to_string(),
+    )?;
+    let mut user_data = user_data_vec.into_iter().next().unwrap();
+    // The whole list is written back below, backfilled ids with it.
+    user_data.backfill_task_ids();
+    let mut published_tasks = user_data.published_tasks;
+
+    let Some(task) = published_tasks
+        .iter_mut()
+        .find(|t| t.id == Some(payload.id))
+    else {
+        tracing::debug!("non-exist published task: {:?}", payload);
+        return Err(RuntimeError::RecordNotFound {
+            name: "payload.id".to_string(),
+        });
+    };
+    let original = task.clone();
+    if let Some(name) = payload.name {
+        task.name = name;
+    }
+    if let Some(description) = payload.description {
+        task.description = description;
+    }
+    let updated_task = task.clone();
+
+    write_published_tasks(&key, &published_tasks, &mut con).await?;
+    tracing::debug!("updated -> published tasks");
+
+    let entry = AuditEntry::published(
+        AuditOperation::UpdateTask,
+        vec![original],
+        vec![updated_task.clone()],
+    );
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, vec![entry], &mut con)
+        .await;
+    Ok(updated_task)
+}
+
+pub(super) async fn perform_sudo_reset_record(
+    payload: ResetRecordPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    audit_log: AuditLog,
+) -> Result<SudoUserRecord, RuntimeError> {
+    let parsed_key = ParsedKey::try_from(payload.key.as_str())?;
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
+
+    let Some(data_str) = con
+        .json_get::<&std::string::String, &str, Option<String>>(
+            &key,
+            SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
+        )
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    };
+    let published_tasks = parse_json::<Vec<SudoUserRecord>>(
+        &data_str,
+        &key,
+        &SudoUserRecordRedisJsonPath::Root.to_string(),
+    )?
+    .into_iter()
+    .next()
+    .unwrap()
+    .published_tasks;
+
+    let user_data = SudoUserRecord {
+        id: parsed_key.id,
+        user_name: parsed_key.user_name.to_string(),
+        published_tasks: vec![],
+    };
+    let _: () = con
+        .json_set(
+            &key,
+            SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
+            &serde_json::json!(user_data),
+        )
+        .await?;
 
+    let entry = AuditEntry::published(AuditOperation::ResetRecord, published_tasks, vec![]);
+    audit_log
+        .append_or_log(&payload.key, &key_prefix, vec![entry], &mut con)
+        .await;
     Ok(user_data)
 }
 
 pub(super) async fn perform_sudo_get_record(
     payload: GetSingleRecordPayload,
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
 ) -> Result<SudoUserRecord, RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&payload.key);
 
     let Some(data_str) = con
         .json_get::<&std::string::String, &str, Option<String>>(
-            &payload.key,
+            &key,
             SudoUserRecordRedisJsonPath::Root.to_string().as_str(),
         )
         .await?
@@ -466,32 +1464,430 @@ pub(super) async fn perform_sudo_get_record(
         });
     };
 
-    let user_data_vec = serde_json::from_str::<Vec<SudoUserRecord>>(&data_str)?;
+    let user_data_vec = parse_json::<Vec<SudoUserRecord>>(
+        &data_str,
+        &key,
+        &SudoUserRecordRedisJsonPath::Root.to_string(),
+    )?;
     let mut user_data = user_data_vec.into_iter().next().unwrap();
+    if user_data.backfill_task_ids() {
+        write_published_tasks(&key, &user_data.published_tasks, &mut con).await?;
+    }
     user_data
         .published_tasks
-        .sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        .sort_by_key(|t| std::cmp::Reverse(t.created_at));
 
     Ok(user_data)
 }
 
+/// Look a record up by its numeric id, returning its key along with it.
+pub(super) async fn perform_get_by_id(
+    payload: GetByIdPayload,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: KeyPrefix,
+    operating_info_cache: OperatingInfoCache,
+    user_record_cache: UserRecordCache,
+) -> Result<(String, serde_json::Value), RuntimeError> {
+    let operating_info =
+        get_operating_info(redis_pool.clone(), &key_prefix, &operating_info_cache).await?;
+    let keys = match payload.user_type {
+        UserType::User => &operating_info.user_list,
+        UserType::SudoUser => &operating_info.sudo_user_list,
+    };
+    let Some(key) = find_key_by_id(keys, payload.id) else {
+        tracing::debug!("non-exist record: {:?}", payload);
+        return Err(RuntimeError::RecordNotFound {
+            name: "payload.id".to_string(),
+        });
+    };
+
+    let lookup = GetSingleRecordPayload {
+        key: key.to_string(),
+        include_history: true,
+    };
+    let record = match payload.user_type {
+        UserType::User => serde_json::json!(
+            perform_get_user_record(lookup, redis_pool, key_prefix, user_record_cache).await?
+        ),
+        UserType::SudoUser => {
+            serde_json::json!(perform_sudo_get_record(lookup, redis_pool, key_prefix).await?)
+        }
+    };
+
+    Ok((key.to_string(), record))
+}
+
+/// Listed keys that don't parse are skipped, they can't be asked for by id.
+fn find_key_by_id(keys: &[String], id: i32) -> Option<&str> {
+    keys.iter()
+        .map(String::as_str)
+        .find(|key| ParsedKey::try_from(*key).is_ok_and(|parsed| parsed.id == id))
+}
+
+/// Response of a multi-path `JSON.GET` on a user record, each path
+/// mapping to the array of its matches. Paths are kept as stored, so the
+/// record can go through `migrations::migrate` like a full read.
+#[derive(serde::Deserialize)]
+struct UserRecordProjection {
+    #[serde(rename = "$.id", default)]
+    id: Vec<serde_json::Value>,
+    #[serde(rename = "$.user_name", default)]
+    user_name: Vec<serde_json::Value>,
+    #[serde(rename = "$.current_task", default)]
+    current_task: Vec<serde_json::Value>,
+    #[serde(rename = "$.daily_goal_seconds", default)]
+    daily_goal_seconds: Vec<serde_json::Value>,
+    #[serde(rename = "$.last_active_at", default)]
+    last_active_at: Vec<serde_json::Value>,
+    #[serde(rename = "$.hourly_rate", default)]
+    hourly_rate: Vec<serde_json::Value>,
+    #[serde(rename = "$.webhook_url", default)]
+    webhook_url: Vec<serde_json::Value>,
+    #[serde(rename = "$.timezone", default)]
+    timezone: Vec<serde_json::Value>,
+    #[serde(rename = "$.schema_version", default)]
+    schema_version: Vec<serde_json::Value>,
+}
+
+impl UserRecordProjection {
+    /// The projected record at `key`, read from `path`, upgraded to the
+    /// current schema and with an empty `task_history`. A missing path
+    /// reads as `null`, so a key that isn't a full record is malformed.
+    fn into_record(self, key: &str, path: &str) -> Result<UserRecord, RuntimeError> {
+        let first =
+            |matches: Vec<serde_json::Value>| matches.into_iter().next().unwrap_or_default();
+        let stored = serde_json::json!({
+            "id": first(self.id),
+            "user_name": first(self.user_name),
+            "task_history": [],
+            "current_task": first(self.current_task),
+            "daily_goal_seconds": first(self.daily_goal_seconds),
+            "last_active_at": first(self.last_active_at),
+            "hourly_rate": first(self.hourly_rate),
+            "webhook_url": first(self.webhook_url),
+            "timezone": first(self.timezone),
+            "schema_version": first(self.schema_version),
+        });
+        let (record, _) = migrations::migrate(key, stored);
+        serde_json::from_value(record).map_err(|source| RuntimeError::MalformedRecord {
+            key: key.to_string(),
+            path: path.to_string(),
+            source,
+        })
+    }
+}
+
+/// The parts of a user record grouped stats need, see `UserRecordProjection`.
+#[derive(serde::Deserialize)]
+struct BillingProjection {
+    #[serde(rename = "$.task_history")]
+    task_history: Vec<Vec<Task>>,
+    #[serde(rename = "$.hourly_rate", default)]
+    hourly_rate: Vec<Option<HourlyRate>>,
+}
+
+/// Read a user record without fetching its `task_history`,
+/// which is returned empty.
+async fn get_user_record_without_history(
+    key: &str,
+    con: &mut redis::aio::Connection,
+) -> Result<UserRecord, RuntimeError> {
+    let paths = vec![
+        UserRecordRedisJsonPath::Id.to_string(),
+        UserRecordRedisJsonPath::UserName.to_string(),
+        UserRecordRedisJsonPath::CurrentTask.to_string(),
+        UserRecordRedisJsonPath::DailyGoalSeconds.to_string(),
+        UserRecordRedisJsonPath::LastActiveAt.to_string(),
+        UserRecordRedisJsonPath::HourlyRate.to_string(),
+        UserRecordRedisJsonPath::WebhookUrl.to_string(),
+        UserRecordRedisJsonPath::Timezone.to_string(),
+        UserRecordRedisJsonPath::SchemaVersion.to_string(),
+    ];
+    let Some(data_str) = con
+        .json_get::<&str, &Vec<String>, Option<String>>(key, &paths)
+        .await?
+    else {
+        tracing::debug!("non-exist record: {:?}", key);
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.key".to_string(),
+        });
+    };
+
+    let path = paths.join(", ");
+    let projection = parse_json::<UserRecordProjection>(&data_str, key, &path)?;
+
+    projection.into_record(key, &path)
+}
+
+/// Stored index of the task at `shown_index` of the history as
+/// `perform_get_user_record` returns it, newest first.
+fn stored_index(task_history: &[Task], shown_index: usize) -> Option<usize> {
+    let mut order: Vec<usize> = (0..task_history.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(task_history[index].begin_time));
+    order.get(shown_index).copied()
+}
+
+/// Store all `published_tasks` of the sudo record at `key`.
+async fn write_published_tasks(
+    key: &str,
+    published_tasks: &[STask],
+    con: &mut redis::aio::Connection,
+) -> Result<(), RuntimeError> {
+    let _: () = con
+        .json_set(
+            key,
+            SudoUserRecordRedisJsonPath::PublishedTasks
+                .to_string()
+                .as_str(),
+            &serde_json::json!(published_tasks),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Store the whole of `user_record`, after it was migrated or backfilled.
+async fn write_user_record(
+    key: &str,
+    user_record: &UserRecord,
+    con: &mut redis::aio::Connection,
+) -> Result<(), RuntimeError> {
+    let _: () = con
+        .json_set(
+            key,
+            UserRecordRedisJsonPath::Root.to_string(),
+            &serde_json::json!(user_record),
+        )
+        .await?;
+    tracing::debug!("rewritten -> {:?}", key);
+
+    Ok(())
+}
+
+/// Stamp `last_active_at` with the current time, after a task write.
+async fn touch_last_active(
+    key: &str,
+    con: &mut redis::aio::Connection,
+) -> Result<(), RuntimeError> {
+    let _: () = con
+        .json_set(
+            key,
+            UserRecordRedisJsonPath::LastActiveAt.to_string().as_str(),
+            &serde_json::json!(chrono::offset::Local::now().naive_local()),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Number of entries in a record's `task_history`, without fetching it.
+/// A missing record or path counts as 0.
+async fn get_task_history_len(
+    key: &str,
+    con: &mut redis::aio::Connection,
+) -> Result<usize, RuntimeError> {
+    let lens: Vec<Option<usize>> = con
+        .json_arr_len(key, UserRecordRedisJsonPath::TaskHistory.to_string())
+        .await?;
+    Ok(lens.into_iter().next().flatten().unwrap_or(0))
+}
+
+/// Deserialize a RedisJSON reply, keeping the key and path it was read from
+/// so a corrupt record can be pinpointed from the logs.
+fn parse_json<T: DeserializeOwned>(
+    data_str: &str,
+    key: &str,
+    path: &str,
+) -> Result<T, RuntimeError> {
+    serde_json::from_str(data_str).map_err(|source| RuntimeError::MalformedRecord {
+        key: key.to_string(),
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Read a stored user record, upgraded by `migrations::migrate`. Also
+/// returns whether a migration ran, i.e. whether to write it back.
+fn parse_user_record(data_str: &str, key: &str) -> Result<(UserRecord, bool), RuntimeError> {
+    let path = UserRecordRedisJsonPath::Root.to_string();
+    let stored = parse_json::<Vec<serde_json::Value>>(data_str, key, &path)?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    let (record, migrated) = migrations::migrate(key, stored);
+    let record =
+        serde_json::from_value(record).map_err(|source| RuntimeError::MalformedRecord {
+            key: key.to_string(),
+            path,
+            source,
+        })?;
+    Ok((record, migrated))
+}
+
+/// Minimum number of digits of the id part of a key. Longer ids are kept whole.
+const KEY_ID_WIDTH: usize = 4;
+
+/// `JSON.SET key path value NX`, which only writes if `key` doesn't exist
+/// yet, replying `OK` when it wrote and nil otherwise.
+fn json_set_nx_cmd(key: &str, path: &str, value: &serde_json::Value) -> redis::Cmd {
+    let mut cmd = redis::cmd("JSON.SET");
+    cmd.arg(key).arg(path).arg(value.to_string()).arg("NX");
+    cmd
+}
+
+/// `JSON.MERGE key path value`, an RFC 7396 merge patch: members of `value`
+/// replace those at `path`, `null` ones are removed and the rest are kept.
+fn json_merge_cmd(key: &str, path: &str, value: &serde_json::Value) -> redis::Cmd {
+    let mut cmd = redis::cmd("JSON.MERGE");
+    cmd.arg(key).arg(path).arg(value.to_string());
+    cmd
+}
+
+/// A new record must never overwrite an existing one, e.g. after an id
+/// collision, so a `JSON.SET ... NX` that didn't write is a conflict.
+fn expect_created(reply: Option<String>, key: &str) -> Result<(), RuntimeError> {
+    match reply {
+        Some(_) => Ok(()),
+        None => {
+            tracing::warn!("record already exists: {:?}", key);
+            Err(RuntimeError::Conflict {
+                message: "A record with this key already exists".to_string(),
+                data: Some(serde_json::json!({ "key": key })),
+            })
+        }
+    }
+}
+
+/// Counter of the appends to the record at `key` during the minute of `now`.
+/// Each minute gets a new counter, which expires once the minute is over.
+fn append_counter_key(key: &str, now: NaiveDateTime) -> String {
+    format!("append_count:{}:{}", key, now.format("%Y%m%d%H%M"))
+}
+
+/// Conflict naming `task` and the logged task it overlaps.
+fn overlap_conflict(task: &Task, existing: &Task) -> RuntimeError {
+    RuntimeError::Conflict {
+        message: format!(
+            "Task \"{}\" overlaps \"{}\" logged from {} to {}",
+            task.name,
+            existing.name,
+            existing.begin_time.format("%Y-%m-%d %H:%M:%S"),
+            existing.end_time.format("%Y-%m-%d %H:%M:%S"),
+        ),
+        data: Some(serde_json::json!({
+            "task": task,
+            "conflicting_task": existing,
+        })),
+    }
+}
+
+/// Refuse moving `current_task` to `state` unless the state machine allows it.
+fn check_transition(current_task: &Task, state: &TaskState) -> Result<(), RuntimeError> {
+    if current_task.state.can_transition_to(state) {
+        return Ok(());
+    }
+    tracing::debug!(
+        "invalid transition: {:?} -> {:?}",
+        current_task.state,
+        state
+    );
+    Err(RuntimeError::Conflict {
+        message: format!("Cannot move a {:?} task to {:?}", current_task.state, state),
+        data: Some(serde_json::json!({
+            "current_task": current_task,
+        })),
+    })
+}
+
+/// Add `imported` to the history. The entry of an active current task stays
+/// last, where task writes expect it, and the imported ones get ids.
+fn import_into_history(user_data: &mut UserRecord, imported: Vec<Task>) {
+    let at = match user_data.current_task.state.is_active() {
+        true => user_data.task_history.len().saturating_sub(1),
+        false => user_data.task_history.len(),
+    };
+    user_data.task_history.splice(at..at, imported);
+    user_data.backfill_task_ids();
+}
+
+/// Refuse an import if one of its tasks overlaps a logged one, or one
+/// imported before it.
+fn check_import_overlaps(task_history: &[Task], imported: &[Task]) -> Result<(), RuntimeError> {
+    for (i, task) in imported.iter().enumerate() {
+        let logged = task_history.iter().chain(&imported[..i]);
+        if let Some(existing) = find_overlap(logged, task.begin_time, Some(task.end_time)) {
+            tracing::debug!("imported task overlaps a logged one: {:?}", task.name);
+            return Err(overlap_conflict(task, existing));
+        }
+    }
+    Ok(())
+}
+
+/// Refuse the `count`th append of the current minute if it's over `limit`.
+/// A client appending in a loop could otherwise grow a history without bound.
+fn check_append_limit(count: u64, limit: u32, now: NaiveDateTime) -> Result<(), RuntimeError> {
+    if count <= u64::from(limit) {
+        return Ok(());
+    }
+    tracing::warn!("append limit reached: {} in this minute", count);
+    Err(RuntimeError::TooManyRequests {
+        retry_after_seconds: u64::from(60 - chrono::Timelike::second(&now)),
+    })
+}
+
 fn generate_key(user_type: UserType, user_name: &str, id: i32) -> String {
-    let id_length = successors(Some(id), |&n| (n >= 10).then_some(n / 10)).count();
-    let filler_length = 4 - id_length;
     format!(
-        "{}:{}:{}{}",
+        "{}:{}:{:0width$}",
         user_type,
         user_name,
-        "0".repeat(filler_length),
-        id
+        id,
+        width = KEY_ID_WIDTH
     )
 }
 
+/// Read `operating_info`, served from the in-process cache while it is fresh.
+async fn get_operating_info(
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: &KeyPrefix,
+    operating_info_cache: &OperatingInfoCache,
+) -> Result<OperatingInfo, RuntimeError> {
+    if let Some(operating_info) = operating_info_cache.get() {
+        return Ok(operating_info);
+    }
+
+    let mut con = redis_pool.get().await.unwrap();
+    let key = key_prefix.apply(&OperatingRedisKey::OperatingInfo.to_string());
+    let path = OperatingInfoRedisJsonPath::Root.to_string();
+    let info_resp_str: String = con.json_get(key.as_str(), path.as_str()).await?;
+    let info_resp = parse_json::<Vec<OperatingInfo>>(&info_resp_str, &key, &path)?;
+    let operating_info = info_resp.into_iter().next().unwrap();
+
+    operating_info_cache.set(operating_info.clone());
+
+    Ok(operating_info)
+}
+
 /// Get new incremented ID when creating a new record.
+///
+/// The increment always goes through Redis, never through the cache,
+/// so concurrent registrations can't be handed the same ID.
 async fn get_new_record_id(
     user_type: UserType,
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: &KeyPrefix,
+    operating_info_cache: &OperatingInfoCache,
 ) -> Result<i32, RuntimeError> {
+    let ids =
+        reserve_record_ids(user_type, 1, redis_pool, key_prefix, operating_info_cache).await?;
+    Ok(*ids.end())
+}
+
+/// Reserve `count` consecutive IDs with a single increment.
+async fn reserve_record_ids(
+    user_type: UserType,
+    count: i32,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: &KeyPrefix,
+    operating_info_cache: &OperatingInfoCache,
+) -> Result<RangeInclusive<i32>, RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
 
     let id_path = match user_type {
@@ -499,34 +1895,592 @@ async fn get_new_record_id(
         UserType::SudoUser => OperatingInfoRedisJsonPath::LatestSudoRecordId.to_string(),
     };
 
-    match con
-        .json_get(OperatingRedisKey::OperatingInfo.to_string(), &id_path)
-        .await?
-    {
-        Some(id) => Ok(id),
-        _ => Ok(0),
+    let key = key_prefix.apply(&OperatingRedisKey::OperatingInfo.to_string());
+    let id_resp_str: String = con.json_num_incr_by(&key, &id_path, count as i64).await?;
+    operating_info_cache.invalidate();
+
+    let id_resp = parse_json::<Vec<i32>>(&id_resp_str, &key, &id_path)?;
+    let last_id = id_resp.into_iter().next().unwrap();
+    Ok(last_id - count + 1..=last_id)
+}
+
+/// Check a batch of user names before anything is written,
+/// so a bad name rejects the whole batch.
+fn validate_user_names(user_names: &[String]) -> Result<(), RuntimeError> {
+    if user_names.is_empty() {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: "payload.user_names".to_string(),
+        });
+    }
+
+    let mut seen = HashSet::new();
+    for (i, user_name) in user_names.iter().enumerate() {
+        let is_valid = !user_name.is_empty()
+            && !user_name.contains(':')
+            && !user_name.contains(char::is_whitespace);
+        if !is_valid || !seen.insert(user_name) {
+            return Err(RuntimeError::UnprocessableEntity {
+                name: format!("payload.user_names[{}]", i),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn record_list_path(user_type: UserType) -> String {
+    match user_type {
+        UserType::User => OperatingInfoRedisJsonPath::UserList.to_string(),
+        UserType::SudoUser => OperatingInfoRedisJsonPath::SudoUserList.to_string(),
     }
 }
 
-/// Store newly created record's name to an according list.
+/// Add a record key, without the namespace prefix, to the list of its user type.
 async fn store_to_record_list(
     user_type: UserType,
-    user_name: &str,
+    user_key: &str,
     redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: &KeyPrefix,
+    operating_info_cache: &OperatingInfoCache,
 ) -> Result<(), RuntimeError> {
     let mut con = redis_pool.get().await.unwrap();
 
-    let key = match user_type {
-        UserType::User => OperatingInfoRedisJsonPath::UserList.to_string(),
-        UserType::SudoUser => OperatingInfoRedisJsonPath::SudoUserList.to_string(),
+    let _: () = con
+        .json_arr_append(
+            key_prefix.apply(&OperatingRedisKey::OperatingInfo.to_string()),
+            record_list_path(user_type),
+            &user_key,
+        )
+        .await?;
+    operating_info_cache.invalidate();
+
+    Ok(())
+}
+
+/// Remove a record key from the list of its user type. A key that isn't
+/// listed is left alone, so removing twice is fine.
+async fn remove_from_record_list(
+    user_type: UserType,
+    user_key: &str,
+    redis_pool: Pool<RedisConnectionManager>,
+    key_prefix: &KeyPrefix,
+    operating_info_cache: &OperatingInfoCache,
+) -> Result<(), RuntimeError> {
+    let mut con = redis_pool.get().await.unwrap();
+    let operating_info_key = key_prefix.apply(&OperatingRedisKey::OperatingInfo.to_string());
+    let list_path = record_list_path(user_type);
+
+    // `-1` when the key isn't in the list.
+    let indices: Vec<Option<i64>> = con
+        .json_arr_index(&operating_info_key, &list_path, &user_key)
+        .await?;
+    let Some(index) = indices.into_iter().next().flatten().filter(|i| *i >= 0) else {
+        tracing::debug!("not listed: {:?}", user_key);
+        return Ok(());
     };
 
-    con.json_arr_append(
-        OperatingRedisKey::OperatingInfo.to_string(),
-        &key,
-        &user_name,
-    )
-    .await?;
+    let _: () = con
+        .json_arr_pop(&operating_info_key, &list_path, index)
+        .await?;
+    operating_info_cache.invalidate();
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use libs::record::SCHEMA_VERSION;
+
+    use super::*;
+
+    #[test]
+    fn test_should_match_user_names_ignoring_case() {
+        let keys: Vec<String> = [
+            "user:Alice:0001",
+            "user:bob:0002",
+            "user:alicia:0003",
+            "sudo:malice:0000",
+            "carol",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        assert_eq!(
+            keys_matching_name(keys.clone(), Some("ALI")),
+            ["user:Alice:0001", "user:alicia:0003", "sudo:malice:0000"]
+        );
+        assert_eq!(keys_matching_name(keys.clone(), Some("ro")), ["carol"]);
+        // The id and user type aren't part of the name.
+        assert!(keys_matching_name(keys.clone(), Some("0002")).is_empty());
+        assert_eq!(keys_matching_name(keys.clone(), None), keys);
+    }
+
+    #[test]
+    fn test_should_cap_rapid_appends() {
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(9, 15, 45)
+            .unwrap();
+        assert_eq!(
+            append_counter_key("user:alice:0001", now),
+            "append_count:user:alice:0001:202401310915"
+        );
+
+        let results: Vec<_> = (1..=4)
+            .map(|count| check_append_limit(count, 3, now))
+            .collect();
+        assert!(results[..3].iter().all(Result::is_ok));
+        match &results[3] {
+            Err(RuntimeError::TooManyRequests {
+                retry_after_seconds,
+            }) => assert_eq!(*retry_after_seconds, 15),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_should_refuse_overlapping_imports() {
+        let at = |h: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let task = |name: &str, from: u32, to: u32| Task {
+            state: TaskState::End,
+            begin_time: at(from),
+            end_time: at(to),
+            ..Task::generate_begin_task(name.to_string())
+        };
+        let history = vec![task("review", 9, 11)];
+
+        assert!(check_import_overlaps(&history, &[task("docs", 11, 12)]).is_ok());
+        match check_import_overlaps(&history, &[task("docs", 10, 12)]) {
+            Err(RuntimeError::Conflict { message, data }) => {
+                assert_eq!(
+                    message,
+                    "Task \"docs\" overlaps \"review\" logged from 2024-01-31 09:00:00 to 2024-01-31 11:00:00"
+                );
+                assert_eq!(data.unwrap()["conflicting_task"]["name"], "review");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // Rows of the same import are checked against each other too.
+        let rows = [task("docs", 12, 14), task("tests", 13, 15)];
+        assert!(check_import_overlaps(&history, &rows).is_err());
+    }
+
+    #[test]
+    fn test_should_refuse_transitions_after_end() {
+        let done = Task::generate_done_task(&Task::generate_begin_task("review".to_string()));
+        for state in [TaskState::Break, TaskState::Back, TaskState::End] {
+            match check_transition(&done, &state) {
+                Err(RuntimeError::Conflict { message, .. }) => {
+                    assert_eq!(message, format!("Cannot move a End task to {:?}", state));
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        let writing = Task::generate_begin_task("writing".to_string());
+        assert!(check_transition(&writing, &TaskState::Break).is_ok());
+        assert!(check_transition(&writing, &TaskState::End).is_ok());
+    }
+
+    #[test]
+    fn test_should_import_before_the_active_task() {
+        let active = Task {
+            id: Some(1),
+            ..Task::generate_begin_task("writing".to_string())
+        };
+        let mut user_data = UserRecord {
+            task_history: vec![
+                Task {
+                    id: Some(0),
+                    ..Task::generate_done_task(&Task::generate_begin_task("review".to_string()))
+                },
+                active.clone(),
+            ],
+            current_task: active,
+            ..UserRecord::new(7, "alice".to_string())
+        };
+        let imported = Task::generate_done_task(&Task::generate_begin_task("docs".to_string()));
+        import_into_history(&mut user_data, vec![imported]);
+
+        let names: Vec<&str> = user_data
+            .task_history
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["review", "docs", "writing"]);
+        assert_eq!(user_data.task_history[1].id, Some(2));
+
+        // Finishing the active task replaces its entry, as `perform_create_task` does.
+        let replaced = user_data.task_history.pop().unwrap();
+        assert_eq!(replaced.id, user_data.current_task.id);
+        assert_eq!(user_data.task_history.len(), 2);
+        assert_eq!(user_data.task_history[1].name, "docs");
+    }
+
+    #[test]
+    fn test_should_merge_settings_at_root() {
+        let cmd = json_merge_cmd(
+            "user:alice:0001",
+            "$",
+            &serde_json::json!({ "timezone": null }),
+        );
+        let packed = String::from_utf8(cmd.get_packed_command()).unwrap();
+        assert_eq!(
+            packed,
+            "*4\r\n$10\r\nJSON.MERGE\r\n$15\r\nuser:alice:0001\r\n$1\r\n$\r\n$17\r\n{\"timezone\":null}\r\n"
+        );
+    }
+
+    #[test]
+    fn test_should_not_register_onto_existing_key() {
+        let cmd = json_set_nx_cmd("user:alice:0001", "$", &serde_json::json!({ "id": 1 }));
+        let packed = String::from_utf8(cmd.get_packed_command()).unwrap();
+        assert!(packed.starts_with("*5\r\n$8\r\nJSON.SET\r\n"));
+        assert!(packed.ends_with("$2\r\nNX\r\n"));
+
+        // Redis replies nil when the key was already there.
+        assert!(expect_created(Some("OK".to_string()), "user:alice:0001").is_ok());
+        match expect_created(None, "user:alice:0001") {
+            Err(RuntimeError::Conflict { data, .. }) => {
+                assert_eq!(data, Some(serde_json::json!({ "key": "user:alice:0001" })))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_should_validate_bulk_user_names() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert!(validate_user_names(&names(&["alice", "bob"])).is_ok());
+        assert!(validate_user_names(&[]).is_err());
+
+        for (batch, field) in [
+            (names(&["alice", "alice"]), "payload.user_names[1]"),
+            (names(&["alice", ""]), "payload.user_names[1]"),
+            (names(&["a:b"]), "payload.user_names[0]"),
+            (names(&["bob", "carol dan"]), "payload.user_names[1]"),
+        ] {
+            match validate_user_names(&batch) {
+                Err(RuntimeError::UnprocessableEntity { name }) => assert_eq!(name, field),
+                other => panic!("unexpected result for {:?}: {:?}", batch, other),
+            }
+        }
+    }
+
+    fn end_task_at(name: &str, begin_time: NaiveDateTime, duration: i64) -> Task {
+        Task {
+            name: name.to_string(),
+            state: TaskState::End,
+            begin_time,
+            end_time: begin_time + chrono::Duration::seconds(duration),
+            duration: Seconds(duration),
+            ..Task::default()
+        }
+    }
+
+    #[test]
+    fn test_should_group_totals() {
+        let at = |d: u32, h: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let mut active_task = end_task_at("writing", at(31, 16), 60);
+        active_task.state = TaskState::Begin;
+        let tasks = vec![
+            end_task_at("writing", at(28, 9), 3600),
+            end_task_at("writing", at(29, 9), 1800),
+            end_task_at("review", at(29, 14), 600),
+            end_task_at("writing", at(31, 9), 900),
+            active_task,
+        ];
+        let (from, to) = (at(29, 0), at(31, 23));
+
+        let by_day = group_totals(&tasks, from, to, GroupBy::Day, None);
+        assert_eq!(
+            by_day,
+            vec![
+                GroupTotal {
+                    group: "2024-01-29".to_string(),
+                    seconds: Seconds(2400),
+                    billable_seconds: Seconds(0),
+                    tasks: 2,
+                    earnings: None,
+                },
+                GroupTotal {
+                    group: "2024-01-31".to_string(),
+                    seconds: Seconds(900),
+                    billable_seconds: Seconds(0),
+                    tasks: 1,
+                    earnings: None,
+                },
+            ]
+        );
+
+        let by_task = group_totals(&tasks, from, to, GroupBy::Task, None);
+        let by_task: Vec<(&str, Seconds)> = by_task
+            .iter()
+            .map(|g| (g.group.as_str(), g.seconds))
+            .collect();
+        assert_eq!(
+            by_task,
+            vec![("review", Seconds(600)), ("writing", Seconds(2700))]
+        );
+
+        // 2024-01-28 is a Sunday, the end of ISO week 4.
+        let by_week = group_totals(&tasks, at(28, 0), to, GroupBy::Week, None);
+        let by_week: Vec<(&str, usize)> = by_week
+            .iter()
+            .map(|g| (g.group.as_str(), g.tasks))
+            .collect();
+        assert_eq!(by_week, vec![("2024-W04", 1), ("2024-W05", 3)]);
+    }
+
+    #[test]
+    fn test_should_sum_billable_time() {
+        let at = |h: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        let billable = |task: Task| Task {
+            billable: true,
+            ..task
+        };
+        let tasks = vec![
+            billable(end_task_at("client", at(9), 3600)),
+            end_task_at("reading", at(11), 1800),
+            billable(end_task_at("client", at(14), 900)),
+        ];
+        let (from, to) = (at(0), at(23));
+
+        let all = group_totals(&tasks, from, to, GroupBy::Day, None);
+        assert_eq!(all[0].seconds, Seconds(6300));
+        assert_eq!(all[0].billable_seconds, Seconds(4500));
+
+        let only_billable = group_totals(&tasks, from, to, GroupBy::Day, Some(true));
+        assert_eq!(only_billable[0].seconds, Seconds(4500));
+        assert_eq!(only_billable[0].tasks, 2);
+
+        let personal = group_totals(&tasks, from, to, GroupBy::Task, Some(false));
+        let personal: Vec<&str> = personal.iter().map(|g| g.group.as_str()).collect();
+        assert_eq!(personal, vec!["reading"]);
+    }
+
+    #[test]
+    fn test_should_generate_key_in_record_scheme() {
+        assert_eq!(generate_key(UserType::User, "alice", 1), "user:alice:0001");
+        assert_eq!(generate_key(UserType::SudoUser, "bob", 42), "sudo:bob:0042");
+        assert_eq!(
+            generate_key(UserType::User, "carol", 10000),
+            "user:carol:10000"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_get_registered_record_back() {
+        let key = generate_key(UserType::User, "alice", 7);
+        let registered = UserRecord::new(7, "alice".to_string());
+
+        // Stored as `perform_register_record` writes it, and read back as
+        // `JSON.GET $` answers: wrapped in an array.
+        let stored = serde_json::json!([registered]).to_string();
+        let (stored, migrated) = parse_user_record(&stored, &key).unwrap();
+        assert!(!migrated);
+
+        // A cached record is served without a connection.
+        let user_record_cache = UserRecordCache::default();
+        user_record_cache.set(&key, stored);
+        let redis_manager = RedisConnectionManager::new("redis://127.0.0.1:6379").unwrap();
+        let record = perform_get_user_record(
+            GetSingleRecordPayload {
+                key: key.clone(),
+                include_history: true,
+            },
+            Pool::builder().build_unchecked(redis_manager),
+            KeyPrefix::default(),
+            user_record_cache,
+        )
+        .await
+        .unwrap();
+
+        let parsed = ParsedKey::try_from(key.as_str()).unwrap();
+        assert_eq!(record.id, parsed.id);
+        assert_eq!(record.user_name, parsed.user_name);
+        assert!(record.current_task.is_placeholder());
+        assert!(record.task_history.is_empty());
+        assert_eq!(record.schema_version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_should_index_history_as_it_is_shown() {
+        let at = |h: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+        // Imported tasks can land out of order in the stored history.
+        let stored = vec![
+            end_task_at("morning", at(9), 3600),
+            end_task_at("evening", at(17), 3600),
+            end_task_at("noon", at(12), 3600),
+        ];
+        let mut shown = stored.clone();
+        shown.sort_by_key(|t| std::cmp::Reverse(t.begin_time));
+
+        for (shown_index, task) in shown.iter().enumerate() {
+            let index = stored_index(&stored, shown_index).unwrap();
+            assert_eq!(stored[index].name, task.name);
+        }
+        assert_eq!(stored_index(&stored, 3), None);
+    }
+
+    #[test]
+    fn test_should_find_key_by_id() {
+        let keys = vec![
+            "user:alice:0001".to_string(),
+            "user:broken".to_string(),
+            "user:bob:0002".to_string(),
+            "user:carol:10000".to_string(),
+        ];
+
+        assert_eq!(find_key_by_id(&keys, 2), Some("user:bob:0002"));
+        assert_eq!(find_key_by_id(&keys, 10000), Some("user:carol:10000"));
+        assert_eq!(find_key_by_id(&keys, 0), None);
+    }
+
+    #[test]
+    fn test_should_point_record_lists_at_operating_info_fields() {
+        let operating_info = serde_json::json!(OperatingInfo {
+            latest_record_id: 1,
+            latest_sudo_record_id: 1,
+            user_list: vec!["user:alice:0001".to_string()],
+            sudo_user_list: vec!["sudo:bob:0001".to_string()],
+        });
+        let field = |user_type| {
+            let path = record_list_path(user_type);
+            operating_info[path.trim_start_matches("$.")].clone()
+        };
+
+        assert_eq!(
+            field(UserType::User),
+            serde_json::json!(["user:alice:0001"])
+        );
+        assert_eq!(
+            field(UserType::SudoUser),
+            serde_json::json!(["sudo:bob:0001"])
+        );
+    }
+
+    #[test]
+    fn test_should_parse_stats_projection() {
+        let data_str = serde_json::json!({
+            UserRecordRedisJsonPath::CurrentTaskState.to_string(): ["Begin"],
+            UserRecordRedisJsonPath::EndTaskBeginTimes.to_string(): [
+                "2024-01-31T09:00:00",
+                "2024-01-30T09:00:00",
+            ],
+        })
+        .to_string();
+
+        let projection = serde_json::from_str::<StatsProjection>(&data_str).unwrap();
+
+        assert_eq!(projection.current_task_state, vec![TaskState::Begin]);
+        assert_eq!(projection.end_task_begin_times.len(), 2);
+    }
+
+    #[test]
+    fn test_should_parse_record_projection_with_settings() {
+        let task = Task::placeholder("initialised", TaskState::Placeholder);
+        let projection = |settings: serde_json::Value| {
+            let mut data = serde_json::json!({
+                UserRecordRedisJsonPath::Id.to_string(): [1],
+                UserRecordRedisJsonPath::UserName.to_string(): ["alice"],
+                UserRecordRedisJsonPath::CurrentTask.to_string(): [&task],
+            });
+            data.as_object_mut()
+                .unwrap()
+                .extend(settings.as_object().unwrap().clone());
+            serde_json::from_value::<UserRecordProjection>(data)
+                .unwrap()
+                .into_record("user:alice:0001", "$")
+                .unwrap()
+        };
+
+        let record = projection(serde_json::json!({
+            UserRecordRedisJsonPath::WebhookUrl.to_string(): ["https://example.com/hook"],
+            UserRecordRedisJsonPath::Timezone.to_string(): ["Europe/Berlin"],
+        }));
+        assert_eq!(
+            record.webhook_url.as_deref(),
+            Some("https://example.com/hook")
+        );
+        assert_eq!(record.timezone.as_deref(), Some("Europe/Berlin"));
+
+        // Records stored before the settings existed have no such paths.
+        let record = projection(serde_json::json!({}));
+        assert_eq!(record.webhook_url, None);
+        assert_eq!(record.timezone, None);
+    }
+
+    #[test]
+    fn test_should_migrate_or_refuse_projected_records() {
+        // An unversioned record still storing `break_seconds`.
+        let stored = serde_json::json!({
+            UserRecordRedisJsonPath::Id.to_string(): [1],
+            UserRecordRedisJsonPath::UserName.to_string(): ["alice"],
+            UserRecordRedisJsonPath::CurrentTask.to_string(): [{
+                "name": "writing",
+                "state": "Back",
+                "begin_time": "2024-01-31T10:30:00",
+                "end_time": "2024-01-31T10:45:00",
+                "duration": 900,
+                "break_seconds": 300
+            }],
+        });
+        let record = serde_json::from_value::<UserRecordProjection>(stored)
+            .unwrap()
+            .into_record("user:alice:0001", "$")
+            .unwrap();
+        assert_eq!(record.schema_version, SCHEMA_VERSION);
+        assert_eq!(record.current_task.total_break_seconds, Seconds(300));
+
+        // A key holding something else than a user record.
+        let other = serde_json::json!({
+            UserRecordRedisJsonPath::Id.to_string(): [1],
+        });
+        let err = serde_json::from_value::<UserRecordProjection>(other)
+            .unwrap()
+            .into_record("user:alice:0001", "$.id, $.user_name")
+            .unwrap_err();
+        match err {
+            RuntimeError::MalformedRecord { key, path, .. } => {
+                assert_eq!(key, "user:alice:0001");
+                assert_eq!(path, "$.id, $.user_name");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_should_attach_key_and_path_to_malformed_record() {
+        let err = parse_json::<Vec<UserRecord>>("[{\"id\": \"oops\"}]", "user:alice:0001", "$")
+            .unwrap_err();
+
+        match err {
+            RuntimeError::MalformedRecord { key, path, .. } => {
+                assert_eq!(key, "user:alice:0001");
+                assert_eq!(path, "$");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
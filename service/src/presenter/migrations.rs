@@ -0,0 +1,204 @@
+use libs::record::SCHEMA_VERSION;
+use serde_json::Value;
+
+/// Upgrades a stored user record by one schema version. Steps work on the
+/// raw JSON, so they don't depend on today's `UserRecord` being able to
+/// read the shape they upgrade from.
+pub type Migration = fn(Value) -> Value;
+
+/// `MIGRATIONS[v]` upgrades a version `v` record to `v + 1`.
+pub const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [add_task_ids, rename_break_seconds];
+
+/// Version a stored record is in. Records from before versioning have none.
+pub fn schema_version(record: &Value) -> u32 {
+    record
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .map_or(0, |version| version as u32)
+}
+
+/// Upgrade `record`, stored at `key`, to `SCHEMA_VERSION`. Returns whether
+/// any step ran, i.e. whether the record should be written back.
+pub fn migrate(key: &str, record: Value) -> (Value, bool) {
+    migrate_with(&MIGRATIONS, key, record)
+}
+
+fn migrate_with(migrations: &[Migration], key: &str, mut record: Value) -> (Value, bool) {
+    let from = schema_version(&record) as usize;
+    for (version, step) in migrations.iter().enumerate().skip(from) {
+        record = step(record);
+        if let Some(fields) = record.as_object_mut() {
+            fields.insert("schema_version".to_string(), Value::from(version + 1));
+        }
+        tracing::info!("migrated {:?} to schema version {}", key, version + 1);
+    }
+    (record, from < migrations.len())
+}
+
+fn has_id(task: &Value) -> bool {
+    task.get("id").is_some_and(|id| !id.is_null())
+}
+
+/// 1: give tasks recorded before ids existed one, in history order, and the
+/// current task the id of its history entry.
+fn add_task_ids(mut record: Value) -> Value {
+    if let Some(history) = record.get_mut("task_history").and_then(Value::as_array_mut) {
+        let mut next_id = history
+            .iter()
+            .filter_map(|t| t.get("id")?.as_i64())
+            .map(|id| id + 1)
+            .max()
+            .unwrap_or(0);
+        for task in history.iter_mut().filter(|t| !has_id(t)) {
+            if let Some(fields) = task.as_object_mut() {
+                fields.insert("id".to_string(), Value::from(next_id));
+                next_id += 1;
+            }
+        }
+    }
+
+    let same_task = |a: &Value, b: &Value| {
+        a.get("name") == b.get("name") && a.get("begin_time") == b.get("begin_time")
+    };
+    let entry_id = match (record.get("current_task"), record.get("task_history")) {
+        (Some(current), Some(Value::Array(history))) if !has_id(current) => history
+            .iter()
+            .rev()
+            .find(|t| same_task(t, current))
+            .and_then(|t| t.get("id"))
+            .cloned(),
+        _ => None,
+    };
+    if let Some(id) = entry_id {
+        if let Some(current) = record
+            .get_mut("current_task")
+            .and_then(Value::as_object_mut)
+        {
+            current.insert("id".to_string(), id);
+        }
+    }
+    record
+}
+
+/// 2: tasks store their breaks as `total_break_seconds`, not `break_seconds`.
+fn rename_break_seconds(mut record: Value) -> Value {
+    let rename = |task: &mut Value| {
+        let Some(fields) = task.as_object_mut() else {
+            return;
+        };
+        if let Some(seconds) = fields.remove("break_seconds") {
+            fields.entry("total_break_seconds").or_insert(seconds);
+        }
+    };
+    if let Some(history) = record.get_mut("task_history").and_then(Value::as_array_mut) {
+        history.iter_mut().for_each(rename);
+    }
+    if let Some(current) = record.get_mut("current_task") {
+        rename(current);
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use libs::record::{Seconds, UserRecord};
+    use serde_json::json;
+
+    use super::*;
+
+    /// A record as stored before schema versions, task ids and
+    /// `total_break_seconds` existed.
+    fn v0_record() -> Value {
+        json!({
+            "id": 1,
+            "user_name": "alice",
+            "task_history": [
+                {
+                    "name": "review",
+                    "state": "End",
+                    "begin_time": "2024-01-31T09:00:00",
+                    "end_time": "2024-01-31T10:00:00",
+                    "duration": 3000,
+                    "break_seconds": 600
+                },
+                {
+                    "name": "writing",
+                    "state": "Back",
+                    "begin_time": "2024-01-31T10:30:00",
+                    "end_time": "2024-01-31T10:45:00",
+                    "duration": 900,
+                    "break_seconds": 300
+                }
+            ],
+            "current_task": {
+                "name": "writing",
+                "state": "Back",
+                "begin_time": "2024-01-31T10:30:00",
+                "end_time": "2024-01-31T10:45:00",
+                "duration": 900,
+                "break_seconds": 300
+            },
+            "daily_goal_seconds": 21600
+        })
+    }
+
+    #[test]
+    fn test_should_chain_migrations_from_v0() {
+        let (migrated, changed) = migrate("user:alice:0001", v0_record());
+        assert!(changed);
+        assert_eq!(schema_version(&migrated), SCHEMA_VERSION);
+
+        let record: UserRecord = serde_json::from_value(migrated.clone()).unwrap();
+        let ids: Vec<Option<i32>> = record.task_history.iter().map(|t| t.id).collect();
+        assert_eq!(ids, [Some(0), Some(1)]);
+        assert_eq!(record.current_task.id, Some(1));
+        assert_eq!(record.task_history[0].total_break_seconds, Seconds(600));
+        assert_eq!(record.current_task.total_break_seconds, Seconds(300));
+        assert!(migrated["current_task"].get("break_seconds").is_none());
+        assert_eq!(record.daily_goal_seconds, Some(21600));
+
+        // A current record is left alone.
+        let (again, changed) = migrate("user:alice:0001", migrated.clone());
+        assert!(!changed);
+        assert_eq!(again, migrated);
+    }
+
+    #[test]
+    fn test_should_only_run_missing_steps() {
+        fn first(mut record: Value) -> Value {
+            record["steps"].as_array_mut().unwrap().push(json!("first"));
+            record
+        }
+        fn second(mut record: Value) -> Value {
+            record["steps"]
+                .as_array_mut()
+                .unwrap()
+                .push(json!("second"));
+            record
+        }
+        let steps: [Migration; 2] = [first, second];
+
+        let (from_v0, _) = migrate_with(&steps, "key", json!({ "steps": [] }));
+        assert_eq!(
+            from_v0,
+            json!({ "steps": ["first", "second"], "schema_version": 2 })
+        );
+
+        let (from_v1, changed) =
+            migrate_with(&steps, "key", json!({ "steps": [], "schema_version": 1 }));
+        assert!(changed);
+        assert_eq!(from_v1, json!({ "steps": ["second"], "schema_version": 2 }));
+    }
+
+    #[test]
+    fn test_should_keep_existing_ids() {
+        let record = json!({
+            "task_history": [{ "id": 4, "name": "a" }, { "name": "b" }],
+            "current_task": { "id": 9, "name": "b" },
+        });
+        let migrated = add_task_ids(record);
+        assert_eq!(migrated["task_history"][0]["id"], 4);
+        assert_eq!(migrated["task_history"][1]["id"], 5);
+        assert_eq!(migrated["current_task"]["id"], 9);
+    }
+}
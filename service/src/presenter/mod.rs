@@ -1,15 +1,25 @@
 use axum::{extract::rejection::JsonRejection, http::StatusCode, response::IntoResponse, Json};
 use bb8_redis::redis;
 use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
 
 use imon_derive::TryFromPayload;
 use libs::payload::{
-    GetSingleRecordPayload, RegisterRecordPayload, ResetRecordPayload, StoreSTaskPayload,
-    StoreTaskPayload, UpdateTaskPayload,
+    GetAllRecordPayload, GetSingleRecordPayload, RegisterRecordPayload, ResetRecordPayload,
+    StoreSTaskPayload, StoreTaskPayload, UpdateSTaskPayload, UpdateTaskPayload,
 };
 
+pub mod audit;
+pub mod cache;
+pub mod etag;
 pub mod handlers;
+pub mod import;
+pub mod key;
 pub mod logic;
+pub mod migrations;
+pub mod namespace;
+pub mod span;
+pub mod validate;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum RpcPayloadType {
@@ -19,28 +29,42 @@ pub enum RpcPayloadType {
     Sudo,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Event names accepted by `/v1/rpc/user`, keep in sync with `UserRpcEventPayload`.
+#[derive(Serialize, Deserialize, Debug, EnumIter, Display)]
+#[strum(serialize_all = "snake_case")]
 pub enum UserRpcEventType {
     #[serde(rename = "register")]
+    #[strum(serialize = "register")]
     RegisterRecord,
     #[serde(rename = "add_task")]
     AddTask,
+    #[serde(rename = "update_task")]
+    UpdateTask,
     #[serde(rename = "reset_record")]
     ResetRecord,
     #[serde(rename = "get_single_record")]
     GetSingleRecord,
+    #[serde(rename = "get_all_record")]
+    GetAllRecord,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Event names accepted by `/v1/rpc/sudo`, keep in sync with `SudoUserRpcEventPayload`.
+#[derive(Serialize, Deserialize, Debug, EnumIter, Display)]
+#[strum(serialize_all = "snake_case")]
 pub enum SudoUserRpcEventType {
     #[serde(rename = "register")]
+    #[strum(serialize = "register")]
     RegisterRecord,
     #[serde(rename = "add_task")]
     AddTask,
+    #[serde(rename = "update_task")]
+    UpdateTask,
     #[serde(rename = "reset_record")]
     ResetRecord,
     #[serde(rename = "get_single_record")]
     GetSingleRecord,
+    #[serde(rename = "get_all_record")]
+    GetAllRecord,
 }
 
 #[derive(Serialize, Deserialize, Debug, TryFromPayload)]
@@ -57,7 +81,7 @@ pub enum UserRpcEventPayload {
     #[serde(rename = "get_single_record")]
     GetSingleRecord(GetSingleRecordPayload),
     #[serde(rename = "get_all_record")]
-    GetAllRecord,
+    GetAllRecord(GetAllRecordPayload),
 }
 
 #[derive(Serialize, Deserialize, Debug, TryFromPayload)]
@@ -67,6 +91,8 @@ pub enum SudoUserRpcEventPayload {
     RegisterRecord(RegisterRecordPayload),
     #[serde(rename = "add_task")]
     AddTask(StoreSTaskPayload),
+    #[serde(rename = "update_task")]
+    UpdateTask(UpdateSTaskPayload),
     #[serde(rename = "reset_record")]
     ResetRecord(ResetRecordPayload),
     #[serde(rename = "get_single_record")]
@@ -100,8 +126,39 @@ pub enum RuntimeError {
     #[error("JSON error: {0}")]
     SerdeError(#[from] serde_json::Error),
 
+    #[error("Malformed record at {key} ({path}): {source}")]
+    MalformedRecord {
+        key: String,
+        path: String,
+        source: serde_json::Error,
+    },
+
     #[error("Invalid payload")]
     UnprocessableEntity { name: String },
+
+    #[error("Invalid key: {key}")]
+    InvalidKey { key: String },
+
+    #[error("Invalid CSV: {} row(s) rejected", errors.len())]
+    InvalidCsv { errors: Vec<import::CsvRowError> },
+
+    #[error("Record not found")]
+    RecordNotFound { name: String },
+
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    #[error("Conflict: {message}")]
+    Conflict {
+        message: String,
+        data: Option<serde_json::Value>,
+    },
+
+    #[error("Too many requests, retry after {retry_after_seconds}s")]
+    TooManyRequests { retry_after_seconds: u64 },
+
+    #[error("Precondition failed, the record is now {etag}")]
+    PreconditionFailed { etag: String },
 }
 
 impl IntoResponse for RuntimeError {
@@ -115,10 +172,54 @@ impl IntoResponse for RuntimeError {
                 let err_payload = construct_err_payload_de_upstream_data(err);
                 (StatusCode::INTERNAL_SERVER_ERROR, axum::Json(err_payload)).into_response()
             }
+            RuntimeError::MalformedRecord { key, path, source } => {
+                let err_payload = construct_err_payload_malformed_record(&key, &path, source);
+                (StatusCode::INTERNAL_SERVER_ERROR, axum::Json(err_payload)).into_response()
+            }
             RuntimeError::UnprocessableEntity { name } => {
                 let err_payload = construct_err_payload_unprocessable_entity(name);
                 (StatusCode::UNPROCESSABLE_ENTITY, axum::Json(err_payload)).into_response()
             }
+            RuntimeError::InvalidKey { key } => {
+                let err_payload = construct_err_payload_invalid_key(key);
+                (StatusCode::BAD_REQUEST, axum::Json(err_payload)).into_response()
+            }
+            RuntimeError::InvalidCsv { errors } => {
+                let err_payload = construct_err_payload_invalid_csv(errors);
+                (StatusCode::UNPROCESSABLE_ENTITY, axum::Json(err_payload)).into_response()
+            }
+            RuntimeError::RecordNotFound { name } => {
+                let err_payload = construct_err_payload_record_not_found(name);
+                (StatusCode::NOT_FOUND, axum::Json(err_payload)).into_response()
+            }
+            RuntimeError::Unauthorized => {
+                let err_payload = construct_err_payload_unauthorized();
+                (StatusCode::UNAUTHORIZED, axum::Json(err_payload)).into_response()
+            }
+            RuntimeError::Conflict { message, data } => {
+                let err_payload = construct_err_payload_conflict(message, data);
+                (StatusCode::CONFLICT, axum::Json(err_payload)).into_response()
+            }
+            RuntimeError::TooManyRequests {
+                retry_after_seconds,
+            } => {
+                let err_payload = construct_err_payload_too_many_requests(retry_after_seconds);
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(axum::http::header::RETRY_AFTER, retry_after_seconds)],
+                    axum::Json(err_payload),
+                )
+                    .into_response()
+            }
+            RuntimeError::PreconditionFailed { etag } => {
+                let err_payload = construct_err_payload_precondition_failed();
+                (
+                    StatusCode::PRECONDITION_FAILED,
+                    [(axum::http::header::ETAG, etag)],
+                    axum::Json(err_payload),
+                )
+                    .into_response()
+            }
         }
     }
 }
@@ -131,6 +232,63 @@ fn construct_err_payload_unprocessable_entity(name: String) -> serde_json::Value
     })
 }
 
+fn construct_err_payload_invalid_key(key: String) -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": format!("Invalid key, expected `{}`", key::KEY_FORMAT),
+        "key": key,
+    })
+}
+
+fn construct_err_payload_invalid_csv(errors: Vec<import::CsvRowError>) -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": "Invalid CSV, nothing was imported",
+        "errors": errors,
+    })
+}
+
+fn construct_err_payload_record_not_found(name: String) -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": "Record not found",
+        "field": name,
+    })
+}
+
+fn construct_err_payload_conflict(
+    message: String,
+    data: Option<serde_json::Value>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": message,
+        "data": data,
+    })
+}
+
+fn construct_err_payload_too_many_requests(retry_after_seconds: u64) -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": "Too many tasks written, slow down",
+        "retry_after_seconds": retry_after_seconds,
+    })
+}
+
+fn construct_err_payload_precondition_failed() -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": "The record changed since it was read, read it again",
+    })
+}
+
+fn construct_err_payload_unauthorized() -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": "Unauthorized",
+    })
+}
+
 fn construct_err_payload_redis(err: redis::RedisError) -> serde_json::Value {
     match err.kind() {
         redis::ErrorKind::ResponseError => serde_json::json!({
@@ -157,10 +315,90 @@ fn construct_err_payload_de_upstream_data(err: serde_json::Error) -> serde_json:
     })
 }
 
+fn construct_err_payload_malformed_record(
+    key: &str,
+    path: &str,
+    err: serde_json::Error,
+) -> serde_json::Value {
+    tracing::error!(
+        "upstream data malformed: record {:?} at path {:?}",
+        key,
+        path
+    );
+    tracing::debug!("upstream de err: {:?}", err);
+    serde_json::json!({
+        "status": "error",
+        "message": "Internal Error - Please report an issue if you encounter this."
+    })
+}
+
+/// The serde error behind a rejected JSON body, with the path it was raised at.
+fn json_body_error(err: &JsonRejection) -> Option<&serde_path_to_error::Error<serde_json::Error>> {
+    let mut source = std::error::Error::source(err);
+    while let Some(e) = source {
+        if let Some(found) = e.downcast_ref() {
+            return Some(found);
+        }
+        source = e.source();
+    }
+    None
+}
+
+/// Whether `err` was raised at the object keys `keys`, outermost first.
+fn is_raised_at(err: &serde_path_to_error::Error<serde_json::Error>, keys: &[&str]) -> bool {
+    let mut segments = err.path().iter();
+    keys.iter().all(|expected| {
+        matches!(segments.next(), Some(serde_path_to_error::Segment::Map { key }) if key == expected)
+    }) && segments.next().is_none()
+}
+
+/// Whether `err` was raised at a task's `begin_time` or `end_time`.
+fn is_raised_at_timestamp(err: &serde_path_to_error::Error<serde_json::Error>) -> bool {
+    matches!(
+        err.path().iter().next_back(),
+        Some(serde_path_to_error::Segment::Map { key }) if key == "begin_time" || key == "end_time"
+    )
+}
+
+/// `event_types` are the values `event_type` may take in the expected body,
+/// empty for bodies without one.
 fn construct_err_resp_invalid_incoming_json(
     err: &JsonRejection,
+    event_types: &[String],
 ) -> (StatusCode, axum::Json<serde_json::Value>) {
+    let body_error = json_body_error(err);
     match err {
+        // An unknown tag otherwise reads as malformed JSON; name the events
+        // this endpoint does accept instead.
+        JsonRejection::JsonDataError(e)
+            if !event_types.is_empty()
+                && body_error
+                    .is_some_and(|found| is_raised_at(found, &["payload", "event_type"])) =>
+        {
+            tracing::error!("unknown event type: {:?}", e);
+            let p = serde_json::json!({
+                "status": "error",
+                "message": format!(
+                    "Unknown event_type, expected one of: {}",
+                    event_types.join(", ")
+                ),
+                "field": "payload.event_type",
+                "event_types": event_types,
+            });
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(p))
+        }
+        // A refused task timestamp is about a single field, like other 422s.
+        JsonRejection::JsonDataError(e) if body_error.is_some_and(is_raised_at_timestamp) => {
+            tracing::error!("rejected timestamp: {:?}", e);
+            let path = body_error.map_or(String::new(), |found| found.path().to_string());
+            let p = serde_json::json!({
+                "status": "error",
+                "message": "Unprocessable entity",
+                "field": format!("payload.{}", path),
+                "error": format!("{:?}", e.body_text()),
+            });
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(p))
+        }
         case @ JsonRejection::JsonDataError(_) => {
             tracing::error!("rejected json: {:?}", case);
             let p = serde_json::json!({
@@ -170,6 +408,20 @@ fn construct_err_resp_invalid_incoming_json(
             });
             (StatusCode::BAD_REQUEST, Json(p))
         }
+        // An empty body ends before its very first position.
+        JsonRejection::JsonSyntaxError(e)
+            if body_error.is_some_and(|found| {
+                found.inner().classify() == serde_json::error::Category::Eof
+                    && found.inner().column() == 0
+            }) =>
+        {
+            tracing::error!("empty body: {:?}", e);
+            let p = serde_json::json!({
+                "status": "error",
+                "message": "Request body is empty",
+            });
+            (StatusCode::BAD_REQUEST, Json(p))
+        }
         JsonRejection::JsonSyntaxError(e) => {
             tracing::error!("invalid json: {:?}", e);
             let p = serde_json::json!({
@@ -178,6 +430,22 @@ fn construct_err_resp_invalid_incoming_json(
             });
             (StatusCode::BAD_REQUEST, Json(p))
         }
+        JsonRejection::MissingJsonContentType(e) => {
+            tracing::error!("missing content type: {:?}", e);
+            let p = serde_json::json!({
+                "status": "error",
+                "message": "Expected a JSON body, set `Content-Type: application/json`",
+            });
+            (StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(p))
+        }
+        JsonRejection::BytesRejection(e) => {
+            tracing::error!("unreadable body: {:?}", e);
+            let p = serde_json::json!({
+                "status": "error",
+                "message": "Failed to read request body",
+            });
+            (e.status(), Json(p))
+        }
         _ => {
             let p = serde_json::json!({
                 "status": "error",
@@ -187,3 +455,94 @@ fn construct_err_resp_invalid_incoming_json(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::FromRequest, http::header::CONTENT_TYPE};
+
+    use super::*;
+
+    async fn reject(content_type: Option<&str>, body: &'static str) -> (StatusCode, String) {
+        let mut req = axum::http::Request::builder().method("POST").uri("/");
+        if let Some(content_type) = content_type {
+            req = req.header(CONTENT_TYPE, content_type);
+        }
+        let req = req.body(Body::from(body)).unwrap();
+
+        let rejection = Json::<serde_json::Value>::from_request(req, &())
+            .await
+            .unwrap_err();
+        let (status, Json(body)) = construct_err_resp_invalid_incoming_json(&rejection, &[]);
+        (status, body["message"].as_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn test_rpc_event_names_match_wire_format() {
+        use strum::IntoEnumIterator;
+
+        for event in UserRpcEventType::iter() {
+            assert_eq!(
+                serde_json::json!(event.to_string()),
+                serde_json::json!(event)
+            );
+        }
+        for event in SudoUserRpcEventType::iter() {
+            assert_eq!(
+                serde_json::json!(event.to_string()),
+                serde_json::json!(event)
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_all_record_filter_is_optional() {
+        let payload: UserRpcEventPayload =
+            serde_json::from_str(r#"{"event_type": "get_all_record"}"#).unwrap();
+        assert!(matches!(
+            payload,
+            UserRpcEventPayload::GetAllRecord(GetAllRecordPayload {
+                filter: libs::payload::RecordFilter::All
+            })
+        ));
+
+        let payload: UserRpcEventPayload =
+            serde_json::from_str(r#"{"event_type": "get_all_record", "filter": "active_only"}"#)
+                .unwrap();
+        assert!(matches!(
+            payload,
+            UserRpcEventPayload::GetAllRecord(GetAllRecordPayload {
+                filter: libs::payload::RecordFilter::ActiveOnly
+            })
+        ));
+    }
+
+    #[test]
+    fn test_too_many_requests_sets_retry_after() {
+        let response = RuntimeError::TooManyRequests {
+            retry_after_seconds: 15,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[axum::http::header::RETRY_AFTER], "15");
+    }
+
+    #[tokio::test]
+    async fn test_should_explain_wrong_content_type() {
+        let (status, message) = reject(Some("text/plain"), r#"{"key": "x"}"#).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(message.contains("Content-Type: application/json"));
+
+        let (status, _) = reject(None, r#"{"key": "x"}"#).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_should_explain_empty_body() {
+        let (status, message) = reject(Some("application/json"), "").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "Request body is empty");
+
+        let (_, message) = reject(Some("application/json"), "{").await;
+        assert_eq!(message, "Invalid JSON");
+    }
+}
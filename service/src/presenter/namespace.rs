@@ -0,0 +1,48 @@
+/// Prefix prepended to every Redis key the service touches, so several
+/// environments (dev/staging/prod) can share one Redis instance.
+///
+/// Keys handed to clients never carry the prefix.
+#[derive(Clone, Debug, Default)]
+pub struct KeyPrefix(String);
+
+impl KeyPrefix {
+    pub fn new(prefix: &str) -> Self {
+        if prefix.is_empty() {
+            KeyPrefix::default()
+        } else {
+            KeyPrefix(format!("{}:", prefix))
+        }
+    }
+
+    /// Read the prefix from `IMON_KEY_PREFIX`, defaulting to no prefix.
+    pub fn from_env() -> Self {
+        KeyPrefix::new(&std::env::var("IMON_KEY_PREFIX").unwrap_or_default())
+    }
+
+    pub fn apply(&self, key: &str) -> String {
+        format!("{}{}", self.0, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_keep_keys_unchanged_without_prefix() {
+        assert_eq!(
+            KeyPrefix::new("").apply("user:alice:0001"),
+            "user:alice:0001"
+        );
+    }
+
+    #[test]
+    fn test_should_separate_records_of_different_prefixes() {
+        let dev = KeyPrefix::new("dev");
+        let prod = KeyPrefix::new("prod");
+
+        assert_eq!(dev.apply("user:alice:0001"), "dev:user:alice:0001");
+        assert_ne!(dev.apply("user:alice:0001"), prod.apply("user:alice:0001"));
+        assert_ne!(dev.apply("operating_info"), prod.apply("operating_info"));
+    }
+}
@@ -0,0 +1,227 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::{body::Body, http::Request};
+use libs::payload::{
+    DeleteRecordPayload, GetByIdPayload, GetSingleRecordPayload, GetTaskPayload,
+    GroupedStatsPayload, RegisterRecordPayload, RegisterRecordsBulkPayload, RenameTasksPayload,
+    ResetRecordPayload, SetGoalPayload, SetRatePayload, SplitTaskPayload, StoreTaskPayload,
+    UpdateSettingsPayload, UpdateTaskPayload,
+};
+use tracing::{field::Empty, Span};
+
+use super::{SudoUserRpcEventPayload, SudoUserRpcRequest, UserRpcEventPayload, UserRpcRequest};
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Root span of a request, everything logged while handling it is nested here.
+///
+/// `key` and `user_name` start out empty and are filled in by `ValidatedJson`
+/// once the payload is parsed, see `record_identity`. Handlers going through
+/// many records fill in `records`, see `record_count`.
+pub fn make_request_span(request: &Request<Body>) -> Span {
+    tracing::info_span!(
+        "request",
+        id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed),
+        method = %request.method(),
+        uri = %request.uri(),
+        key = Empty,
+        user_name = Empty,
+        records = Empty,
+    )
+}
+
+/// Note how many records the current request went through, so a slow
+/// response can be told apart from a big one.
+pub fn record_count(records: usize) {
+    Span::current().record("records", records);
+}
+
+/// Who a payload is about, as far as it tells.
+pub trait RequestIdentity {
+    fn key(&self) -> Option<&str> {
+        None
+    }
+
+    fn user_name(&self) -> Option<&str> {
+        None
+    }
+}
+
+pub fn record_identity(span: &Span, payload: &impl RequestIdentity) {
+    if let Some(key) = payload.key() {
+        span.record("key", key);
+    }
+    if let Some(user_name) = payload.user_name() {
+        span.record("user_name", user_name);
+    }
+}
+
+macro_rules! impl_keyed_identity {
+    ($($payload:ty),* $(,)?) => {
+        $(
+            impl RequestIdentity for $payload {
+                fn key(&self) -> Option<&str> {
+                    Some(&self.key)
+                }
+            }
+        )*
+    };
+}
+
+impl_keyed_identity!(
+    StoreTaskPayload,
+    ResetRecordPayload,
+    DeleteRecordPayload,
+    SetGoalPayload,
+    SetRatePayload,
+    UpdateSettingsPayload,
+    GetSingleRecordPayload,
+    UpdateTaskPayload,
+    GetTaskPayload,
+    SplitTaskPayload,
+    RenameTasksPayload,
+    GroupedStatsPayload,
+);
+
+impl RequestIdentity for RegisterRecordPayload {
+    fn user_name(&self) -> Option<&str> {
+        Some(&self.user_name)
+    }
+}
+
+impl RequestIdentity for RegisterRecordsBulkPayload {}
+
+impl RequestIdentity for GetByIdPayload {}
+
+impl RequestIdentity for UserRpcRequest {
+    fn key(&self) -> Option<&str> {
+        match &self.payload {
+            UserRpcEventPayload::AddTask(payload) => payload.key(),
+            UserRpcEventPayload::UpdateTask(payload) => payload.key(),
+            UserRpcEventPayload::ResetRecord(payload) => payload.key(),
+            UserRpcEventPayload::GetSingleRecord(payload) => payload.key(),
+            UserRpcEventPayload::RegisterRecord(_) | UserRpcEventPayload::GetAllRecord(_) => None,
+        }
+    }
+
+    fn user_name(&self) -> Option<&str> {
+        match &self.payload {
+            UserRpcEventPayload::RegisterRecord(payload) => payload.user_name(),
+            _ => None,
+        }
+    }
+}
+
+impl RequestIdentity for SudoUserRpcRequest {
+    fn key(&self) -> Option<&str> {
+        match &self.payload {
+            SudoUserRpcEventPayload::AddTask(payload) => Some(&payload.key),
+            SudoUserRpcEventPayload::UpdateTask(payload) => Some(&payload.key),
+            SudoUserRpcEventPayload::ResetRecord(payload) => payload.key(),
+            SudoUserRpcEventPayload::GetSingleRecord(payload) => payload.key(),
+            SudoUserRpcEventPayload::RegisterRecord(_) | SudoUserRpcEventPayload::GetAllRecord => {
+                None
+            }
+        }
+    }
+
+    fn user_name(&self) -> Option<&str> {
+        match &self.payload {
+            SudoUserRpcEventPayload::RegisterRecord(payload) => payload.user_name(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{extract::FromRequest, http::header};
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+    use super::*;
+    use crate::presenter::handlers::ValidatedJson;
+
+    /// Collects the fields of every span by name.
+    #[derive(Clone, Default)]
+    struct SpanFields(Arc<Mutex<HashMap<String, String>>>);
+
+    impl Visit for SpanFields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for SpanFields {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            attrs.record(&mut self.clone());
+        }
+
+        fn on_record(&self, _id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    fn json_request(body: &'static str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/task/new")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_should_record_key_on_request_span() {
+        let fields = SpanFields::default();
+        let _guard = tracing_subscriber::registry()
+            .with(fields.clone())
+            .set_default();
+
+        let request = json_request(
+            r#"{"key":"user:alice:0001","task":{"name":"write","state":"Begin","begin_time":"2024-01-01T09:00:00","end_time":"2024-01-01T09:00:00","duration":0}}"#,
+        );
+        let span = make_request_span(&request);
+        let _entered = span.enter();
+        ValidatedJson::<StoreTaskPayload>::from_request(request, &())
+            .await
+            .unwrap();
+
+        let fields = fields.0.lock().unwrap();
+        assert!(fields.contains_key("id"));
+        assert_eq!(fields["method"], "POST");
+        assert_eq!(fields["uri"], "/v1/task/new");
+        assert_eq!(fields["key"], "user:alice:0001");
+        assert!(!fields.contains_key("user_name"));
+    }
+
+    #[test]
+    fn test_should_take_user_name_from_rpc_register() {
+        let request: UserRpcRequest = serde_json::from_value(serde_json::json!({
+            "metadata": { "of": "user" },
+            "payload": { "event_type": "register", "user_name": "alice" },
+        }))
+        .unwrap();
+
+        assert_eq!(request.key(), None);
+        assert_eq!(request.user_name(), Some("alice"));
+    }
+}
@@ -0,0 +1,143 @@
+use libs::{
+    payload::{
+        DeleteRecordPayload, GetByIdPayload, GetSingleRecordPayload, GetTaskPayload,
+        GroupedStatsPayload, RegisterRecordPayload, RegisterRecordsBulkPayload, RenameTasksPayload,
+        ResetRecordPayload, SetGoalPayload, SetRatePayload, SplitTaskPayload, StoreTaskPayload,
+        UpdateSettingsPayload, UpdateTaskPayload,
+    },
+    record::Task,
+};
+
+use strum::IntoEnumIterator;
+
+use super::{
+    RuntimeError, SudoUserRpcEventType, SudoUserRpcRequest, UserRpcEventPayload, UserRpcEventType,
+    UserRpcRequest,
+};
+
+/// Checks on an incoming payload that its shape can't express, run by
+/// `ValidatedJson` right after deserializing it.
+pub trait Validate {
+    fn validate(&self) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    /// Values the body's `event_type` may take, for the RPC requests that
+    /// carry one. Listed back to clients that send an unknown one.
+    fn event_types() -> Vec<String>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+}
+
+/// `field` is where `task` sits in the payload, e.g. `payload.task`.
+fn validate_task(task: &Task, field: &str) -> Result<(), RuntimeError> {
+    if task.end_time < task.begin_time {
+        return Err(RuntimeError::UnprocessableEntity {
+            name: format!("{}.end_time", field),
+        });
+    }
+    Ok(())
+}
+
+impl Validate for StoreTaskPayload {
+    fn validate(&self) -> Result<(), RuntimeError> {
+        validate_task(&self.task, "payload.task")
+    }
+}
+
+/// An absolute `http` or `https` URL with a host.
+fn is_webhook_url(url: &str) -> bool {
+    let Some(rest) = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    else {
+        return false;
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    !host.is_empty() && !url.chars().any(char::is_whitespace)
+}
+
+impl Validate for UpdateSettingsPayload {
+    fn validate(&self) -> Result<(), RuntimeError> {
+        let settings = &self.settings;
+        let invalid = |field: &str| {
+            Err(RuntimeError::UnprocessableEntity {
+                name: format!("payload.settings{}", field),
+            })
+        };
+        if settings.is_empty() {
+            return invalid("");
+        }
+        if let Some(Some(url)) = &settings.webhook_url {
+            if !is_webhook_url(url) {
+                return invalid(".webhook_url");
+            }
+        }
+        if settings
+            .daily_goal_seconds
+            .flatten()
+            .is_some_and(|goal| goal <= 0)
+        {
+            return invalid(".daily_goal_seconds");
+        }
+        if let Some(Some(rate)) = &settings.hourly_rate {
+            if rate.cents <= 0 || rate.currency.trim().is_empty() {
+                return invalid(".hourly_rate");
+            }
+        }
+        if let Some(Some(timezone)) = &settings.timezone {
+            if timezone.parse::<chrono_tz::Tz>().is_err() {
+                return invalid(".timezone");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for UserRpcRequest {
+    fn validate(&self) -> Result<(), RuntimeError> {
+        match &self.payload {
+            UserRpcEventPayload::AddTask(payload) => {
+                validate_task(&payload.task, "payload.payload.task")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn event_types() -> Vec<String> {
+        UserRpcEventType::iter().map(|e| e.to_string()).collect()
+    }
+}
+
+impl Validate for SudoUserRpcRequest {
+    fn event_types() -> Vec<String> {
+        SudoUserRpcEventType::iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+}
+
+macro_rules! impl_no_validation {
+    ($($payload:ty),* $(,)?) => {
+        $(impl Validate for $payload {})*
+    };
+}
+
+impl_no_validation!(
+    RegisterRecordPayload,
+    RegisterRecordsBulkPayload,
+    ResetRecordPayload,
+    DeleteRecordPayload,
+    GetSingleRecordPayload,
+    GetByIdPayload,
+    SetGoalPayload,
+    SetRatePayload,
+    UpdateTaskPayload,
+    GetTaskPayload,
+    SplitTaskPayload,
+    RenameTasksPayload,
+    GroupedStatsPayload,
+);